@@ -13,18 +13,19 @@ fn all_benchmark(c: &mut Criterion) {
         fs_extra::generate_random_folder_with_files(3255, 322);
 
     c.bench_function("file_hashing::get_hash_file", |b| {
+        b.iter(|| file_hashing::get_hash_file(&path_one_file, &hash))
+    });
+
+    c.bench_function("file_hashing::io::hash_update 64 MiB", |b| {
         b.iter(|| {
-            file_hashing::get_hash_file(
-                &path_one_file.to_path_buf(),
-                &mut hash,
-            )
+            let reader = fs_extra::RandomReader::new(64 * 1024 * 1024, 0);
+            file_hashing::io::hash_update(reader, &mut hash).unwrap()
         })
     });
 
     c.bench_function("file_hashing::get_hash_files", |b| {
         b.iter(|| {
-            let walkdir =
-                walkdir::WalkDir::new(temp_dir_many_files.to_path_buf());
+            let walkdir = walkdir::WalkDir::new(&temp_dir_many_files);
             let mut paths: Vec<PathBuf> = Vec::new();
 
             for file in walkdir.into_iter().filter_map(|file| file.ok()) {
@@ -33,16 +34,15 @@ fn all_benchmark(c: &mut Criterion) {
                 }
             }
 
-            file_hashing::get_hash_files(&paths, &mut hash, 12, |_| {})
-                .unwrap();
+            file_hashing::get_hash_files(&paths, &hash, 12, |_| {}).unwrap();
         })
     });
 
     c.bench_function("file_hashing::get_hash_folder", |b| {
         b.iter(|| {
             file_hashing::get_hash_folder(
-                &temp_dir_many_files.to_path_buf(),
-                &mut hash,
+                &temp_dir_many_files,
+                &hash,
                 12,
                 |_| {},
             )
@@ -57,7 +57,7 @@ fn all_benchmark(c: &mut Criterion) {
                     temp_dir_many_files.to_path_buf(),
                     temp_dir_many_files2.to_path_buf(),
                 ],
-                &mut hash,
+                &hash,
                 12,
                 |_| {},
             )
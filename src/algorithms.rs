@@ -0,0 +1,175 @@
+//! One-liners for common algorithms
+//!
+//! Every algorithm is behind the feature of the same name (`sha2`, `blake2`, `blake3`),
+//! so you don't have to pick and wire a digest crate yourself.
+//! MD5 and SHA-1 are behind `insecure-legacy`, only for interop with existing manifests and APIs
+//!
+//! # Example
+//!
+//! ```no_run
+//! // features = ["sha2"]
+//! let result = file_hashing::get_sha256_file("/home/gladi/test-hashing.txt").unwrap();
+//!
+//! assert_eq!(result.len(), 64); // Sha256 len == 64
+//! ```
+
+use super::*;
+
+macro_rules! algorithm {
+    (
+        $(#[$doc:meta])*
+        $feature:literal, $name:literal, $hash:ty, $file:ident, $folder:ident
+    ) => {
+        #[doc = concat!("Get **", $name, "** hash from **file**, see [get_hash_file]")]
+        $(#[$doc])*
+        #[cfg(feature = $feature)]
+        pub fn $file<P: AsRef<Path>>(path: P) -> Result<String, IOError> {
+            let hash = <$hash>::default();
+            get_hash_file(path, &hash)
+        }
+
+        #[doc = concat!("Get **", $name, "** hash from **folder**, see [get_hash_folder]")]
+        $(#[$doc])*
+        #[cfg(feature = $feature)]
+        pub fn $folder<P>(
+            dir: P,
+            num_threads: usize,
+            progress: impl Fn(ProgressInfo),
+        ) -> Result<String, IOError>
+        where
+            P: AsRef<Path> + std::marker::Sync,
+        {
+            let hash = <$hash>::default();
+            get_hash_folder(dir, &hash, num_threads, progress)
+        }
+    };
+}
+
+algorithm!(
+    "sha2",
+    "SHA-256",
+    sha2::Sha256,
+    get_sha256_file,
+    get_sha256_folder
+);
+algorithm!(
+    "sha2",
+    "SHA-512",
+    sha2::Sha512,
+    get_sha512_file,
+    get_sha512_folder
+);
+algorithm!(
+    "blake2",
+    "BLAKE2s-256",
+    blake2::Blake2s256,
+    get_blake2s_file,
+    get_blake2s_folder
+);
+algorithm!(
+    "blake2",
+    "BLAKE2b-512",
+    blake2::Blake2b512,
+    get_blake2b_file,
+    get_blake2b_folder
+);
+algorithm!(
+    "blake3",
+    "BLAKE3",
+    blake3::Hasher,
+    get_blake3_file,
+    get_blake3_folder
+);
+algorithm!(
+    ///
+    /// # Warning
+    ///
+    /// MD5 is **broken**: collisions can be made in seconds.
+    /// Use it only to check against existing MD5 sums, never to detect tampering
+    "insecure-legacy",
+    "MD5",
+    md5::Md5,
+    get_md5_file,
+    get_md5_folder
+);
+algorithm!(
+    ///
+    /// # Warning
+    ///
+    /// SHA-1 is **broken**: chosen-prefix collisions are practical.
+    /// Use it only to check against existing SHA-1 sums, never to detect tampering
+    "insecure-legacy",
+    "SHA-1",
+    sha1::Sha1,
+    get_sha1_file,
+    get_sha1_folder
+);
+
+#[cfg(test)]
+mod tests {
+    fn hello_world() -> (assert_fs::TempDir, std::path::PathBuf) {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, "hello world").unwrap();
+
+        (temp_dir, path)
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn get_sha256_file() {
+        let (_temp_dir, path) = hello_world();
+
+        assert_eq!(
+            super::get_sha256_file(&path).unwrap(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "blake2")]
+    fn get_blake2s_file() {
+        let (_temp_dir, path) = hello_world();
+
+        assert_eq!(
+            super::get_blake2s_file(&path).unwrap(),
+            "9aec6806794561107e594b1f6a8a6b0c92a0cba9acf5e5e93cca06f781813b0b"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "blake3")]
+    fn get_blake3_file() {
+        let (_temp_dir, path) = hello_world();
+
+        assert_eq!(
+            super::get_blake3_file(&path).unwrap(),
+            "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "blake3")]
+    fn get_blake3_folder() {
+        let (temp_dir, _paths) =
+            crate::fs::extra::generate_random_folder_with_files(5, 32);
+
+        let result = super::get_blake3_folder(&temp_dir, 2, |_| {}).unwrap();
+        assert_eq!(result.len(), 64); // BLAKE3 len == 64
+    }
+
+    #[test]
+    #[cfg(feature = "insecure-legacy")]
+    fn get_legacy_file() {
+        let (_temp_dir, path) = hello_world();
+
+        assert_eq!(
+            super::get_md5_file(&path).unwrap(),
+            "5eb63bbbe01eeed093cb22bb8f5acdc3"
+        );
+        assert_eq!(
+            super::get_sha1_file(&path).unwrap(),
+            "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Async IO adapters for **tokio**
+//!
+//! The async counterparts of [HashingReader](crate::io::HashingReader) and [HashingWriter](crate::io::HashingWriter)
+
+use super::*;
+use pin_project_lite::pin_project;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{mpsc, Semaphore};
+
+pin_project! {
+    /// [AsyncRead] adapter that hashes everything read through it
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::async_io::AsyncHashingReader;
+    ///
+    /// # async fn download() -> std::io::Result<()> {
+    /// let file = tokio::fs::File::open("/home/gladi/upload.tar").await?;
+    /// let mut reader = AsyncHashingReader::new(file, Blake2s256::new());
+    ///
+    /// tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    ///
+    /// let (_, result) = reader.finalize();
+    /// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub struct AsyncHashingReader<R, HashType> {
+        #[pin]
+        inner: R,
+        hash: HashType,
+        bytes: u64,
+    }
+}
+
+impl<R, HashType> AsyncHashingReader<R, HashType>
+where
+    HashType: DynDigest + Clone,
+{
+    pub fn new(inner: R, hash: HashType) -> Self {
+        Self {
+            inner,
+            hash,
+            bytes: 0,
+        }
+    }
+
+    /// Hash of the bytes read so far as **hex lower**
+    pub fn hash(&mut self) -> String {
+        crate::encoding::get_lowerhex(&mut self.hash)
+    }
+
+    /// Number of bytes read so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get the inner reader back together with the final hash
+    pub fn finalize(mut self) -> (R, String) {
+        let result = self.hash();
+        (self.inner, result)
+    }
+}
+
+impl<R, HashType> AsyncRead for AsyncHashingReader<R, HashType>
+where
+    R: AsyncRead,
+    HashType: DynDigest,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let before = buf.filled().len();
+
+        let result = this.inner.poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            let read = &buf.filled()[before..];
+            this.hash.update(read);
+            *this.bytes += read.len() as u64;
+        }
+
+        result
+    }
+}
+
+pin_project! {
+    /// [AsyncWrite] adapter that hashes everything written through it
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::async_io::AsyncHashingWriter;
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # async fn export() -> std::io::Result<()> {
+    /// let file = tokio::fs::File::create("/home/gladi/export.json").await?;
+    /// let mut writer = AsyncHashingWriter::new(file, Blake2s256::new());
+    ///
+    /// writer.write_all(b"{}").await?;
+    /// writer.flush().await?;
+    ///
+    /// let (_, result) = writer.finalize();
+    /// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub struct AsyncHashingWriter<W, HashType> {
+        #[pin]
+        inner: W,
+        hash: HashType,
+        bytes: u64,
+    }
+}
+
+impl<W, HashType> AsyncHashingWriter<W, HashType>
+where
+    HashType: DynDigest + Clone,
+{
+    pub fn new(inner: W, hash: HashType) -> Self {
+        Self {
+            inner,
+            hash,
+            bytes: 0,
+        }
+    }
+
+    /// Hash of the bytes written so far as **hex lower**
+    pub fn hash(&mut self) -> String {
+        crate::encoding::get_lowerhex(&mut self.hash)
+    }
+
+    /// Number of bytes written so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get the inner writer back together with the final hash
+    pub fn finalize(mut self) -> (W, String) {
+        let result = self.hash();
+        (self.inner, result)
+    }
+}
+
+impl<W, HashType> AsyncWrite for AsyncHashingWriter<W, HashType>
+where
+    W: AsyncWrite,
+    HashType: DynDigest,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.project();
+
+        let result = this.inner.poll_write(cx, buf);
+        if let Poll::Ready(Ok(i)) = result {
+            this.hash.update(&buf[0..i]);
+            *this.bytes += i as u64;
+        }
+
+        result
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Path of a file with its hash
+pub type FileResult = (PathBuf, Result<String, IOError>);
+
+/// Hash every file of **folder** on tokio tasks
+///
+/// At most **concurrency** files are hashed at the same time. Each file is hashed with a copy of `hash`
+/// and its result is sent to the returned channel as soon as it is ready, so the order is not stable.
+/// Errors of the walk (e.g. folders without permission) are sent first.
+/// Must be called within a tokio runtime
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::async_io::get_hash_folder_stream;
+///
+/// # async fn scan() -> std::io::Result<()> {
+/// let mut results =
+///     get_hash_folder_stream("/home/gladi/Pictures", Blake2s256::new(), 16)?;
+///
+/// while let Some((path, result)) = results.recv().await {
+///     println!("{}: {}", path.display(), result?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Error
+///
+/// * If the **concurrency** is 0, the **IOErrorKind::InvalidInput** error will be returned
+pub fn get_hash_folder_stream<HashType, P>(
+    dir: P,
+    hash: HashType,
+    concurrency: usize,
+) -> Result<mpsc::Receiver<FileResult>, IOError>
+where
+    HashType: DynDigest + Clone + Send + 'static,
+    P: AsRef<Path>,
+{
+    if concurrency == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let dir = dir.as_ref().to_path_buf();
+    let (sender, receiver) = mpsc::channel(concurrency);
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    tokio::spawn(async move {
+        let walk = tokio::task::spawn_blocking(move || {
+            fs::get_all_file_with_metadata(dir)
+        })
+        .await
+        .unwrap_or_default();
+
+        for (path, error) in walk.errors {
+            if sender.send((path, Err(error))).await.is_err() {
+                return;
+            }
+        }
+
+        for path in walk.files.into_iter().map(|entry| entry.path) {
+            let permit = match semaphore.clone().acquire_owned().await {
+                Ok(permit) => permit,
+                Err(_) => break,
+            };
+
+            let hash = hash.clone();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                let result = get_hash_file_async(&path, hash).await;
+                let _ = sender.send((path, result)).await;
+                drop(permit);
+            });
+        }
+    });
+
+    Ok(receiver)
+}
+
+async fn get_hash_file_async<HashType>(
+    path: &Path,
+    hash: HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    let file = tokio::fs::File::open(path).await?;
+    let mut reader = AsyncHashingReader::new(file, hash);
+
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(reader.finalize().1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_hash_folder_stream, AsyncHashingReader, AsyncHashingWriter,
+    };
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+
+    #[tokio::test]
+    async fn async_hashing_reader_and_writer() {
+        let (temp_dir, path) = extra::generate_random_file(10_000);
+        let dst = temp_dir.path().join("copy.txt");
+
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        let mut reader = AsyncHashingReader::new(file, Blake2s256::new());
+        let file = tokio::fs::File::create(&dst).await.unwrap();
+        let mut writer = AsyncHashingWriter::new(file, Blake2s256::new());
+
+        tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        assert_eq!(reader.bytes(), 10_000);
+        assert_eq!(writer.bytes(), 10_000);
+
+        let hash = Blake2s256::new();
+        let expected = crate::get_hash_file(&path, &hash).unwrap();
+        assert_eq!(reader.finalize().1, expected);
+        assert_eq!(writer.finalize().1, expected);
+    }
+
+    #[tokio::test]
+    async fn get_hash_folder_stream_bounded() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+
+        let mut results =
+            get_hash_folder_stream(&temp_dir, Blake2s256::new(), 4).unwrap();
+        let mut count = 0;
+
+        while let Some((path, result)) = results.recv().await {
+            let hash = Blake2s256::new();
+            let expected = crate::get_hash_file(&path, &hash).unwrap();
+
+            assert_eq!(result.unwrap(), expected);
+            count += 1;
+        }
+        assert_eq!(count, paths.len());
+
+        let error = get_hash_folder_stream(&temp_dir, Blake2s256::new(), 0)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,264 @@
+//! [BagIt](https://www.rfc-editor.org/rfc/rfc8493) bags
+//!
+//! A bag is a folder with the payload in `data/` and manifests of it next to it:
+//!
+//! ```text
+//! bag/
+//! ├── bagit.txt
+//! ├── manifest-sha256.txt
+//! ├── tagmanifest-sha256.txt
+//! └── data/
+//!     └── ...
+//! ```
+
+use super::*;
+use crate::manifest::Manifest;
+use crate::verify::DiffReport;
+use std::path::PathBuf;
+
+const BAGIT_TXT: &str =
+    "BagIt-Version: 1.0\nTag-File-Character-Encoding: UTF-8\n";
+
+/// Write `bagit.txt`, `manifest-<algorithm>.txt` and `tagmanifest-<algorithm>.txt` for the payload in `bag_dir/data`
+///
+/// **algorithm** is the BagIt name of `hash`, e.g. `sha256` or `sha512`
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2b512, Digest};
+/// use file_hashing::{bagit, HashOptions};
+///
+/// let hash = Blake2b512::new();
+/// bagit::create_bag("/home/gladi/bag", "blake2b512", &hash, &HashOptions::new(12), |_| {})
+///     .unwrap();
+/// ```
+///
+/// # Error
+///
+/// * If there is no `data` folder, the **IOErrorKind::NotFound** error will be returned
+/// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+pub fn create_bag<HashType, P>(
+    bag_dir: P,
+    algorithm: &str,
+    hash: &HashType,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<(), IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    let bag_dir = bag_dir.as_ref();
+    let payload_dir = bag_dir.join("data");
+
+    if !payload_dir.is_dir() {
+        return Err(IOError::from(IOErrorKind::NotFound));
+    }
+
+    // Payload paths are always relative to `data`
+    let options = &options.for_layout();
+    let payload = Manifest::create(&payload_dir, hash, options, progress)?;
+    let mut manifest = Manifest::new();
+    for (path, hash) in payload.entries {
+        manifest.insert(encode_path(&Path::new("data").join(path)), hash);
+    }
+
+    std::fs::write(bag_dir.join("bagit.txt"), BAGIT_TXT)?;
+    manifest.save(bag_dir.join(format!("manifest-{}.txt", algorithm)))?;
+
+    let mut tag_manifest = Manifest::new();
+    for entry in std::fs::read_dir(bag_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if entry.file_type()?.is_file() && !name.starts_with("tagmanifest-") {
+            let result = crate::get_hash_file(entry.path(), hash)?;
+            tag_manifest.insert(encode_path(Path::new(&name)), result);
+        }
+    }
+
+    tag_manifest.save(bag_dir.join(format!("tagmanifest-{}.txt", algorithm)))
+}
+
+/// Validate bag in `bag_dir`
+///
+/// Payload files are hashed in parallel. Tag files are checked against `tagmanifest-<algorithm>.txt`, if it exists.
+/// Paths in the report are relative to `bag_dir`, e.g. `data/photo.png`
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2b512, Digest};
+/// use file_hashing::{bagit, HashOptions};
+///
+/// let hash = Blake2b512::new();
+/// let report = bagit::validate_bag(
+///     "/home/gladi/bag",
+///     "blake2b512",
+///     &hash,
+///     &HashOptions::new(12),
+///     |_| {},
+/// )
+/// .unwrap();
+///
+/// if !report.is_empty() {
+///     println!("bag is invalid:\n{}", report);
+/// }
+/// ```
+///
+/// # Error
+///
+/// * If `bagit.txt` or `manifest-<algorithm>.txt` is missing, the **IOErrorKind::NotFound** error will be returned
+/// * If a manifest is malformed, the **IOErrorKind::InvalidData** error will be returned
+pub fn validate_bag<HashType, P>(
+    bag_dir: P,
+    algorithm: &str,
+    hash: &HashType,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<DiffReport, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    let bag_dir = bag_dir.as_ref();
+
+    if !bag_dir.join("bagit.txt").is_file() {
+        return Err(IOError::from(IOErrorKind::NotFound));
+    }
+
+    let manifest =
+        Manifest::load(bag_dir.join(format!("manifest-{}.txt", algorithm)))?;
+
+    let mut payload = Manifest::new();
+    for (path, hash) in manifest.entries {
+        let path = decode_path(&path);
+        let path = path.strip_prefix("data").map_err(|_| {
+            IOError::new(
+                IOErrorKind::InvalidData,
+                format!("{} is outside of the payload", path.display()),
+            )
+        })?;
+
+        payload.insert(path, hash);
+    }
+
+    let options = &options.for_layout();
+    let mut report =
+        payload.verify(bag_dir.join("data"), hash, options, progress)?;
+
+    for entry in report
+        .added
+        .iter_mut()
+        .chain(report.removed.iter_mut())
+        .chain(report.modified.iter_mut())
+    {
+        entry.path = Path::new("data").join(&entry.path);
+    }
+
+    let tag_manifest_path =
+        bag_dir.join(format!("tagmanifest-{}.txt", algorithm));
+
+    if tag_manifest_path.is_file() {
+        for (path, old) in Manifest::load(tag_manifest_path)?.entries {
+            let path = decode_path(&path);
+            match crate::get_hash_file(bag_dir.join(&path), hash) {
+                Ok(new) if !new.eq_ignore_ascii_case(&old) => {
+                    report.push_modified(path, old, new)
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == IOErrorKind::NotFound => {
+                    report.push_removed(path, old)
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    report.sort();
+    Ok(report)
+}
+
+/// Percent-encode `%`, `CR` and `LF` as BagIt requires
+fn encode_path(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    PathBuf::from(
+        path.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A"),
+    )
+}
+
+fn decode_path(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy();
+
+    PathBuf::from(
+        path.replace("%0A", "\n")
+            .replace("%0a", "\n")
+            .replace("%0D", "\r")
+            .replace("%0d", "\r")
+            .replace("%25", "%"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn create_and_validate_bag() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let bag_dir = assert_fs::TempDir::new().unwrap();
+        let data_dir = bag_dir.path().join("data");
+
+        std::fs::create_dir_all(data_dir.join("photos")).unwrap();
+        std::fs::write(data_dir.join("photos/cat.png"), "meow").unwrap();
+        std::fs::write(data_dir.join("100%.txt"), "percent").unwrap();
+
+        super::create_bag(&bag_dir, "blake2s256", &hash, &options, |_| {})
+            .unwrap();
+        assert!(bag_dir.path().join("manifest-blake2s256.txt").is_file());
+        assert!(bag_dir.path().join("tagmanifest-blake2s256.txt").is_file());
+
+        let report = super::validate_bag(
+            &bag_dir,
+            "blake2s256",
+            &hash,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        assert!(report.is_empty(), "{}", report);
+
+        std::fs::write(data_dir.join("photos/cat.png"), "woof").unwrap();
+        std::fs::write(bag_dir.path().join("bag-info.txt"), "new").unwrap();
+
+        let report = super::validate_bag(
+            &bag_dir,
+            "blake2s256",
+            &hash,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(
+            report.modified[0].path,
+            PathBuf::from("data/photos/cat.png")
+        );
+    }
+
+    #[test]
+    fn encode_path() {
+        let path = Path::new("data/a%b\nc");
+        let encoded = super::encode_path(path);
+
+        assert_eq!(encoded, PathBuf::from("data/a%25b%0Ac"));
+        assert_eq!(super::decode_path(&encoded), path);
+    }
+}
@@ -0,0 +1,285 @@
+//! Persistent hash cache
+//!
+//! A file is not hashed again while its size and modification time stay the same
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Cached hash of one file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub hash: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Hashes of files with their size and modification time
+///
+/// The cache does not know the hash algorithm, so use one cache per algorithm
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::cache::HashCache;
+/// use std::time::Duration;
+///
+/// let mut cache = HashCache::load("/home/gladi/.cache/hashes.json")
+///     .unwrap_or_default()
+///     .with_mtime_tolerance(Duration::from_secs(2)); // FAT
+///
+/// let hash = Blake2s256::new();
+/// let result = cache.get_hash_file("/mnt/usb/photo.png", &hash).unwrap();
+///
+/// cache.save("/home/gladi/.cache/hashes.json").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+
+    /// Entries by `"device:inode"`
+    #[serde(default)]
+    inodes: HashMap<String, CacheEntry>,
+
+    #[serde(skip)]
+    mtime_tolerance: Duration,
+
+    #[serde(skip)]
+    inode_keys: bool,
+}
+
+impl HashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat modification times that differ by at most **tolerance** as equal
+    ///
+    /// FAT stores times with 2 seconds precision and some NFS servers round them too,
+    /// without a tolerance the cache would never hit on such filesystems
+    pub fn with_mtime_tolerance(mut self, tolerance: Duration) -> Self {
+        self.mtime_tolerance = tolerance;
+        self
+    }
+
+    /// Also find entries by device and inode number
+    ///
+    /// Renamed but unchanged files and the same file seen through a bind mount
+    /// still hit the cache. Only supported on **Unix**, elsewhere only paths are used
+    pub fn with_inode_keys(mut self, inode_keys: bool) -> Self {
+        self.inode_keys = inode_keys;
+        self
+    }
+
+    /// Load cache from **JSON** file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<HashCache, IOError> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data).map_err(IOError::from)
+    }
+
+    /// Save cache to **JSON** file
+    ///
+    /// The mtime tolerance is not saved
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IOError> {
+        crate::fs::write_atomic(path, false, |writer| {
+            serde_json::to_writer(writer, self).map_err(IOError::from)
+        })
+    }
+
+    /// Get cached hash if the file has not changed
+    pub fn get<P: AsRef<Path>>(
+        &self,
+        path: P,
+        metadata: &Metadata,
+    ) -> Option<&str> {
+        let modified = metadata.modified().ok()?;
+        let is_fresh = |entry: &&CacheEntry| {
+            entry.size == metadata.len()
+                && self.same_time(entry.modified, modified)
+        };
+
+        let by_inode = match self.inode_keys {
+            true => inode_key(metadata).and_then(|key| self.inodes.get(&key)),
+            false => None,
+        };
+
+        by_inode
+            .filter(is_fresh)
+            .or_else(|| self.entries.get(path.as_ref()).filter(is_fresh))
+            .map(|entry| entry.hash.as_str())
+    }
+
+    /// Remember hash of file
+    pub fn insert<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        metadata: &Metadata,
+        hash: String,
+    ) -> Result<(), IOError> {
+        let entry = CacheEntry {
+            hash,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        };
+
+        if self.inode_keys {
+            if let Some(key) = inode_key(metadata) {
+                self.inodes.insert(key, entry.clone());
+            }
+        }
+
+        self.entries.insert(path.as_ref().to_path_buf(), entry);
+        Ok(())
+    }
+
+    /// Get hash of **file** from the cache, or hash the file and cache the result
+    ///
+    /// The file is hashed with a copy of `hash`
+    pub fn get_hash_file<HashType, P>(
+        &mut self,
+        path: P,
+        hash: &HashType,
+    ) -> Result<String, IOError>
+    where
+        HashType: DynDigest + Clone,
+        P: AsRef<Path>,
+    {
+        self.get_hash_file_with(path, hash, |_| {})
+    }
+
+    /// The same as [HashCache::get_hash_file], but a hash from the cache is reported through **progress**
+    /// as [ProgressInfo::CacheHit]
+    pub fn get_hash_file_with<HashType, P>(
+        &mut self,
+        path: P,
+        hash: &HashType,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<String, IOError>
+    where
+        HashType: DynDigest + Clone,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let metadata = std::fs::metadata(path)?;
+
+        if let Some(cached) = self.get(path, &metadata) {
+            let cached = cached.to_string();
+            progress(ProgressInfo::CacheHit(path.to_path_buf()));
+            return Ok(cached);
+        }
+
+        let result = crate::get_hash_file(path, hash)?;
+        self.insert(path, &metadata, result.clone())?;
+
+        Ok(result)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn same_time(&self, a: SystemTime, b: SystemTime) -> bool {
+        let difference = a
+            .duration_since(b)
+            .or_else(|_| b.duration_since(a))
+            .unwrap_or_default();
+
+        difference <= self.mtime_tolerance
+    }
+}
+
+#[cfg(unix)]
+fn inode_key(metadata: &Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(format!("{}:{}", metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode_key(_metadata: &Metadata) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashCache;
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn get_hash_file() {
+        let hash = Blake2s256::new();
+        let (temp_dir, path) = extra::generate_random_file(32);
+        let cache_path = temp_dir.path().join("cache.json");
+
+        let mut cache = HashCache::new();
+        let result = cache.get_hash_file(&path, &hash).unwrap();
+        cache.save(&cache_path).unwrap();
+
+        let mut cache = HashCache::load(&cache_path).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(cache.get(&path, &metadata), Some(result.as_str()));
+
+        let hits = std::cell::RefCell::new(Vec::new());
+        let cached = cache
+            .get_hash_file_with(&path, &hash, |info| {
+                if let crate::ProgressInfo::CacheHit(path) = info {
+                    hits.borrow_mut().push(path);
+                }
+            })
+            .unwrap();
+        assert_eq!(cached, result);
+        assert_eq!(hits.into_inner(), vec![path.to_path_buf()]);
+    }
+
+    #[test]
+    fn mtime_tolerance() {
+        let (_temp_dir, path) = extra::generate_random_file(32);
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+
+        let mut cache = HashCache::new();
+        let metadata = std::fs::metadata(&path).unwrap();
+        cache.insert(&path, &metadata, String::from("aa")).unwrap();
+
+        // Coarse timestamps of FAT
+        let modified = metadata.modified().unwrap() + Duration::from_secs(1);
+        file.set_modified(modified).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(cache.get(&path, &metadata), None);
+
+        let cache = cache.with_mtime_tolerance(Duration::from_secs(2));
+        assert_eq!(cache.get(&path, &metadata), Some("aa"));
+
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(cache.get(&path, &metadata), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inode_keys() {
+        let hash = Blake2s256::new();
+        let (temp_dir, path) = extra::generate_random_file(32);
+        let renamed = temp_dir.path().join("renamed.txt");
+
+        let mut cache = HashCache::new().with_inode_keys(true);
+        let result = cache.get_hash_file(&path, &hash).unwrap();
+
+        std::fs::rename(&path, &renamed).unwrap();
+        let metadata = std::fs::metadata(&renamed).unwrap();
+        assert_eq!(cache.get(&renamed, &metadata), Some(result.as_str()));
+
+        let cache = cache.with_inode_keys(false);
+        assert_eq!(cache.get(&renamed, &metadata), None);
+    }
+}
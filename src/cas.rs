@@ -0,0 +1,441 @@
+//! Content-addressed store
+//!
+//! Files are stored under their hash, split after the first two characters, with their original name:
+//!
+//! ```text
+//! store/
+//! ├── 8c/
+//! │   └── 5a0b0e3c.../
+//! │       └── cat.png
+//! └── 1f/
+//!     └── 3d5e2a9b.../
+//!         └── dog.png
+//! ```
+//!
+//! The same content is stored only once
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How [import] puts files into the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Copy the file, it is hashed while it is copied
+    Copy,
+
+    /// Hard link the file, the store must be on the same filesystem
+    HardLink,
+}
+
+/// File in the store
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Object {
+    pub hash: String,
+
+    /// Path relative to the store, e.g. `8c/5a0b0e3c.../cat.png`
+    pub path: PathBuf,
+}
+
+/// Put **files** into the **store**
+///
+/// Files are imported in parallel, each is hashed with a copy of `hash`.
+/// A file whose content is already in the store under the same name is not stored again
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::cas::{self, ImportMode};
+/// use file_hashing::{fs, HashOptions};
+///
+/// let files = fs::get_all_file_from_folder("/home/gladi/Pictures");
+/// let objects = cas::import(
+///     "/mnt/backup/store",
+///     &files,
+///     &Sha256::new(),
+///     ImportMode::Copy,
+///     &HashOptions::new(4),
+///     |_| {},
+/// )
+/// .unwrap();
+///
+/// for (path, object) in objects.iter() {
+///     println!("{} -> {}", path.display(), object.path.display());
+/// }
+/// ```
+///
+/// # Error
+///
+/// * Files that cannot be imported are reported through **progress** as [ProgressInfo::Error] and are not included
+/// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+pub fn import<HashType, P, Q>(
+    store: P,
+    files: &[Q],
+    hash: &HashType,
+    mode: ImportMode,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<BTreeMap<PathBuf, Object>, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+    Q: AsRef<Path> + std::marker::Sync,
+{
+    let store = store.as_ref();
+    let options = &options.for_layout();
+    std::fs::create_dir_all(store)?;
+
+    let progress = options.broadcast(progress);
+    let pool = options.pool();
+    let results: Vec<Result<Object, IOError>> =
+        pool.map_with(files.iter().collect(), hash.clone(), |hash, path| {
+            options.check_cancelled()?;
+            import_file(store, path.as_ref(), hash.clone(), mode, options)
+        });
+
+    let mut objects = BTreeMap::new();
+    let mut done_files = 0;
+
+    for (path, result) in files.iter().zip(results) {
+        done_files += 1;
+
+        match result {
+            Ok(object) => {
+                objects.insert(path.as_ref().to_path_buf(), object);
+                progress(ProgressInfo::Yield(done_files));
+            }
+            Err(error)
+                if error.kind() == IOErrorKind::Interrupted
+                    && options.is_cancelled() => {}
+            Err(error) => progress(ProgressInfo::Error(error)),
+        }
+    }
+
+    options.check_cancelled()?;
+    Ok(objects)
+}
+
+/// Hash every object of the **store** again and report the corrupted ones
+///
+/// Objects are hashed in parallel with fresh copies of `hash`.
+/// An object is corrupted if its content no longer matches the hash in its path
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{cas, HashOptions};
+///
+/// let corrupted =
+///     cas::verify("/mnt/backup/store", &Sha256::new(), &HashOptions::new(4), |_| {})
+///         .unwrap();
+///
+/// for object in corrupted {
+///     println!("corrupted: {}", object.path.display());
+/// }
+/// ```
+///
+/// # Error
+///
+/// * Objects that cannot be read are reported through **progress** as [ProgressInfo::Error] and are skipped
+/// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+pub fn verify<HashType, P>(
+    store: P,
+    hash: &HashType,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<Vec<Object>, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    let store = store.as_ref();
+    let options = &options.for_layout();
+    let progress = options.broadcast(progress);
+    let objects = list(store, options, &progress);
+    let paths: Vec<PathBuf> = objects
+        .iter()
+        .map(|object| store.join(&object.path))
+        .collect();
+    let results = file::get_hash_each_file(&paths, hash, options);
+    options.check_cancelled()?;
+
+    let mut corrupted = Vec::new();
+    let mut done_files = 0;
+
+    for (object, result) in objects.into_iter().zip(results) {
+        done_files += 1;
+
+        match result {
+            Ok(result) => {
+                if !result.eq_ignore_ascii_case(&object.hash) {
+                    corrupted.push(object);
+                }
+                progress(ProgressInfo::Yield(done_files));
+            }
+            Err(error) => progress(ProgressInfo::Error(error)),
+        }
+    }
+
+    Ok(corrupted)
+}
+
+/// Remove the objects of the **store** whose hash is not in **referenced**
+///
+/// Folders left empty are removed too. Returns the removed objects
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::{cas, manifest::Manifest, HashOptions};
+///
+/// // Hashes of every backup that is kept
+/// let manifest = Manifest::load("/mnt/backup/SHA256SUMS").unwrap();
+/// let referenced = manifest.entries.values().cloned().collect();
+///
+/// let removed =
+///     cas::prune("/mnt/backup/store", &referenced, &HashOptions::new(4), |_| {})
+///         .unwrap();
+/// println!("{} objects removed", removed.len());
+/// ```
+///
+/// # Error
+///
+/// * Objects that cannot be removed are reported through **progress** as [ProgressInfo::Error] and are kept
+pub fn prune<P: AsRef<Path>>(
+    store: P,
+    referenced: &HashSet<String>,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<Vec<Object>, IOError> {
+    let store = store.as_ref();
+    let progress = options.broadcast(progress);
+    let referenced: HashSet<String> = referenced
+        .iter()
+        .map(|hash| hash.to_ascii_lowercase())
+        .collect();
+
+    let mut removed = Vec::new();
+
+    for object in list(store, options, &progress) {
+        if referenced.contains(&object.hash.to_ascii_lowercase()) {
+            continue;
+        }
+
+        let path = store.join(&object.path);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                // Only empty folders are removed, errors mean "not empty"
+                for dir in path.ancestors().skip(1).take(2) {
+                    if std::fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                }
+
+                removed.push(object);
+            }
+            Err(error) => progress(ProgressInfo::Error(error)),
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Objects of the **store**, files that are not objects (e.g. unfinished imports) are left out
+fn list(
+    store: &Path,
+    options: &HashOptions,
+    progress: &impl Fn(ProgressInfo),
+) -> Vec<Object> {
+    fs::walk(store, options)
+        .report_errors(progress)
+        .into_iter()
+        .filter_map(|entry| {
+            let path = entry.path.strip_prefix(store).ok()?.to_path_buf();
+            let mut components = path.iter().map(|c| c.to_str());
+            let hash = match (
+                components.next()??,
+                components.next()??,
+                components.next(),
+                components.next(),
+            ) {
+                (prefix, rest, Some(_), None) => {
+                    format!("{}{}", prefix, rest)
+                }
+                _ => return None,
+            };
+
+            Some(Object { hash, path })
+        })
+        .collect()
+}
+
+/// Path of an object relative to the store
+pub fn object_path(hash: &str, name: &Path) -> PathBuf {
+    let split = hash.len().min(2);
+    Path::new(&hash[..split]).join(&hash[split..]).join(name)
+}
+
+fn import_file<HashType>(
+    store: &Path,
+    path: &Path,
+    mut hash: HashType,
+    mode: ImportMode,
+    options: &HashOptions,
+) -> Result<Object, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+    let name = path.file_name().ok_or_else(|| {
+        IOError::new(
+            IOErrorKind::InvalidInput,
+            format!("{} has no file name", path.display()),
+        )
+    })?;
+
+    let object = match mode {
+        ImportMode::Copy => {
+            let temp = store.join(format!(
+                ".import-{}-{}.tmp",
+                std::process::id(),
+                TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+            ));
+            let reader = fs::open_file(path, options)?;
+            let writer = BufWriter::new(File::create(&temp)?);
+
+            let (_, result) =
+                crate::io::copy_and_hash_writer(reader, writer, &mut hash)
+                    .inspect_err(|_| {
+                        let _ = std::fs::remove_file(&temp);
+                    })?;
+            let object = object_path(&result, Path::new(name));
+            let target = store.join(&object);
+
+            if target.exists() {
+                std::fs::remove_file(&temp)?;
+            } else {
+                std::fs::create_dir_all(target.parent().unwrap())?;
+                std::fs::rename(&temp, &target)?;
+            }
+
+            Object {
+                hash: result,
+                path: object,
+            }
+        }
+        ImportMode::HardLink => {
+            let (result, _) = file::hash_file_with(path, &hash, options)?;
+            let object = object_path(&result, Path::new(name));
+            let target = store.join(&object);
+
+            if !target.exists() {
+                std::fs::create_dir_all(target.parent().unwrap())?;
+                std::fs::hard_link(path, &target)?;
+            }
+
+            Object {
+                hash: result,
+                path: object,
+            }
+        }
+    };
+
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImportMode;
+    use crate::fs::extra;
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+
+    #[test]
+    fn import() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(5, 64);
+        let store = assert_fs::TempDir::new().unwrap();
+
+        for mode in [ImportMode::Copy, ImportMode::HardLink] {
+            let objects =
+                super::import(&store, &paths, &hash, mode, &options, |_| {})
+                    .unwrap();
+            assert_eq!(objects.len(), 5);
+
+            for path in paths.iter() {
+                let object = &objects[path.path()];
+                let hash = Blake2s256::new();
+
+                assert_eq!(
+                    object.hash,
+                    crate::get_hash_file(path, &hash).unwrap()
+                );
+                assert_eq!(
+                    std::fs::read(store.path().join(&object.path)).unwrap(),
+                    std::fs::read(path).unwrap()
+                );
+            }
+        }
+
+        // Stored once, no temporary files left
+        assert_eq!(
+            crate::fs::get_all_file_from_folder(&store).len(),
+            paths.len()
+        );
+    }
+
+    #[test]
+    fn object_path() {
+        assert_eq!(
+            super::object_path("8c5a0b", "cat.png".as_ref()),
+            std::path::Path::new("8c").join("5a0b").join("cat.png")
+        );
+    }
+
+    #[test]
+    fn verify_and_prune() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(5, 64);
+        let store = assert_fs::TempDir::new().unwrap();
+
+        let objects = super::import(
+            &store,
+            &paths,
+            &hash,
+            ImportMode::Copy,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        std::fs::write(store.path().join(".import-1-1.tmp"), "").unwrap();
+
+        let corrupted = objects[paths[0].path()].clone();
+        std::fs::write(store.path().join(&corrupted.path), "bit rot")
+            .unwrap();
+
+        let report = super::verify(&store, &hash, &options, |_| {}).unwrap();
+        assert_eq!(report, vec![corrupted]);
+
+        let kept = objects[paths[1].path()].clone();
+        let referenced = [kept.hash.clone()].into_iter().collect();
+        let removed =
+            super::prune(&store, &referenced, &options, |_| {}).unwrap();
+        assert_eq!(removed.len(), 4);
+
+        let report = super::verify(&store, &hash, &options, |_| {}).unwrap();
+        assert!(report.is_empty());
+        assert!(store.path().join(&kept.path).is_file());
+        assert_eq!(std::fs::read_dir(&store).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,475 @@
+//! Content-defined chunking
+//!
+//! Files are split with [FastCDC](https://www.usenix.org/conference/atc16/technical-sessions/presentation/xia),
+//! so an insert or a delete only changes the chunks around it.
+//! A [ChunkManifest] stores the chunks of a file for delta synchronization
+
+use super::*;
+use fastcdc::v2020::{
+    StreamCDC, AVERAGE_MAX, AVERAGE_MIN, MAXIMUM_MAX, MINIMUM_MIN,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::{
+    fs::File,
+    io::{Read, Write},
+};
+
+/// One piece of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Position in the file
+    pub offset: u64,
+
+    /// Length in bytes
+    pub length: usize,
+
+    /// Hash of the chunk data
+    pub hash: String,
+}
+
+/// Chunk size limits in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkSizes {
+    pub min: usize,
+    pub avg: usize,
+    pub max: usize,
+}
+
+impl Default for ChunkSizes {
+    fn default() -> Self {
+        Self {
+            min: 16 * 1024,
+            avg: 64 * 1024,
+            max: 256 * 1024,
+        }
+    }
+}
+
+impl ChunkSizes {
+    fn is_valid(&self) -> bool {
+        self.min >= MINIMUM_MIN
+            && (AVERAGE_MIN..=AVERAGE_MAX).contains(&self.avg)
+            && self.max <= MAXIMUM_MAX
+            && self.min <= self.avg
+            && self.avg <= self.max
+    }
+}
+
+/// Get hash of each chunk of **file**
+///
+/// Every chunk is hashed with a copy of `hash`
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::chunking::{get_hash_chunks, ChunkSizes};
+///
+/// let hash = Blake2s256::new();
+/// let chunks = get_hash_chunks(
+///     "/home/gladi/backup.tar",
+///     &hash,
+///     &ChunkSizes::default(),
+/// )
+/// .unwrap();
+///
+/// for chunk in chunks {
+///     println!("{} {} {}", chunk.offset, chunk.length, chunk.hash);
+/// }
+/// ```
+///
+/// # Error
+///
+/// * If the **sizes** are out of range, the **IOErrorKind::InvalidInput** error will be returned
+pub fn get_hash_chunks<HashType, P>(
+    path: P,
+    hash: &HashType,
+    sizes: &ChunkSizes,
+) -> Result<Vec<Chunk>, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    get_hash_chunks_reader(File::open(path)?, hash, sizes)
+}
+
+/// Get hash of each chunk of **reader**
+///
+/// The same as [get_hash_chunks], but for any [Read]
+pub fn get_hash_chunks_reader<HashType, R>(
+    reader: R,
+    hash: &HashType,
+    sizes: &ChunkSizes,
+) -> Result<Vec<Chunk>, IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+{
+    if !sizes.is_valid() {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let mut chunks = Vec::new();
+
+    for chunk in StreamCDC::new(reader, sizes.min, sizes.avg, sizes.max) {
+        let chunk = chunk?;
+
+        let mut chunk_hash = hash.clone();
+        chunk_hash.update(&chunk.data);
+
+        chunks.push(Chunk {
+            offset: chunk.offset,
+            length: chunk.length,
+            hash: crate::encoding::get_lowerhex(&mut chunk_hash),
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// How a file is split into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Chunker {
+    /// Chunks of **size** bytes, the last one may be shorter
+    Fixed { size: usize },
+
+    /// Content-defined chunks, see [get_hash_chunks]
+    Cdc(ChunkSizes),
+}
+
+/// Chunks of one file as a stable interchange format
+///
+/// Stored as **JSON**:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "algorithm": "BLAKE2s-256",
+///   "chunker": { "type": "cdc", "min": 16384, "avg": 65536, "max": 262144 },
+///   "file_size": 1048576,
+///   "chunks": [{ "offset": 0, "length": 70213, "hash": "..." }, ...]
+/// }
+/// ```
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::chunking::{ChunkManifest, ChunkSizes, Chunker};
+///
+/// let hash = Blake2s256::new();
+/// let chunker = Chunker::Cdc(ChunkSizes::default());
+///
+/// let old = ChunkManifest::load("/home/gladi/backup.tar.chunks").unwrap();
+/// let new = ChunkManifest::create("/home/gladi/backup.tar", &hash, &chunker).unwrap();
+///
+/// let diff = old.compare(&new);
+/// println!("need to send {} bytes", diff.missing_bytes());
+/// new.save("/home/gladi/backup.tar.chunks").unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    /// Format version, [ChunkManifest::VERSION] for manifests of this crate
+    pub version: u32,
+
+    /// Name of the hash algorithm if it is known, see [identify](crate::selftest::identify)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+
+    pub chunker: Chunker,
+
+    /// Size of the file
+    pub file_size: u64,
+
+    /// In file order, without gaps
+    pub chunks: Vec<Chunk>,
+}
+
+/// Chunks of a newer [ChunkManifest] compared with a baseline, see [ChunkManifest::compare]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkDiff {
+    /// Chunks whose hash the baseline does not have, their data has to be transferred
+    pub missing: Vec<Chunk>,
+
+    /// Chunks whose hash the baseline has, their data can be copied from the old file
+    pub reused: Vec<Chunk>,
+}
+
+impl ChunkDiff {
+    /// Bytes that have to be transferred
+    pub fn missing_bytes(&self) -> u64 {
+        self.missing.iter().map(|chunk| chunk.length as u64).sum()
+    }
+
+    /// Bytes that can be copied from the old file
+    pub fn reused_bytes(&self) -> u64 {
+        self.reused.iter().map(|chunk| chunk.length as u64).sum()
+    }
+}
+
+impl ChunkManifest {
+    pub const VERSION: u32 = 1;
+
+    /// Split **file** with **chunker** and hash every chunk with a copy of `hash`
+    ///
+    /// # Error
+    ///
+    /// * If the chunk sizes are 0 or out of range, the **IOErrorKind::InvalidInput** error will be returned
+    pub fn create<HashType, P>(
+        path: P,
+        hash: &HashType,
+        chunker: &Chunker,
+    ) -> Result<ChunkManifest, IOError>
+    where
+        HashType: DynDigest + Clone,
+        P: AsRef<Path>,
+    {
+        ChunkManifest::create_reader(File::open(path)?, hash, chunker)
+    }
+
+    /// The same as [ChunkManifest::create], but for any [Read]
+    pub fn create_reader<HashType, R>(
+        mut reader: R,
+        hash: &HashType,
+        chunker: &Chunker,
+    ) -> Result<ChunkManifest, IOError>
+    where
+        HashType: DynDigest + Clone,
+        R: Read,
+    {
+        let chunks = match chunker {
+            Chunker::Cdc(sizes) => {
+                get_hash_chunks_reader(reader, hash, sizes)?
+            }
+            Chunker::Fixed { size } => {
+                let signature = crate::delta::generate_signature_reader(
+                    &mut reader,
+                    hash,
+                    *size,
+                )?;
+
+                let file_size = signature.file_size;
+                let mut offset = 0;
+                signature
+                    .blocks
+                    .into_iter()
+                    .map(|block| {
+                        let length =
+                            std::cmp::min(*size as u64, file_size - offset);
+                        let chunk = Chunk {
+                            offset,
+                            length: length as usize,
+                            hash: block.strong,
+                        };
+                        offset += length;
+                        chunk
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(ChunkManifest {
+            version: ChunkManifest::VERSION,
+            algorithm: crate::selftest::identify(hash).map(String::from),
+            chunker: *chunker,
+            file_size: chunks.iter().map(|chunk| chunk.length as u64).sum(),
+            chunks,
+        })
+    }
+
+    /// Compare with a **newer** manifest, no files are read
+    ///
+    /// `self` is treated as the baseline. Chunks are matched by hash wherever they are in the file,
+    /// so both manifests should use the same [Chunker] and algorithm
+    pub fn compare(&self, other: &ChunkManifest) -> ChunkDiff {
+        let known: HashSet<&str> = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.hash.as_str())
+            .collect();
+        let (reused, missing) = other
+            .chunks
+            .iter()
+            .cloned()
+            .partition(|chunk| known.contains(chunk.hash.as_str()));
+
+        ChunkDiff { missing, reused }
+    }
+
+    /// Parse manifest from **JSON**
+    ///
+    /// # Error
+    ///
+    /// * If the text is not a manifest, has an unknown version
+    ///   or its chunks do not cover the file without gaps, the **IOErrorKind::InvalidData** error will be returned
+    pub fn parse(text: &str) -> Result<ChunkManifest, IOError> {
+        let manifest: ChunkManifest = serde_json::from_str(text)
+            .map_err(|error| IOError::new(IOErrorKind::InvalidData, error))?;
+
+        if manifest.version != ChunkManifest::VERSION {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!(
+                    "unknown chunk manifest version {}",
+                    manifest.version
+                ),
+            ));
+        }
+
+        let mut offset = 0;
+        for chunk in manifest.chunks.iter() {
+            if chunk.offset != offset {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidData,
+                    format!(
+                        "chunk at {} does not follow {}",
+                        chunk.offset, offset
+                    ),
+                ));
+            }
+            offset =
+                offset.checked_add(chunk.length as u64).ok_or_else(|| {
+                    IOError::new(
+                        IOErrorKind::InvalidData,
+                        "chunk lengths overflow",
+                    )
+                })?;
+        }
+
+        if offset != manifest.file_size {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!(
+                    "chunks cover {} of {} bytes",
+                    offset, manifest.file_size
+                ),
+            ));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Load manifest from file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ChunkManifest, IOError> {
+        ChunkManifest::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write manifest as pretty **JSON**
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), IOError> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Save manifest to file
+    ///
+    /// The file is replaced atomically, an interrupted save leaves the old manifest in place
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IOError> {
+        fs::write_atomic(path, false, |writer| self.write(writer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkManifest, ChunkSizes, Chunker};
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+    use std::collections::HashSet;
+
+    #[test]
+    fn get_hash_chunks() {
+        let hash = Blake2s256::new();
+        let (_temp_dir, path) = extra::generate_random_file(1024 * 1024);
+        let sizes = ChunkSizes::default();
+
+        let chunks = super::get_hash_chunks(&path, &hash, &sizes).unwrap();
+        let total: usize = chunks.iter().map(|chunk| chunk.length).sum();
+        assert_eq!(total, 1024 * 1024);
+
+        // Insert data at the beginning: only the first chunks may change
+        let mut data = b"inserted".to_vec();
+        data.extend(std::fs::read(&path).unwrap());
+        let shifted =
+            super::get_hash_chunks_reader(&data[..], &hash, &sizes).unwrap();
+
+        let before: HashSet<_> = chunks.iter().map(|c| &c.hash).collect();
+        let common =
+            shifted.iter().filter(|c| before.contains(&c.hash)).count();
+        assert!(common >= chunks.len() - 2);
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let hash = Blake2s256::new();
+        let sizes = ChunkSizes {
+            min: 1,
+            avg: 2,
+            max: 3,
+        };
+
+        let error = super::get_hash_chunks_reader(&b""[..], &hash, &sizes)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn chunk_manifest() {
+        let hash = Blake2s256::new();
+        let (temp_dir, path) = extra::generate_random_file(10_000);
+
+        let fixed = Chunker::Fixed { size: 4096 };
+        let manifest = ChunkManifest::create(&path, &hash, &fixed).unwrap();
+        let fixed_manifest = manifest.clone();
+        assert_eq!(manifest.file_size, 10_000);
+        assert_eq!(manifest.chunks.len(), 3);
+        assert_eq!(manifest.chunks[2].offset, 8192);
+        assert_eq!(manifest.chunks[2].length, 10_000 - 8192);
+        assert_eq!(manifest.algorithm.as_deref(), Some("BLAKE2s-256"));
+
+        let saved = temp_dir.path().join("file.chunks");
+        manifest.save(&saved).unwrap();
+        assert_eq!(ChunkManifest::load(&saved).unwrap(), manifest);
+
+        let mut data = std::fs::read(&path).unwrap();
+        data[5000] ^= 1;
+        let changed =
+            ChunkManifest::create_reader(&data[..], &hash, &fixed).unwrap();
+        let diff = manifest.compare(&changed);
+        assert_eq!(diff.missing.len(), 1);
+        assert_eq!(diff.missing[0].offset, 4096);
+        assert_eq!(diff.reused_bytes(), 10_000 - 4096);
+
+        let cdc = Chunker::Cdc(ChunkSizes::default());
+        let manifest = ChunkManifest::create(&path, &hash, &cdc).unwrap();
+        let mut json = Vec::new();
+        manifest.write(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"type\": \"cdc\""));
+        assert!(manifest.compare(&manifest).missing.is_empty());
+
+        let error = ChunkManifest::parse(
+            &json.replace("\"file_size\": 10000", "\"file_size\": 9999"),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let error = ChunkManifest::parse(
+            &json.replace("\"version\": 1", "\"version\": 2"),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let mut overflow = fixed_manifest;
+        overflow.chunks.truncate(2);
+        overflow.chunks[0].length = usize::MAX;
+        overflow.chunks[1].offset = usize::MAX as u64;
+        overflow.chunks[1].length = usize::MAX;
+        let mut json = Vec::new();
+        overflow.write(&mut json).unwrap();
+        let error = ChunkManifest::parse(&String::from_utf8(json).unwrap())
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("overflow"));
+    }
+}
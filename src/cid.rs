@@ -0,0 +1,432 @@
+//! [CIDv1](https://github.com/multiformats/cid) of files
+//!
+//! A CID of the `raw` codec addresses the content of one file by its hash,
+//! the same way IPFS does for files that fit into one block.
+//! Bigger files are addressed by the root of their UnixFS DAG, see [get_unixfs_cid_file]
+
+use super::*;
+use crate::encoding::Multibase;
+
+/// Multicodec code of the `raw` content type
+const RAW: u64 = 0x55;
+
+/// Multicodec code of `dag-pb`, the codec of UnixFS nodes
+const DAG_PB: u64 = 0x70;
+
+/// Most links of one node in the balanced layout of `ipfs add`
+const MAX_LINKS: usize = 174;
+
+/// [Multicodec](https://github.com/multiformats/multicodec) codes of the algorithms known to [identify](crate::selftest::identify)
+const CODES: &[(&str, u64)] = &[
+    ("MD5", 0xd5),
+    ("SHA-1", 0x11),
+    ("SHA-256", 0x12),
+    ("SHA-384", 0x20),
+    ("SHA-512", 0x13),
+    ("SHA3-256", 0x16),
+    ("SHA3-512", 0x14),
+    ("BLAKE2s-256", 0xb260),
+    ("BLAKE2b-512", 0xb240),
+    ("BLAKE3", 0x1e),
+];
+
+/// Multicodec code of the algorithm of `hash`, `None` if it is unknown
+pub fn multihash_code<HashType>(hash: &HashType) -> Option<u64>
+where
+    HashType: DynDigest + Clone,
+{
+    let name = crate::selftest::identify(hash)?;
+
+    CODES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, code)| *code)
+}
+
+/// [Multihash](https://github.com/multiformats/multihash) of **digest**: code, length and the digest itself
+pub fn multihash(code: u64, digest: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(digest.len() + 4);
+    push_varint(&mut result, code);
+    push_varint(&mut result, digest.len() as u64);
+    result.extend_from_slice(digest);
+    result
+}
+
+/// Get CIDv1 of the `raw` codec for **file**, e.g. `bafkrei...` with [Multibase::Base32]
+///
+/// The file is read through the same pipeline as [get_hash_file]
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{cid::get_cid_file, encoding::Multibase};
+///
+/// let hash = Sha256::new();
+/// let cid = get_cid_file("/home/gladi/cat.png", &hash, Multibase::Base32).unwrap();
+///
+/// assert!(cid.starts_with("bafkrei"));
+/// ```
+///
+/// # Error
+///
+/// * If the algorithm has no known multicodec code, the **IOErrorKind::Unsupported** error will be returned
+pub fn get_cid_file<HashType, P>(
+    path: P,
+    hash: &HashType,
+    base: Multibase,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    let code = multihash_code(hash).ok_or_else(|| {
+        IOError::new(IOErrorKind::Unsupported, "unknown algorithm")
+    })?;
+
+    let mut hash = hash.clone();
+    crate::io::hash_update(std::fs::File::open(path)?, &mut hash)?;
+    let digest = Box::new(hash).finalize();
+
+    let mut cid = Vec::with_capacity(digest.len() + 6);
+    push_varint(&mut cid, 1);
+    push_varint(&mut cid, RAW);
+    cid.extend(multihash(code, &digest));
+
+    Ok(base.encode(&cid))
+}
+
+/// Settings of the UnixFS DAG, see [get_unixfs_cid_file]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DagOptions {
+    /// Size of the leaves, 256 KiB by default
+    pub chunk_size: usize,
+
+    /// Emit CIDv1 instead of CIDv0 (`Qm...`)
+    pub cid_v1: bool,
+
+    /// Store leaves as `raw` blocks instead of UnixFS nodes, only with CIDv1
+    pub raw_leaves: bool,
+
+    /// Base of the CIDv1, CIDv0 is always base58btc without a prefix
+    pub base: Multibase,
+}
+
+impl DagOptions {
+    /// The same DAG as `ipfs add`
+    pub fn v0() -> Self {
+        Self {
+            chunk_size: 256 * 1024,
+            cid_v1: false,
+            raw_leaves: false,
+            base: Multibase::Base58Btc,
+        }
+    }
+
+    /// The same DAG as `ipfs add --cid-version=1`
+    pub fn v1() -> Self {
+        Self {
+            chunk_size: 256 * 1024,
+            cid_v1: true,
+            raw_leaves: true,
+            base: Multibase::Base32,
+        }
+    }
+}
+
+impl Default for DagOptions {
+    fn default() -> Self {
+        Self::v1()
+    }
+}
+
+/// Block of the DAG as its parent links to it
+struct DagNode {
+    cid: Vec<u8>,
+
+    /// Bytes of all blocks of the subtree
+    tsize: u64,
+
+    /// Bytes of the file in the subtree
+    filesize: u64,
+}
+
+/// Get root CID of the UnixFS DAG of **file**, the same as `ipfs add --only-hash` reports
+///
+/// The file is split into leaves of **chunk_size** bytes, which are joined by a balanced tree
+/// with at most 174 links per node. Every block is hashed with a copy of `hash`,
+/// which must be SHA-256 for CIDv0
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::cid::{get_unixfs_cid_file, DagOptions};
+///
+/// let cid = get_unixfs_cid_file("/home/gladi/video.mp4", &Sha256::new(), &DagOptions::v0()).unwrap();
+/// assert!(cid.starts_with("Qm"));
+/// ```
+///
+/// # Error
+///
+/// * If the algorithm has no known multicodec code, the **IOErrorKind::Unsupported** error will be returned
+/// * If **chunk_size** is 0, or CIDv0 is combined with raw leaves or an algorithm other than SHA-256,
+///   the **IOErrorKind::InvalidInput** error will be returned
+pub fn get_unixfs_cid_file<HashType, P>(
+    path: P,
+    hash: &HashType,
+    options: &DagOptions,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    let code = multihash_code(hash).ok_or_else(|| {
+        IOError::new(IOErrorKind::Unsupported, "unknown algorithm")
+    })?;
+
+    if options.chunk_size == 0
+        || (!options.cid_v1 && (options.raw_leaves || code != 0x12))
+    {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let block = |codec: u64, data: &[u8]| -> Vec<u8> {
+        let mut hash = hash.clone();
+        hash.update(data);
+        let multihash = multihash(code, &hash.finalize_reset());
+
+        match options.cid_v1 {
+            true => {
+                let mut cid = Vec::with_capacity(multihash.len() + 2);
+                push_varint(&mut cid, 1);
+                push_varint(&mut cid, codec);
+                cid.extend(multihash);
+                cid
+            }
+            false => multihash,
+        }
+    };
+
+    let mut file = std::fs::File::open(path)?;
+    let mut chunk = vec![0; options.chunk_size];
+    let mut nodes: Vec<DagNode> = Vec::new();
+
+    loop {
+        let len = read_full(&mut file, &mut chunk)?;
+        if len == 0 && !nodes.is_empty() {
+            break;
+        }
+
+        let data = &chunk[..len];
+        nodes.push(match options.raw_leaves {
+            true => DagNode {
+                cid: block(RAW, data),
+                tsize: len as u64,
+                filesize: len as u64,
+            },
+            false => {
+                let node = dag_pb(&[], &unixfs_file(data, len as u64, &[]));
+                DagNode {
+                    cid: block(DAG_PB, &node),
+                    tsize: node.len() as u64,
+                    filesize: len as u64,
+                }
+            }
+        });
+
+        if len < chunk.len() {
+            break;
+        }
+    }
+
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(MAX_LINKS)
+            .map(|children| {
+                let filesize =
+                    children.iter().map(|child| child.filesize).sum();
+                let blocksizes: Vec<u64> =
+                    children.iter().map(|child| child.filesize).collect();
+                let node = dag_pb(
+                    children,
+                    &unixfs_file(&[], filesize, &blocksizes),
+                );
+
+                DagNode {
+                    cid: block(DAG_PB, &node),
+                    tsize: node.len() as u64
+                        + children
+                            .iter()
+                            .map(|child| child.tsize)
+                            .sum::<u64>(),
+                    filesize,
+                }
+            })
+            .collect();
+    }
+
+    let root = &nodes[0].cid;
+    Ok(match options.cid_v1 {
+        true => options.base.encode(root),
+        false => Multibase::Base58Btc.encode(root)[1..].to_string(),
+    })
+}
+
+/// Read until **buf** is full or the end of **reader**
+fn read_full(
+    reader: &mut impl std::io::Read,
+    buf: &mut [u8],
+) -> Result<usize, IOError> {
+    let mut len = 0;
+
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(error) if error.kind() == IOErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(len)
+}
+
+/// `PBNode` of dag-pb: links first, then the data, as go-ipfs writes them
+fn dag_pb(links: &[DagNode], data: &[u8]) -> Vec<u8> {
+    let mut node = Vec::new();
+
+    for link in links {
+        let mut pb_link = Vec::new();
+        push_bytes(&mut pb_link, 1, &link.cid);
+        push_bytes(&mut pb_link, 2, &[]); // the name is always written
+        push_varint(&mut pb_link, 3 << 3);
+        push_varint(&mut pb_link, link.tsize);
+
+        push_bytes(&mut node, 2, &pb_link);
+    }
+
+    push_bytes(&mut node, 1, data);
+    node
+}
+
+/// UnixFS `Data` of a file node
+fn unixfs_file(data: &[u8], filesize: u64, blocksizes: &[u64]) -> Vec<u8> {
+    let mut result = vec![1 << 3, 2]; // Type: File
+
+    if !data.is_empty() {
+        push_bytes(&mut result, 2, data);
+    }
+
+    push_varint(&mut result, 3 << 3);
+    push_varint(&mut result, filesize);
+
+    for blocksize in blocksizes {
+        push_varint(&mut result, 4 << 3);
+        push_varint(&mut result, *blocksize);
+    }
+
+    result
+}
+
+/// Protobuf field of wire type 2
+fn push_bytes(buf: &mut Vec<u8>, field: u64, data: &[u8]) {
+    push_varint(buf, (field << 3) | 2);
+    push_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+/// Unsigned LEB128, as multiformats use it
+fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::Multibase;
+    use blake2::{Blake2b512, Digest};
+    use sha2::Sha256;
+
+    #[test]
+    fn get_cid_file() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, "hello world").unwrap();
+
+        let hash = Sha256::new();
+        let cid =
+            super::get_cid_file(&path, &hash, Multibase::Base32).unwrap();
+        assert_eq!(
+            cid,
+            "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+        );
+
+        let hash = Blake2b512::new();
+        let cid =
+            super::get_cid_file(&path, &hash, Multibase::Base16).unwrap();
+        assert!(cid.starts_with("f0155c0e40240")); // raw, blake2b-512, 64 bytes
+    }
+
+    #[test]
+    fn get_unixfs_cid_file() {
+        use super::DagOptions;
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let empty = temp_dir.path().join("empty");
+        let hello = temp_dir.path().join("hello.txt");
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(&hello, "hello world\n").unwrap();
+        assert_eq!(
+            super::get_unixfs_cid_file(
+                &hello,
+                &Sha256::new(),
+                &DagOptions::v0()
+            )
+            .unwrap(),
+            "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+        );
+
+        let cid = |path, options| {
+            super::get_unixfs_cid_file(path, &Sha256::new(), &options)
+                .unwrap()
+        };
+
+        assert_eq!(
+            cid(&empty, DagOptions::v0()),
+            "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH"
+        );
+        // One leaf is the root
+        let hash = Sha256::new();
+        assert_eq!(
+            cid(&hello, DagOptions::v1()),
+            super::get_cid_file(&hello, &hash, Multibase::Base32).unwrap()
+        );
+
+        let small_chunks = DagOptions {
+            chunk_size: 4,
+            ..DagOptions::v1()
+        };
+        let root = cid(&hello, small_chunks);
+        assert!(root.starts_with("bafybei")); // dag-pb
+        assert_ne!(root, cid(&hello, DagOptions::v1()));
+
+        let error = super::get_unixfs_cid_file(
+            &hello,
+            &Blake2b512::new(),
+            &DagOptions::v0(),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn push_varint() {
+        let mut buf = Vec::new();
+        super::push_varint(&mut buf, 0xb240);
+        assert_eq!(buf, [0xc0, 0xe4, 0x02]);
+    }
+}
@@ -0,0 +1,187 @@
+//! Checksums in the formats of cloud storages
+//!
+//! Local files can be compared with uploaded objects without downloading them
+
+use super::*;
+use crate::encoding::Encoding;
+use md5::Md5;
+use std::{
+    fs::File,
+    io::{Read, Write},
+};
+
+/// Get **S3 ETag** of file
+///
+/// S3 uploads a file in parts of **part_size** bytes, its ETag is the MD5 of the concatenated
+/// MD5 of every part plus `-N`, where `N` is the number of parts.
+/// A file that fits into one part is treated as a plain upload, its ETag is just the MD5.
+/// The ETag is returned without quotes
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::cloud::get_s3_etag;
+///
+/// let etag = get_s3_etag("/home/gladi/backup.tar", 8 * 1024 * 1024).unwrap();
+/// println!("{}", etag); // e.g. 9b2cf535f27731c974343645a3985328-12
+/// ```
+///
+/// # Error
+///
+/// * If the **part_size** is 0, the **IOErrorKind::InvalidInput** error will be returned
+pub fn get_s3_etag<P: AsRef<Path>>(
+    path: P,
+    part_size: u64,
+) -> Result<String, IOError> {
+    get_s3_etag_reader(File::open(path)?, part_size)
+}
+
+/// Get **S3 ETag** of reader
+///
+/// The same as [get_s3_etag], but for any [Read]
+pub fn get_s3_etag_reader<R: Read>(
+    mut reader: R,
+    part_size: u64,
+) -> Result<String, IOError> {
+    if part_size == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let mut etag = Md5::default();
+    let mut last_part = Vec::new();
+    let mut parts = 0;
+
+    loop {
+        let mut part = Md5::default();
+        let i =
+            crate::io::hash_update((&mut reader).take(part_size), &mut part)?;
+
+        if i == 0 && parts > 0 {
+            break;
+        }
+
+        last_part = Box::new(part).finalize().to_vec();
+        etag.update(&last_part);
+        parts += 1;
+
+        if i < part_size {
+            break;
+        }
+    }
+
+    if parts == 1 {
+        return Ok(data_encoding::HEXLOWER.encode(&last_part));
+    }
+
+    Ok(format!(
+        "{}-{}",
+        crate::encoding::get_lowerhex(&mut etag),
+        parts
+    ))
+}
+
+/// Get MD5 of file as **Azure Blob Storage** expects it in `Content-MD5`
+///
+/// That is the raw MD5 in base64, see [Encoding::Base64](crate::encoding::Encoding::Base64)
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::cloud::get_azure_content_md5;
+///
+/// let content_md5 = get_azure_content_md5("/home/gladi/backup.tar").unwrap();
+/// println!("Content-MD5: {}", content_md5);
+/// ```
+pub fn get_azure_content_md5<P: AsRef<Path>>(
+    path: P,
+) -> Result<String, IOError> {
+    let mut hash = Md5::default();
+    crate::io::hash_update(File::open(path)?, &mut hash)?;
+
+    Ok(crate::encoding::get_encoded(&mut hash, Encoding::Base64))
+}
+
+/// Get CRC32C of file as **Google Cloud Storage** reports it in object metadata
+///
+/// That is the big-endian CRC32C in base64
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::cloud::get_gcs_crc32c;
+///
+/// let crc32c = get_gcs_crc32c("/home/gladi/backup.tar").unwrap();
+/// println!("crc32c: {}", crc32c); // compare with `gsutil ls -L`
+/// ```
+pub fn get_gcs_crc32c<P: AsRef<Path>>(path: P) -> Result<String, IOError> {
+    get_gcs_crc32c_reader(File::open(path)?)
+}
+
+/// Get CRC32C of reader as **Google Cloud Storage** reports it
+///
+/// The same as [get_gcs_crc32c], but for any [Read]
+pub fn get_gcs_crc32c_reader<R: Read>(
+    mut reader: R,
+) -> Result<String, IOError> {
+    let mut crc = Crc32c(0);
+    std::io::copy(&mut reader, &mut crc)?;
+
+    Ok(Encoding::Base64.encode(&crc.0.to_be_bytes()))
+}
+
+struct Crc32c(u32);
+
+impl Write for Crc32c {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 = crc32c::crc32c_append(self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn get_s3_etag() {
+        let data = [7u8; 25];
+
+        // One part is a plain MD5
+        assert_eq!(
+            super::get_s3_etag_reader(&data[..], 100).unwrap(),
+            "58b24ed0050a103a4ca2b8e978673a70"
+        );
+
+        // 10 + 10 + 5
+        assert_eq!(
+            super::get_s3_etag_reader(&data[..], 10).unwrap(),
+            "5e0a5bcec937ca6c4a327f500c1e2788-3"
+        );
+
+        // Exactly two parts, no empty third part
+        let etag = super::get_s3_etag_reader(&data[..20], 10).unwrap();
+        assert!(etag.ends_with("-2"));
+    }
+
+    #[test]
+    fn get_azure_content_md5() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let path = temp_dir.path().join("hello.txt");
+        std::fs::write(&path, "hello world").unwrap();
+
+        assert_eq!(
+            super::get_azure_content_md5(&path).unwrap(),
+            "XrY7u+Ae7tCTyyK7j1rNww=="
+        );
+    }
+
+    #[test]
+    fn get_gcs_crc32c() {
+        assert_eq!(
+            super::get_gcs_crc32c_reader(&b"hello world"[..]).unwrap(),
+            "yZRlqg=="
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Duplicate files
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Files with the same content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+
+    /// Size of every file of the group
+    pub size: u64,
+
+    /// Sorted, at least two
+    pub paths: Vec<PathBuf>,
+}
+
+/// Which file of a [DuplicateGroup] to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// The first path in sort order
+    FirstPath,
+
+    /// The path with the fewest components, then the first one
+    ShallowestPath,
+}
+
+impl DuplicateGroup {
+    /// Bytes that removing all but one file would free
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+
+    /// The file to keep by **policy**
+    pub fn keeper(&self, policy: KeepPolicy) -> &Path {
+        match policy {
+            KeepPolicy::FirstPath => &self.paths[0],
+            KeepPolicy::ShallowestPath => {
+                self.keeper_by(|path| path.components().count())
+            }
+        }
+    }
+
+    /// The file with the smallest **key** to keep, ties go to the first path
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # let group: file_hashing::dedup::DuplicateGroup = unimplemented!();
+    /// // Keep the oldest file
+    /// let keeper = group.keeper_by(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+    /// ```
+    pub fn keeper_by<K: Ord>(
+        &self,
+        mut key: impl FnMut(&Path) -> K,
+    ) -> &Path {
+        self.paths
+            .iter()
+            .min_by_key(|path| key(path))
+            .expect("a group has at least two paths")
+    }
+
+    /// All files except the one to keep by **policy**
+    pub fn redundant(&self, policy: KeepPolicy) -> Vec<&Path> {
+        let keeper = self.keeper(policy);
+
+        self.paths
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|path| *path != keeper)
+            .collect()
+    }
+}
+
+/// Groups of duplicate files, see [find_duplicates]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    /// Sorted by wasted bytes, the most wasteful first
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DuplicateReport {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Files that are in a group
+    pub fn total_files(&self) -> usize {
+        self.groups.iter().map(|group| group.paths.len()).sum()
+    }
+
+    /// Bytes that removing all redundant files would free
+    pub fn total_wasted_bytes(&self) -> u64 {
+        self.groups.iter().map(DuplicateGroup::wasted_bytes).sum()
+    }
+
+    /// Redundant files of all groups by **policy**
+    pub fn redundant(&self, policy: KeepPolicy) -> Vec<&Path> {
+        self.groups
+            .iter()
+            .flat_map(|group| group.redundant(policy))
+            .collect()
+    }
+}
+
+/// Find files with the same content in **folder**
+///
+/// Only files that share their size with another file are hashed, each with a copy of `hash`.
+/// With [HashOptions::shared_extents] reflinked copies are hashed only once
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2b512, Digest};
+/// use file_hashing::{dedup::{find_duplicates, KeepPolicy}, HashOptions};
+///
+/// let report =
+///     find_duplicates("/home/gladi/Pictures", &Blake2b512::new(), &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// println!("{} bytes wasted", report.total_wasted_bytes());
+/// for path in report.redundant(KeepPolicy::ShallowestPath) {
+///     println!("rm {}", path.display());
+/// }
+/// ```
+///
+/// # Error
+///
+/// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are left out
+/// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+pub fn find_duplicates<HashType, P>(
+    dir: P,
+    hash: &HashType,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<DuplicateReport, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    let dir = fs::resolve_root(dir.as_ref(), options)?;
+    let progress = options.broadcast(progress);
+
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for entry in fs::walk(&dir, options).report_errors(&progress) {
+        by_size.entry(entry.size()).or_default().push(entry.path);
+    }
+
+    let mut clones: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut by_extents = HashMap::new();
+
+    let (sizes, paths): (Vec<u64>, Vec<PathBuf>) = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |p| (size, p)))
+        .filter(|(size, path)| {
+            if !options.shared_extents {
+                return true;
+            }
+
+            // Only the first of reflinked copies is hashed
+            match shared_extents(path) {
+                Some(extents) => match by_extents.entry((*size, extents)) {
+                    Entry::Occupied(first) => {
+                        let first: &PathBuf = first.get();
+                        clones
+                            .entry(first.clone())
+                            .or_default()
+                            .push(path.clone());
+                        false
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(path.clone());
+                        true
+                    }
+                },
+                None => true,
+            }
+        })
+        .unzip();
+
+    let results = file::get_hash_each_file(&paths, hash, options);
+    options.check_cancelled()?;
+
+    let mut groups: BTreeMap<(u64, String), Vec<PathBuf>> = BTreeMap::new();
+    let mut done_files = 0;
+
+    for ((size, path), result) in sizes.into_iter().zip(paths).zip(results) {
+        let clones = clones.remove(&path).unwrap_or_default();
+        done_files += 1 + clones.len() as u64;
+
+        match result {
+            Ok(result) => {
+                let group = groups.entry((size, result)).or_default();
+                group.push(path);
+                group.extend(clones);
+                progress(ProgressInfo::Yield(done_files));
+            }
+            Err(error) => progress(ProgressInfo::Error(error)),
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, hash), mut paths)| {
+            paths.sort();
+            DuplicateGroup { hash, size, paths }
+        })
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.wasted_bytes()));
+
+    Ok(DuplicateReport { groups })
+}
+
+#[cfg(target_os = "linux")]
+fn shared_extents(path: &Path) -> Option<Vec<crate::extents::Extent>> {
+    crate::extents::shared_extents(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn shared_extents(_path: &Path) -> Option<Vec<(u64, u64, u64)>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DuplicateGroup, KeepPolicy};
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn find_duplicates() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir(dir.join("backup")).unwrap();
+        std::fs::write(dir.join("cat.png"), "meow").unwrap();
+        std::fs::write(dir.join("backup/cat.png"), "meow").unwrap();
+        std::fs::write(dir.join("dog.png"), "woof").unwrap(); // same size
+        std::fs::write(dir.join("a.txt"), "12345678").unwrap();
+        std::fs::write(dir.join("b.txt"), "12345678").unwrap();
+        std::fs::write(dir.join("c.txt"), "12345678").unwrap();
+
+        let report = super::find_duplicates(
+            dir,
+            &Blake2s256::new(),
+            &HashOptions::new(4),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].paths.len(), 3); // *.txt waste the most
+        assert_eq!(report.total_files(), 5);
+        assert_eq!(report.total_wasted_bytes(), 2 * 8 + 4);
+
+        let redundant = report.redundant(KeepPolicy::ShallowestPath);
+        assert_eq!(redundant.len(), 3);
+        assert!(redundant.contains(&dir.join("backup/cat.png").as_path()));
+
+        let with_extents = super::find_duplicates(
+            dir,
+            &Blake2s256::new(),
+            &HashOptions::new(4).shared_extents(true),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(with_extents, report);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            serde_json::from_str::<super::DuplicateReport>(&json).unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    fn keeper() {
+        let group = DuplicateGroup {
+            hash: String::from("aa"),
+            size: 10,
+            paths: vec![
+                PathBuf::from("a/b/c"),
+                PathBuf::from("a/d"),
+                PathBuf::from("e"),
+            ],
+        };
+
+        assert_eq!(group.wasted_bytes(), 20);
+        assert_eq!(group.keeper(KeepPolicy::FirstPath), Path::new("a/b/c"));
+        assert_eq!(group.keeper(KeepPolicy::ShallowestPath), Path::new("e"));
+        assert_eq!(
+            group.keeper_by(|path| std::cmp::Reverse(path.as_os_str().len())),
+            Path::new("a/b/c")
+        );
+        assert_eq!(
+            group.redundant(KeepPolicy::FirstPath),
+            vec![Path::new("a/d"), Path::new("e")]
+        );
+    }
+}
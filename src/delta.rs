@@ -0,0 +1,650 @@
+//! rsync-style signatures and deltas
+//!
+//! A signature holds a weak rolling checksum and a strong hash for each block of a file.
+//! It is enough to find which blocks of the file another side already has,
+//! so only the rest has to be transferred as a delta
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::{
+    cmp,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+};
+
+/// Rolling checksum from **rsync**
+///
+/// It can be moved along the data one byte at a time with [RollingChecksum::roll]
+///
+/// # Example
+///
+/// ```
+/// use file_hashing::delta::RollingChecksum;
+///
+/// let data = b"hello world";
+///
+/// let mut checksum = RollingChecksum::new(&data[0..5]);
+/// checksum.roll(data[0], data[5]);
+///
+/// assert_eq!(checksum.digest(), RollingChecksum::new(&data[1..6]).digest());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RollingChecksum {
+    a: u16,
+    b: u16,
+    len: usize,
+}
+
+impl RollingChecksum {
+    /// Get checksum of **block**
+    pub fn new(block: &[u8]) -> Self {
+        let mut a: u16 = 0;
+        let mut b: u16 = 0;
+
+        for (i, &byte) in block.iter().enumerate() {
+            a = a.wrapping_add(byte as u16);
+            b = b.wrapping_add(
+                ((block.len() - i) as u16).wrapping_mul(byte as u16),
+            );
+        }
+
+        Self {
+            a,
+            b,
+            len: block.len(),
+        }
+    }
+
+    /// Remove **old** from the start of the window and add **new** to the end
+    pub fn roll(&mut self, old: u8, new: u8) {
+        self.a = self.a.wrapping_sub(old as u16).wrapping_add(new as u16);
+        self.b = self
+            .b
+            .wrapping_sub((self.len as u16).wrapping_mul(old as u16))
+            .wrapping_add(self.a);
+    }
+
+    /// Checksum value
+    pub fn digest(&self) -> u32 {
+        (self.a as u32) | ((self.b as u32) << 16)
+    }
+}
+
+/// Checksums of one block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSignature {
+    /// [RollingChecksum] of the block
+    pub weak: u32,
+
+    /// Hash of the block
+    pub strong: String,
+}
+
+/// Signature of a whole file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Size of every block except the last one
+    pub block_size: usize,
+
+    /// Size of the file
+    pub file_size: u64,
+
+    pub blocks: Vec<BlockSignature>,
+}
+
+/// Generate signature of **file**
+///
+/// Every block is hashed with a copy of `hash`
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::delta::generate_signature;
+///
+/// let hash = Blake2s256::new();
+/// let signature =
+///     generate_signature("/home/gladi/backup.tar", &hash, 2048).unwrap();
+///
+/// println!("blocks: {}", signature.blocks.len());
+/// ```
+///
+/// # Error
+///
+/// * If the **block_size** is 0, the **IOErrorKind::InvalidInput** error will be returned
+pub fn generate_signature<HashType, P>(
+    path: P,
+    hash: &HashType,
+    block_size: usize,
+) -> Result<Signature, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    generate_signature_reader(File::open(path)?, hash, block_size)
+}
+
+/// Generate signature of **reader**
+///
+/// The same as [generate_signature], but for any [Read]
+pub fn generate_signature_reader<HashType, R>(
+    mut reader: R,
+    hash: &HashType,
+    block_size: usize,
+) -> Result<Signature, IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+{
+    if block_size == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let mut buf = vec![0u8; block_size];
+    let mut signature = Signature {
+        block_size,
+        file_size: 0,
+        blocks: Vec::new(),
+    };
+
+    loop {
+        let i = read_full(&mut reader, &mut buf)?;
+        if i == 0 {
+            return Ok(signature);
+        }
+
+        signature.file_size += i as u64;
+        signature.blocks.push(block_signature(&buf[0..i], hash));
+    }
+}
+
+/// One operation of a delta
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeltaOp {
+    /// Copy the block with this index from the old file
+    Copy(usize),
+
+    /// Write these bytes
+    Literal(Vec<u8>),
+}
+
+/// Compute delta of **reader** against a signature of the old file
+///
+/// `hash` must be the same algorithm that was used for the [Signature]
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::delta::{compute_delta, generate_signature, DeltaOp};
+/// use std::fs::File;
+///
+/// let hash = Blake2s256::new();
+/// let signature = generate_signature("/home/gladi/old.tar", &hash, 2048).unwrap();
+///
+/// let new_file = File::open("/home/gladi/new.tar").unwrap();
+/// let delta = compute_delta(new_file, &signature, &hash).unwrap();
+///
+/// let literal_bytes: usize = delta
+///     .iter()
+///     .map(|op| match op {
+///         DeltaOp::Literal(data) => data.len(),
+///         DeltaOp::Copy(_) => 0,
+///     })
+///     .sum();
+///
+/// println!("need to send {} bytes", literal_bytes);
+/// ```
+///
+/// # Error
+///
+/// * If the blocks of the signature do not cover its file size, the **IOErrorKind::InvalidData** error will be returned
+pub fn compute_delta<HashType, R>(
+    mut reader: R,
+    signature: &Signature,
+    hash: &HashType,
+) -> Result<Vec<DeltaOp>, IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+{
+    signature.check()?;
+    let block_size = signature.block_size;
+
+    let mut table: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, block) in signature.blocks.iter().enumerate() {
+        table.entry(block.weak).or_default().push(index);
+    }
+
+    let mut ops = Vec::new();
+    let mut literal = Vec::new();
+    let mut buf = Vec::new();
+    let mut read_buf = vec![0u8; cmp::max(PAGE_SIZE, block_size)];
+    let mut start = 0;
+    let mut eof = false;
+    let mut checksum: Option<RollingChecksum> = None;
+
+    loop {
+        if start >= read_buf.len() {
+            buf.drain(..start);
+            start = 0;
+        }
+
+        while !eof && buf.len() - start <= block_size {
+            let i = reader.read(&mut read_buf)?;
+            eof = i == 0;
+            buf.extend_from_slice(&read_buf[0..i]);
+        }
+
+        let end = cmp::min(start + block_size, buf.len());
+        if start == end {
+            break;
+        }
+
+        let window = &buf[start..end];
+        let weak = checksum.unwrap_or_else(|| RollingChecksum::new(window));
+
+        if let Some(indices) = table.get(&weak.digest()) {
+            let mut strong = hash.clone();
+            strong.update(window);
+            let strong = crate::encoding::get_lowerhex(&mut strong);
+
+            let found = indices.iter().find(|&&index| {
+                signature.blocks[index].strong == strong
+                    && signature.block_len(index) == window.len()
+            });
+
+            if let Some(&index) = found {
+                if !literal.is_empty() {
+                    ops.push(DeltaOp::Literal(std::mem::take(&mut literal)));
+                }
+
+                ops.push(DeltaOp::Copy(index));
+                start = end;
+                checksum = None;
+                continue;
+            }
+        }
+
+        literal.push(buf[start]);
+
+        checksum = if end < buf.len() {
+            let mut weak = weak;
+            weak.roll(buf[start], buf[end]);
+            Some(weak)
+        } else {
+            None
+        };
+
+        start += 1;
+    }
+
+    if !literal.is_empty() {
+        ops.push(DeltaOp::Literal(literal));
+    }
+
+    Ok(ops)
+}
+
+/// Rebuild the new file from the **old** one and a delta
+///
+/// # Error
+///
+/// * If the blocks of the signature do not cover its file size, the **IOErrorKind::InvalidData** error will be returned
+/// * If the delta refers to a block that is not in the signature, the **IOErrorKind::InvalidData** error will be returned
+pub fn apply_delta<R, W>(
+    mut old: R,
+    signature: &Signature,
+    delta: &[DeltaOp],
+    mut out: W,
+) -> Result<(), IOError>
+where
+    R: Read + Seek,
+    W: Write,
+{
+    signature.check()?;
+    let mut buf = vec![0u8; signature.block_size];
+
+    for op in delta {
+        match op {
+            DeltaOp::Literal(data) => out.write_all(data)?,
+            DeltaOp::Copy(index) => {
+                if *index >= signature.blocks.len() {
+                    return Err(IOError::from(IOErrorKind::InvalidData));
+                }
+
+                let len = signature.block_len(*index);
+                old.seek(SeekFrom::Start(
+                    (*index as u64) * signature.block_size as u64,
+                ))?;
+                old.read_exact(&mut buf[0..len])?;
+                out.write_all(&buf[0..len])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Byte ranges of **reader** whose blocks differ from a signature of the old file
+///
+/// Unlike [compute_delta] blocks are compared at the same offsets only, so an insertion shifts every later block.
+/// In exchange the data is read once without a rolling checksum, which is enough for files changed in place
+/// (disk images, databases, ...). Adjacent changed blocks are merged into one range.
+/// Ranges past the end of the new data were cut off, ranges past the end of the old file were appended
+///
+/// `hash` must be the same algorithm that was used for the [Signature]
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::delta::{changed_regions, generate_signature};
+/// use std::fs::File;
+///
+/// let hash = Blake2s256::new();
+/// let signature = generate_signature("/home/gladi/disk.img", &hash, 4096).unwrap();
+///
+/// // ... later
+/// let file = File::open("/home/gladi/disk.img").unwrap();
+/// for range in changed_regions(file, &signature, &hash).unwrap() {
+///     println!("changed {}..{}", range.start, range.end);
+/// }
+/// ```
+///
+/// # Error
+///
+/// * If the blocks of the signature do not cover its file size, the **IOErrorKind::InvalidData** error will be returned
+pub fn changed_regions<HashType, R>(
+    mut reader: R,
+    signature: &Signature,
+    hash: &HashType,
+) -> Result<Vec<Range<u64>>, IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+{
+    signature.check()?;
+    let block_size = signature.block_size;
+
+    let mut buf = vec![0u8; block_size];
+    let mut ranges = Vec::new();
+    let mut offset: u64 = 0;
+
+    for index in 0.. {
+        let i = read_full(&mut reader, &mut buf)?;
+        if i == 0 {
+            break;
+        }
+
+        let same = index < signature.blocks.len()
+            && signature.block_len(index) == i
+            && signature.blocks[index].strong
+                == block_signature(&buf[0..i], hash).strong;
+        if !same {
+            push_range(&mut ranges, offset..offset + i as u64);
+        }
+
+        offset += i as u64;
+    }
+
+    if offset < signature.file_size {
+        push_range(&mut ranges, offset..signature.file_size);
+    }
+
+    Ok(ranges)
+}
+
+/// The same as [changed_regions], but two local files are compared byte by byte in blocks of **block_size**
+///
+/// # Error
+///
+/// * If the **block_size** is 0, the **IOErrorKind::InvalidInput** error will be returned
+pub fn changed_regions_files<P, Q>(
+    old: P,
+    new: Q,
+    block_size: usize,
+) -> Result<Vec<Range<u64>>, IOError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    if block_size == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let mut old = File::open(old)?;
+    let mut new = File::open(new)?;
+    let mut old_buf = vec![0u8; block_size];
+    let mut new_buf = vec![0u8; block_size];
+    let mut ranges = Vec::new();
+    let mut offset: u64 = 0;
+
+    loop {
+        let old_len = read_full(&mut old, &mut old_buf)?;
+        let new_len = read_full(&mut new, &mut new_buf)?;
+        let len = cmp::max(old_len, new_len);
+        if len == 0 {
+            return Ok(ranges);
+        }
+
+        if old_buf[0..old_len] != new_buf[0..new_len] {
+            push_range(&mut ranges, offset..offset + len as u64);
+        }
+
+        offset += len as u64;
+    }
+}
+
+/// Add **range** to **ranges**, merged with the last one if they touch
+fn push_range(ranges: &mut Vec<Range<u64>>, range: Range<u64>) {
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+impl Signature {
+    /// Make sure the blocks cover **file_size** exactly, signatures may come from another peer
+    fn check(&self) -> Result<(), IOError> {
+        let valid = self.block_size > 0
+            && (self.blocks.len() as u64)
+                == self.file_size.div_ceil(self.block_size as u64);
+
+        match valid {
+            true => Ok(()),
+            false => Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!(
+                    "{} blocks of {} bytes do not cover {} bytes",
+                    self.blocks.len(),
+                    self.block_size,
+                    self.file_size
+                ),
+            )),
+        }
+    }
+
+    /// Length of the block with this index
+    fn block_len(&self, index: usize) -> usize {
+        let offset = (index as u64).saturating_mul(self.block_size as u64);
+        cmp::min(
+            self.block_size as u64,
+            self.file_size.saturating_sub(offset),
+        ) as usize
+    }
+}
+
+pub(crate) fn block_signature<HashType>(
+    block: &[u8],
+    hash: &HashType,
+) -> BlockSignature
+where
+    HashType: DynDigest + Clone,
+{
+    let mut strong = hash.clone();
+    strong.update(block);
+
+    BlockSignature {
+        weak: RollingChecksum::new(block).digest(),
+        strong: crate::encoding::get_lowerhex(&mut strong),
+    }
+}
+
+/// Read until **buf** is full or the end of **reader**
+pub(crate) fn read_full<R: Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> Result<usize, IOError> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(i) => filled += i,
+            Err(error) if error.kind() == IOErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeltaOp, RollingChecksum};
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+    use std::io::Cursor;
+
+    #[test]
+    fn rolling_checksum() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let mut checksum = RollingChecksum::new(&data[0..512]);
+
+        for i in 0..(data.len() - 512) {
+            checksum.roll(data[i], data[i + 512]);
+            assert_eq!(
+                checksum.digest(),
+                RollingChecksum::new(&data[i + 1..i + 513]).digest()
+            );
+        }
+    }
+
+    #[test]
+    fn generate_signature() {
+        let hash = Blake2s256::new();
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+
+        let signature =
+            super::generate_signature(&path, &hash, 1024).unwrap();
+        assert_eq!(signature.file_size, 10_000);
+        assert_eq!(signature.blocks.len(), 10);
+        assert_eq!(signature.blocks[0].strong.len(), 64); // Blake2s256 len == 64
+    }
+
+    #[test]
+    fn compute_and_apply_delta() {
+        let hash = Blake2s256::new();
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+        let old = std::fs::read(&path).unwrap();
+
+        let mut new = old.clone();
+        new.splice(5000..5000, b"inserted".iter().copied());
+        new.truncate(9000);
+
+        let signature =
+            super::generate_signature(&path, &hash, 1024).unwrap();
+        let delta =
+            super::compute_delta(&new[..], &signature, &hash).unwrap();
+
+        let copied = delta
+            .iter()
+            .filter(|op| matches!(op, DeltaOp::Copy(_)))
+            .count();
+        assert!(copied >= 7);
+
+        let mut rebuilt = Vec::new();
+        super::apply_delta(
+            Cursor::new(old),
+            &signature,
+            &delta,
+            &mut rebuilt,
+        )
+        .unwrap();
+        assert_eq!(rebuilt, new);
+    }
+
+    #[test]
+    fn changed_regions() {
+        let hash = Blake2s256::new();
+        let (temp_dir, path) = extra::generate_random_file(10_000);
+        let old = std::fs::read(&path).unwrap();
+        let signature =
+            super::generate_signature(&path, &hash, 1024).unwrap();
+
+        let mut new = old.clone();
+        new[100] ^= 1;
+        new[2000] ^= 1;
+        new[3100] ^= 1;
+        new.truncate(8000);
+
+        // Blocks 0 and 1 merge, block 3 alone, the cut-off tail from block 7
+        let expected = vec![0..2048, 3072..4096, 7168..10_000];
+        assert_eq!(
+            super::changed_regions(&new[..], &signature, &hash).unwrap(),
+            expected
+        );
+
+        let new_path = temp_dir.path().join("new");
+        std::fs::write(&new_path, &new).unwrap();
+        assert_eq!(
+            super::changed_regions_files(&path, &new_path, 1024).unwrap(),
+            expected
+        );
+        assert!(super::changed_regions_files(&path, &path, 1024)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn malformed_signature() {
+        let hash = Blake2s256::new();
+        let (_temp_dir, path) = extra::generate_random_file(3000);
+        let old = std::fs::read(&path).unwrap();
+        let signature =
+            super::generate_signature(&path, &hash, 1024).unwrap();
+
+        let mut extra_blocks = signature.clone();
+        extra_blocks.blocks.push(signature.blocks[0].clone());
+        let mut zero_size = signature.clone();
+        zero_size.block_size = 0;
+        let mut short = signature;
+        short.file_size = 1000;
+
+        for signature in [extra_blocks, zero_size, short] {
+            let invalid = |result: Result<(), std::io::Error>| {
+                assert_eq!(
+                    result.unwrap_err().kind(),
+                    std::io::ErrorKind::InvalidData
+                )
+            };
+
+            invalid(
+                super::compute_delta(&old[..], &signature, &hash).map(|_| ()),
+            );
+            invalid(
+                super::changed_regions(&old[..], &signature, &hash)
+                    .map(|_| ()),
+            );
+            invalid(super::apply_delta(
+                Cursor::new(&old),
+                &signature,
+                &[DeltaOp::Copy(3)],
+                Vec::new(),
+            ));
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Diagnostics of the hashing setup
+
+use super::*;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Number of files of the temporary data set
+const TEMP_FILES: usize = 32;
+
+/// Size of every file of the temporary data set
+const TEMP_FILE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Result of [measure_throughput]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput {
+    pub files: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Throughput in **MB/s** (10^6 bytes)
+    pub fn mb_per_sec(&self) -> f64 {
+        self.bytes_per_sec() / 1_000_000.0
+    }
+}
+
+impl std::fmt::Display for Throughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files, {} bytes in {:.3}s: {:.1} MB/s",
+            self.files,
+            self.bytes,
+            self.elapsed.as_secs_f64(),
+            self.mb_per_sec()
+        )
+    }
+}
+
+/// Result of [dry_run]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// Files that would be hashed
+    pub files: Vec<PathBuf>,
+
+    /// Total size of the files
+    pub bytes: u64,
+
+    /// Entries that could not be read and why, they would be missing from the run
+    pub errors: Vec<(PathBuf, IOErrorKind)>,
+}
+
+/// Traverse **folders** like [get_hash_folders](crate::get_hash_folders) does, but hash nothing
+///
+/// Shows which files a run would hash and how many bytes it would read
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::diagnostics::dry_run;
+///
+/// let estimate = dry_run(&["/home/gladi/Pictures", "/home/gladi/Music"]).unwrap();
+/// println!("{} files, {} bytes", estimate.files.len(), estimate.bytes);
+/// ```
+pub fn dry_run<P: AsRef<Path>>(dirs: &[P]) -> Result<Estimate, IOError> {
+    let mut estimate = Estimate::default();
+
+    for dir in dirs {
+        let mut walk = fs::get_all_file_with_metadata(dir);
+
+        for entry in walk.files {
+            estimate.bytes += entry.size();
+            estimate.files.push(entry.path);
+        }
+        estimate.errors.extend(
+            walk.errors
+                .drain(..)
+                .map(|(path, error)| (path, error.kind())),
+        );
+    }
+
+    Ok(estimate)
+}
+
+/// Measure how fast `hash` with **options** hashes **path** (file or folder)
+///
+/// If **path** is `None`, a temporary data set of 128 MiB is written, measured and removed.
+/// It has just been written, so it is most likely read from the page cache
+/// and shows the speed of hashing rather than of the disk
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{diagnostics::measure_throughput, HashOptions};
+///
+/// let hash = Blake2s256::new();
+///
+/// for num_threads in [1, 2, 4, 8] {
+///     let options = HashOptions::new(num_threads);
+///     let throughput =
+///         measure_throughput(Some("/home/gladi/Pictures"), &hash, &options)
+///             .unwrap();
+///
+///     println!("{} threads: {}", num_threads, throughput);
+/// }
+/// ```
+///
+/// # Error
+///
+/// * If files or folders cannot be read, the error of the first of them will be returned
+pub fn measure_throughput<HashType, P>(
+    path: Option<P>,
+    hash: &HashType,
+    options: &HashOptions,
+) -> Result<Throughput, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    match path {
+        Some(path) => {
+            let path = fs::resolve_root(path.as_ref(), options)?;
+            let mut walk = fs::walk(path, options);
+
+            if !walk.errors.is_empty() {
+                let (_, error) = walk.errors.swap_remove(0);
+                return Err(error);
+            }
+
+            measure_paths(&walk.paths(), hash, options)
+        }
+        None => {
+            let temp_dir = std::env::temp_dir().join(format!(
+                "file-hashing-throughput-{}",
+                std::process::id()
+            ));
+
+            let result = write_temp_data(&temp_dir)
+                .and_then(|paths| measure_paths(&paths, hash, options));
+
+            std::fs::remove_dir_all(&temp_dir)?;
+            result
+        }
+    }
+}
+
+fn measure_paths<HashType>(
+    paths: &[PathBuf],
+    hash: &HashType,
+    options: &HashOptions,
+) -> Result<Throughput, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+{
+    let mut bytes = 0;
+    for path in paths {
+        bytes += std::fs::metadata(path)?.len();
+    }
+
+    let start = Instant::now();
+    for result in file::get_hash_each_file(paths, hash, options) {
+        result?;
+    }
+
+    Ok(Throughput {
+        files: paths.len(),
+        bytes,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Write files of pseudo-random data, which is not compressible by the filesystem
+fn write_temp_data(dir: &Path) -> Result<Vec<PathBuf>, IOError> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut buf = vec![0u8; TEMP_FILE_SIZE];
+    let mut paths = Vec::with_capacity(TEMP_FILES);
+
+    for i in 0..TEMP_FILES {
+        for chunk in buf.chunks_mut(8) {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
+        }
+
+        let path = dir.join(format!("data_{}.bin", i));
+        std::fs::File::create(&path)?.write_all(&buf)?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::extra;
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+
+    #[test]
+    fn measure_throughput() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(10, 1024);
+
+        let throughput =
+            super::measure_throughput(Some(&temp_dir), &hash, &options)
+                .unwrap();
+        assert_eq!(throughput.files, 10);
+        assert_eq!(throughput.bytes, 10 * 1024);
+        assert!(throughput.mb_per_sec() > 0.0);
+
+        let root = temp_dir.path().join("root");
+        let upload = root.join("upload");
+        std::fs::create_dir_all(&upload).unwrap();
+        std::fs::write(root.join("a_secret"), "secret").unwrap();
+        std::fs::write(root.join("b_secret"), "secret").unwrap();
+        let error = super::measure_throughput(
+            Some(&root),
+            &hash,
+            &options.clone().jail(&upload),
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn dry_run() {
+        let (temp_dir1, _paths1) =
+            extra::generate_random_folder_with_files(10, 1024);
+        let (temp_dir2, _paths2) =
+            extra::generate_random_folder_with_files(5, 100);
+
+        let estimate =
+            super::dry_run(&[temp_dir1.path(), temp_dir2.path()]).unwrap();
+        assert_eq!(estimate.files.len(), 15);
+        assert_eq!(estimate.bytes, 10 * 1024 + 5 * 100);
+        assert!(estimate.errors.is_empty());
+
+        let missing = temp_dir1.path().join("missing");
+        let estimate = super::dry_run(&[temp_dir1.path(), &missing]).unwrap();
+        assert_eq!(estimate.files.len(), 10);
+        assert_eq!(
+            estimate.errors,
+            vec![(missing, std::io::ErrorKind::NotFound)]
+        );
+        assert_eq!(estimate.clone(), estimate);
+    }
+}
@@ -2,9 +2,238 @@
 
 use digest::DynDigest;
 
+/// Text representation of a hash
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// **hex lower**, used by default everywhere
+    #[default]
+    HexLower,
+
+    /// **HEX UPPER**
+    HexUpper,
+
+    /// Standard **base64** with padding
+    Base64,
+}
+
+impl Encoding {
+    /// Encode raw bytes
+    pub fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Encoding::HexLower => data_encoding::HEXLOWER.encode(data),
+            Encoding::HexUpper => data_encoding::HEXUPPER.encode(data),
+            Encoding::Base64 => data_encoding::BASE64.encode(data),
+        }
+    }
+}
+
+/// [Multibase](https://github.com/multiformats/multibase): the text of a hash prefixed with the code of its base
+///
+/// For content-addressed systems, e.g. in the IPFS ecosystem
+///
+/// # Example
+///
+/// ```
+/// use file_hashing::encoding::Multibase;
+///
+/// assert_eq!(Multibase::Base32.encode(b"yes mani !"), "bpfsxgidnmfxgsibb");
+/// assert_eq!(Multibase::Base58Btc.encode(b"yes mani !"), "z7paNL19xttacUY");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Multibase {
+    /// `f`, **hex lower**
+    Base16,
+
+    /// `F`, **HEX UPPER**
+    Base16Upper,
+
+    /// `b`, lowercase RFC 4648 base32 without padding, the default of CIDv1
+    #[default]
+    Base32,
+
+    /// `B`, uppercase RFC 4648 base32 without padding
+    Base32Upper,
+
+    /// `z`, base58 with the Bitcoin alphabet
+    Base58Btc,
+
+    /// `m`, standard base64 without padding
+    Base64,
+
+    /// `u`, URL-safe base64 without padding
+    Base64Url,
+}
+
+impl Multibase {
+    /// Prefix of the base
+    pub fn code(&self) -> char {
+        match self {
+            Multibase::Base16 => 'f',
+            Multibase::Base16Upper => 'F',
+            Multibase::Base32 => 'b',
+            Multibase::Base32Upper => 'B',
+            Multibase::Base58Btc => 'z',
+            Multibase::Base64 => 'm',
+            Multibase::Base64Url => 'u',
+        }
+    }
+
+    /// Encode raw bytes with the prefix
+    pub fn encode(&self, data: &[u8]) -> String {
+        let text = match self {
+            Multibase::Base16 => data_encoding::HEXLOWER.encode(data),
+            Multibase::Base16Upper => data_encoding::HEXUPPER.encode(data),
+            Multibase::Base32 => data_encoding::BASE32_NOPAD
+                .encode(data)
+                .to_ascii_lowercase(),
+            Multibase::Base32Upper => {
+                data_encoding::BASE32_NOPAD.encode(data)
+            }
+            Multibase::Base58Btc => base58btc(data),
+            Multibase::Base64 => data_encoding::BASE64_NOPAD.encode(data),
+            Multibase::Base64Url => {
+                data_encoding::BASE64URL_NOPAD.encode(data)
+            }
+        };
+
+        format!("{}{}", self.code(), text)
+    }
+}
+
+impl Encoder for Multibase {
+    fn encode(&self, digest: &[u8]) -> String {
+        Multibase::encode(self, digest)
+    }
+}
+
+fn base58btc(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 58] =
+        b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    // Little-endian digits in base 58
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 138 / 100 + 1);
+    for &byte in data {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = data.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&digit| ALPHABET[digit as usize] as char),
+        )
+        .collect()
+}
+
+/// Text representation of a finished digest
+///
+/// Set it for a run with [HashOptions::encoder](crate::HashOptions::encoder),
+/// or format a single hash with [FileHash::encode](crate::hash::FileHash::encode)
+///
+/// # Example
+///
+/// ```
+/// use file_hashing::encoding::Encoder;
+///
+/// /// The first 8 bytes as **hex lower**, like `git log --abbrev`
+/// struct Short;
+///
+/// impl Encoder for Short {
+///     fn encode(&self, digest: &[u8]) -> String {
+///         data_encoding::HEXLOWER.encode(&digest[..digest.len().min(8)])
+///     }
+/// }
+///
+/// assert_eq!(Short.encode(&[0xab; 32]), "abababababababab");
+/// ```
+pub trait Encoder: Send + Sync {
+    fn encode(&self, digest: &[u8]) -> String;
+}
+
+impl Encoder for Encoding {
+    fn encode(&self, digest: &[u8]) -> String {
+        Encoding::encode(self, digest)
+    }
+}
+
 /// Convert hash to readable **hex lower**
-pub(crate) fn get_lowerhex<HashType: DynDigest + Clone>(
+///
+/// `hash` itself is not finalized
+pub fn get_lowerhex<HashType: DynDigest + Clone>(
+    hash: &mut HashType,
+) -> String {
+    get_encoded(hash, Encoding::HexLower)
+}
+
+/// Convert hash to text with the given **encoding**
+///
+/// `hash` itself is not finalized
+pub fn get_encoded<HashType: DynDigest + Clone>(
+    hash: &mut HashType,
+    encoding: Encoding,
+) -> String {
+    get_encoded_with(hash, &encoding)
+}
+
+/// Convert hash to text with a custom **encoder**
+///
+/// `hash` itself is not finalized
+pub fn get_encoded_with<HashType: DynDigest + Clone>(
     hash: &mut HashType,
+    encoder: &dyn Encoder,
 ) -> String {
-    data_encoding::HEXLOWER.encode(Box::new(hash.clone()).finalize().as_ref())
+    encoder.encode(Box::new(hash.clone()).finalize().as_ref())
+}
+
+/// The same as [get_lowerhex], but for an algorithm chosen at runtime
+pub fn get_lowerhex_dyn(hash: &dyn DynDigest) -> String {
+    get_encoded_dyn(hash, Encoding::HexLower)
+}
+
+/// The same as [get_encoded], but for an algorithm chosen at runtime
+pub fn get_encoded_dyn(hash: &dyn DynDigest, encoding: Encoding) -> String {
+    encoded(hash, &encoding)
+}
+
+pub(crate) fn encoded<HashType: DynDigest + ?Sized>(
+    hash: &HashType,
+    encoder: &dyn Encoder,
+) -> String {
+    encoder.encode(hash.box_clone().finalize().as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Multibase;
+
+    #[test]
+    fn multibase() {
+        // https://github.com/multiformats/multibase/blob/master/tests/basic.csv
+        let data = b"yes mani !";
+
+        assert_eq!(Multibase::Base16.encode(data), "f796573206d616e692021");
+        assert_eq!(
+            Multibase::Base16Upper.encode(data),
+            "F796573206D616E692021"
+        );
+        assert_eq!(Multibase::Base32.encode(data), "bpfsxgidnmfxgsibb");
+        assert_eq!(Multibase::Base32Upper.encode(data), "BPFSXGIDNMFXGSIBB");
+        assert_eq!(Multibase::Base58Btc.encode(data), "z7paNL19xttacUY");
+        assert_eq!(Multibase::Base64.encode(data), "meWVzIG1hbmkgIQ");
+        assert_eq!(Multibase::Base64Url.encode(data), "ueWVzIG1hbmkgIQ");
+
+        assert_eq!(Multibase::Base58Btc.encode(&[0, 0, 1]), "z112");
+        assert_eq!(Multibase::Base58Btc.encode(&[]), "z");
+    }
 }
@@ -0,0 +1,124 @@
+//! Physical extents of files (`FS_IOC_FIEMAP`)
+//!
+//! Reflinked copies on copy-on-write filesystems (btrfs, XFS, ...) share their extents,
+//! so files with the same extent layout have the same content without reading them.
+//! Only **Linux** is supported
+
+use crate::IOError;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+/// `_IOWR('f', 11, struct fiemap)`
+const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+const FIEMAP_EXTENT_LAST: u32 = 0x1;
+const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+/// Extents without a stable physical location: unknown, delayed allocation,
+/// encoded, unaligned, inline or tail-packed
+const FIEMAP_EXTENT_UNSTABLE: u32 = 0x2 | 0x4 | 0x8 | 0x100 | 0x200 | 0x400;
+
+/// Extents requested per ioctl
+const BATCH: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+}
+
+#[repr(C)]
+struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extents: [FiemapExtent; BATCH],
+}
+
+/// Extent of a file: logical offset, physical offset and length in bytes
+pub(crate) type Extent = (u64, u64, u64);
+
+/// Extents of **path** if all of them are shared with another file and have a stable physical location
+///
+/// `None` if the file is not (completely) reflinked, is empty or the filesystem does not support `FIEMAP`
+pub(crate) fn shared_extents(path: &Path) -> Option<Vec<Extent>> {
+    let extents = get_extents(&File::open(path).ok()?).ok()?;
+
+    let shared = !extents.is_empty()
+        && extents.iter().all(|extent| {
+            extent.flags & FIEMAP_EXTENT_SHARED != 0
+                && extent.flags & FIEMAP_EXTENT_UNSTABLE == 0
+        });
+
+    shared.then(|| {
+        extents
+            .iter()
+            .map(|extent| (extent.logical, extent.physical, extent.length))
+            .collect()
+    })
+}
+
+fn get_extents(file: &File) -> Result<Vec<FiemapExtent>, IOError> {
+    let mut extents = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let mut fiemap = Fiemap {
+            start,
+            length: u64::MAX - start,
+            flags: FIEMAP_FLAG_SYNC,
+            mapped_extents: 0,
+            extent_count: BATCH as u32,
+            reserved: 0,
+            extents: [FiemapExtent::default(); BATCH],
+        };
+
+        // SAFETY: `fiemap` has room for `extent_count` extents and outlives the call
+        if unsafe {
+            libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap)
+        } == -1
+        {
+            return Err(IOError::last_os_error());
+        }
+
+        let mapped = &fiemap.extents[..fiemap.mapped_extents as usize];
+        extents.extend_from_slice(mapped);
+
+        match mapped.last() {
+            Some(last) if last.flags & FIEMAP_EXTENT_LAST == 0 => {
+                start = last.logical + last.length;
+            }
+            _ => return Ok(extents),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::extra;
+
+    #[test]
+    fn get_extents() {
+        let (_temp_dir, path) = extra::generate_random_file(64 * 1024);
+        let file = std::fs::File::open(&path).unwrap();
+
+        // tmpfs and some other filesystems don't support FIEMAP
+        if let Ok(extents) = super::get_extents(&file) {
+            let mapped: u64 =
+                extents.iter().map(|extent| extent.length).sum();
+            assert!(extents.is_empty() || mapped >= 64 * 1024);
+        }
+
+        // A fresh file shares nothing
+        assert_eq!(super::shared_extents(&path), None);
+    }
+}
@@ -1,10 +1,20 @@
 //! File functions
 
-use super::{DynDigest, IOError, IOErrorKind, ProgressInfo, PAGE_SIZE};
-use std::{fs::File, io::Read, path::Path};
+use super::{DynDigest, HashOptions, IOError, IOErrorKind, ProgressInfo};
+use crate::encoding::Encoding;
+use crate::stats::Stats;
+use std::collections::HashMap;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 /// Get hash from **file**
 ///
+/// **hash** is not changed, the file is hashed with a copy of it.
+/// Data already fed to **hash** (e.g. a salt) is therefore hashed before the file, every time
+///
 /// # Example
 ///
 /// ```no_run
@@ -14,30 +24,106 @@ use std::{fs::File, io::Read, path::Path};
 ///
 /// let path = PathBuf::from("/home/gladi/test-hashing.txt");
 ///
-/// let mut hash = Blake2s256::new();
-/// let result = get_hash_file(&path, &mut hash).unwrap();
+/// let hash = Blake2s256::new();
+/// let result = get_hash_file(&path, &hash).unwrap();
 ///
 /// assert_eq!(result.len(), 64); // Blake2s256 len == 64
 /// ```
 pub fn get_hash_file<HashType, P>(
     path: P,
-    hash: &mut HashType,
+    hash: &HashType,
 ) -> Result<String, IOError>
 where
     HashType: DynDigest + Clone,
     P: AsRef<Path>,
 {
-    let mut file = File::open(path)?;
-    let mut buf = [0u8; PAGE_SIZE];
+    let (_, result) =
+        crate::io::hash_copy(File::open(path)?, &mut hash.clone())?;
+    Ok(result)
+}
 
-    loop {
-        let i = file.read(&mut buf)?;
-        hash.update(&buf[0..i]);
+/// Get hash from an already open **file**
+///
+/// The file is read from its current position to the end. Use it when the file was opened with custom flags,
+/// or to hash exactly the file that was checked before, without opening the path again.
+/// **hash** is not changed, see [get_hash_file]
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::file::get_hash_open_file;
+///
+/// let file = std::fs::File::open("/home/gladi/upload.bin").unwrap();
+/// assert!(file.metadata().unwrap().is_file());
+///
+/// let hash = Blake2s256::new();
+/// let result = get_hash_open_file(&file, &hash).unwrap();
+///
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub fn get_hash_open_file<HashType>(
+    file: &File,
+    hash: &HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    let (_, result) = crate::io::hash_copy(file, &mut hash.clone())?;
+    Ok(result)
+}
 
-        if i == 0 {
-            return Ok(crate::encoding::get_lowerhex(hash));
-        }
-    }
+/// Get hash from an open file descriptor, see [get_hash_open_file]
+///
+/// The descriptor is not closed
+#[cfg(unix)]
+pub fn get_hash_fd<HashType>(
+    fd: std::os::fd::BorrowedFd<'_>,
+    hash: &HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    get_hash_open_file(&File::from(fd.try_clone_to_owned()?), hash)
+}
+
+/// Get hash from an open file handle, see [get_hash_open_file]
+///
+/// The handle is not closed
+#[cfg(windows)]
+pub fn get_hash_handle<HashType>(
+    handle: std::os::windows::io::BorrowedHandle<'_>,
+    hash: &HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    get_hash_open_file(&File::from(handle.try_clone_to_owned()?), hash)
+}
+
+/// Get hash from **data** in memory
+///
+/// The result is formatted exactly like [get_hash_file] does it, so in-memory and on-disk hashes can be compared directly.
+/// **hash** is not changed
+///
+/// # Example
+///
+/// ```
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::get_hash_bytes;
+///
+/// let hash = Blake2s256::new();
+/// let result = get_hash_bytes(b"hello world", &hash);
+///
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub fn get_hash_bytes<HashType>(data: &[u8], hash: &HashType) -> String
+where
+    HashType: DynDigest + Clone,
+{
+    let mut hash = hash.clone();
+    hash.update(data);
+    crate::encoding::get_lowerhex(&mut hash)
 }
 
 /// Get hash from **files**
@@ -48,6 +134,12 @@ where
 ///
 /// If you can get all files from a folder with this [function](fs::get_all_file_from_folder)
 ///
+/// # Format
+///
+/// **hash** is not changed. Every file is hashed with its own copy of **hash**, like [get_hash_file] does it,
+/// and the result is the hash (again from a copy of **hash**) of the lowercase hex hashes of all files in the order of **paths**.
+/// With a fresh **hash** it is the same as [get_hash_files_with_factory] with default options
+///
 /// # Example
 ///
 /// ```no_run
@@ -65,12 +157,13 @@ where
 ///     }
 /// }
 ///
-/// let mut hash = Blake2s256::new();
-/// let result = get_hash_files(&paths, &mut hash, 4, |info| match info {
+/// let hash = Blake2s256::new();
+/// let result = get_hash_files(&paths, &hash, 4, |info| match info {
 ///     ProgressInfo::Yield(done_files) => {
 ///         println!("done files {}/{}", done_files, paths.len())
 ///     }
 ///     ProgressInfo::Error(error) => println!("error: {}", error),
+///     _ => {}
 /// })
 /// .unwrap();
 ///
@@ -82,49 +175,626 @@ where
 ///
 /// * if the **path** variable is empty, the error **IOErrorKind::InvalidInput** will be returned
 pub fn get_hash_files<HashType, P>(
-    paths: &Vec<P>,
-    hash: &mut HashType,
+    paths: &[P],
+    hash: &HashType,
     num_threads: usize,
     progress: impl Fn(ProgressInfo),
 ) -> Result<String, IOError>
 where
-    HashType: DynDigest + Clone + std::marker::Send,
+    HashType: DynDigest + Clone + std::marker::Send + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    hash_files(paths, hash, num_threads, progress)
+}
+
+/// The same as [get_hash_files], but for any iterator of paths
+///
+/// Paths are hashed as they come, so slices, iterators and streaming sources don't have to be collected into a `Vec` first
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::file::get_hash_files_iter;
+///
+/// let paths = std::io::stdin().lines().map(|line| line.unwrap());
+///
+/// let hash = Blake2s256::new();
+/// let result = get_hash_files_iter(paths, &hash, 4, |_| {}).unwrap();
+/// println!("{}", result);
+/// ```
+///
+/// # Error
+///
+/// * if there are no paths, the error **IOErrorKind::InvalidInput** will be returned
+pub fn get_hash_files_iter<HashType, I>(
+    paths: I,
+    hash: &HashType,
+    num_threads: usize,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send + std::marker::Sync,
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    hash_files(paths, hash, num_threads, progress)
+}
+
+/// The same as [get_hash_file], but for an algorithm chosen at runtime
+///
+/// # Example
+///
+/// ```no_run
+/// use digest::DynDigest;
+/// use file_hashing::file::get_hash_file_dyn;
+///
+/// let hash: Box<dyn DynDigest> = match std::env::args().nth(1).as_deref() {
+///     Some("sha256") => Box::new(sha2::Sha256::default()),
+///     _ => Box::new(blake2::Blake2s256::default()),
+/// };
+///
+/// let result = get_hash_file_dyn("/home/gladi/test-hashing.txt", hash.as_ref()).unwrap();
+/// println!("{}", result);
+/// ```
+pub fn get_hash_file_dyn<P: AsRef<Path>>(
+    path: P,
+    hash: &dyn DynDigest,
+) -> Result<String, IOError> {
+    hash_file(path.as_ref(), hash)
+}
+
+/// The same as [get_hash_files], but for an algorithm chosen at runtime
+pub fn get_hash_files_dyn<P>(
+    paths: &[P],
+    hash: &(dyn DynDigest + std::marker::Send + std::marker::Sync),
+    num_threads: usize,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    P: AsRef<Path> + std::marker::Sync,
+{
+    hash_files(paths, hash, num_threads, progress)
+}
+
+/// Hash **path** with a copy of **hash**
+fn hash_file<HashType>(
+    path: &Path,
+    hash: &HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + ?Sized,
+{
+    let mut hash = hash.box_clone();
+    crate::io::hash_update(File::open(path)?, hash.as_mut())?;
+    Ok(crate::encoding::encoded(hash.as_ref(), &Encoding::HexLower))
+}
+
+fn hash_files<HashType, I>(
+    paths: I,
+    hash: &HashType,
+    num_threads: usize,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + std::marker::Sync + ?Sized,
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let pool = crate::pool::Pool::ambient(num_threads);
+    let mut combined = hash.box_clone();
+    let mut done_files = 0;
+
+    for path in paths {
+        let path = path.as_ref();
+        done_files += 1;
+
+        match pool.install(|| hash_file(path, hash)) {
+            Ok(file_hash) => {
+                combined.update(file_hash.as_bytes());
+                progress(ProgressInfo::Yield(done_files));
+            }
+            Err(error) => progress(ProgressInfo::Error(error)),
+        }
+    }
+
+    if done_files == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    Ok(crate::encoding::encoded(
+        combined.as_ref(),
+        &Encoding::HexLower,
+    ))
+}
+
+/// Get hash from **files**, every file is hashed with its own digest from **new_hash**
+///
+/// Unlike [get_hash_files] no hasher is shared between files, so they are really hashed in parallel.
+/// The result is the hash (again from **new_hash**) of the hashes of all files in the order of **paths**
+///
+/// # Format
+///
+/// For every file that could be read, in order, the combining digest is updated with:
+///
+/// * the hash of the file encoded like the result (lowercase hex by default) as UTF-8
+/// * with [HashOptions::include_sizes], the number of hashed bytes as `u64` little-endian
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{file::get_hash_files_with_factory, fs, HashOptions};
+///
+/// let paths = fs::get_all_file_from_folder("/home/gladi/Pictures");
+/// let result =
+///     get_hash_files_with_factory(&paths, Sha256::new, &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// assert_eq!(result.len(), 64); // Sha256 len == 64
+/// ```
+///
+/// # Error
+///
+/// * If the **paths** is empty, the **IOErrorKind::InvalidInput** error will be returned
+/// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are left out
+/// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+pub fn get_hash_files_with_factory<HashType, F, P>(
+    paths: &[P],
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    let progress = options.broadcast(progress);
+    combine_with_factory(paths, &new_hash, options, progress)
+}
+
+/// The same as [get_hash_files_with_factory], but [Stats] of the run are returned alongside the hash
+///
+/// # Error
+///
+/// The same as [get_hash_files_with_factory]
+pub fn get_hash_files_with_stats<HashType, F, P>(
+    paths: &[P],
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<(String, Stats), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    let progress = options.broadcast(progress);
+    combine_with_stats(
+        paths,
+        &new_hash,
+        options,
+        progress,
+        Stats::default(),
+        Instant::now(),
+        |_, _, _| {},
+    )
+}
+
+/// [get_hash_files_with_factory] without broadcasting **progress**, the caller does that
+pub(crate) fn combine_with_factory<HashType, F, P>(
+    paths: &[P],
+    new_hash: &F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    let stats = Stats::default();
+    combine_with_stats(
+        paths,
+        new_hash,
+        options,
+        progress,
+        stats,
+        Instant::now(),
+        |_, _, _| {},
+    )
+    .map(|(result, _)| result)
+}
+
+/// [combine_with_factory] that adds to **stats** of a run started at **started**
+///
+/// **on_included** gets the index in **paths**, the hash and the hashed bytes of every file that went into the hash
+pub(crate) fn combine_with_stats<HashType, F, P>(
+    paths: &[P],
+    new_hash: &F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+    stats: Stats,
+    started: Instant,
+    mut on_included: impl FnMut(usize, &str, u64),
+) -> Result<(String, Stats), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
     P: AsRef<Path> + std::marker::Sync,
 {
     if paths.is_empty() {
         return Err(IOError::from(IOErrorKind::InvalidInput));
     }
 
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(num_threads)
-        .build()
-        .unwrap();
-    let mut jobs = Vec::with_capacity(paths.len());
+    let results = get_hash_each_file_counted(paths, new_hash, options);
+    options.check_cancelled()?;
+
+    let mut combiner = Combiner::new(new_hash(), stats);
+    combiner.add(results, options, &progress, &mut on_included);
 
-    for path in paths.iter() {
-        jobs.push(pool.install(|| -> Result<(), std::io::Error> {
-            let file_hash = get_hash_file(path, hash)?;
-            hash.update(file_hash.as_bytes());
-            Ok(())
-        }));
+    Ok(combiner.finish(options, started))
+}
+
+/// Combined hash of files whose results are added in path order, batch by batch
+pub(crate) struct Combiner<HashType> {
+    hash: HashType,
+    pub(crate) stats: Stats,
+    done_files: u64,
+}
+
+impl<HashType: DynDigest> Combiner<HashType> {
+    pub(crate) fn new(hash: HashType, stats: Stats) -> Self {
+        Self {
+            hash,
+            stats,
+            done_files: 0,
+        }
     }
 
-    let mut done_files = 0;
-    for job in jobs.into_iter() {
-        done_files += 1;
+    /// Add **results** of [get_hash_each_file_counted],
+    /// **on_included** gets the index in **results**, the hash and the hashed bytes of every file that went into the hash
+    pub(crate) fn add(
+        &mut self,
+        results: Vec<Result<(String, u64), IOError>>,
+        options: &HashOptions,
+        progress: &impl Fn(ProgressInfo),
+        mut on_included: impl FnMut(usize, &str, u64),
+    ) {
+        for (index, result) in results.into_iter().enumerate() {
+            self.done_files += 1;
 
-        match job {
-            Err(error) => progress(ProgressInfo::Error(error)),
-            Ok(_) => progress(ProgressInfo::Yield(done_files)),
+            match result {
+                Ok((file_hash, bytes)) => {
+                    self.hash.update(file_hash.as_bytes());
+                    if options.include_sizes {
+                        self.hash.update(&bytes.to_le_bytes());
+                    }
+                    self.stats.files += 1;
+                    self.stats.bytes += bytes;
+                    on_included(index, &file_hash, bytes);
+                    progress(ProgressInfo::Yield(self.done_files));
+                }
+                Err(error) => {
+                    self.stats.errors += 1;
+                    progress(ProgressInfo::Error(error));
+                }
+            }
         }
     }
 
-    Ok(crate::encoding::get_lowerhex(hash))
+    /// Number of added results, including the failed ones
+    pub(crate) fn len(&self) -> u64 {
+        self.done_files
+    }
+
+    pub(crate) fn finish(
+        mut self,
+        options: &HashOptions,
+        started: Instant,
+    ) -> (String, Stats) {
+        self.stats.elapsed = started.elapsed();
+        (options.encode(&self.hash), self.stats)
+    }
+}
+
+/// Hash files whose paths arrive over **paths** while they arrive
+///
+/// Every worker of the pool of the run (**options.num_threads** or [HashOptions::thread_pool]) takes paths
+/// from the channel and is busy until the senders are dropped, each file gets a copy of `hash`.
+/// Every result is passed to **on_result** from the worker that produced it, in no particular order.
+/// Returns the number of received paths once all senders are dropped and every file is hashed
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{file::get_hash_files_from_channel, HashOptions};
+/// use std::path::PathBuf;
+///
+/// let (sender, receiver) = std::sync::mpsc::sync_channel::<PathBuf>(1024);
+///
+/// std::thread::spawn(move || {
+///     // e.g. rows of a database query
+///     for id in 0..1000 {
+///         sender.send(PathBuf::from(format!("/srv/uploads/{}", id))).unwrap();
+///     }
+/// });
+///
+/// get_hash_files_from_channel(receiver, &Blake2s256::new(), &HashOptions::new(8), |path, result| {
+///     println!("{}: {:?}", path.display(), result)
+/// })
+/// .unwrap();
+/// ```
+///
+/// # Error
+///
+/// * If the run is cancelled, the workers stop taking paths and the **IOErrorKind::Interrupted** error will be returned
+pub fn get_hash_files_from_channel<HashType>(
+    paths: std::sync::mpsc::Receiver<PathBuf>,
+    hash: &HashType,
+    options: &HashOptions,
+    on_result: impl Fn(PathBuf, Result<String, IOError>) + std::marker::Sync,
+) -> Result<u64, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+{
+    let prototype = std::sync::Mutex::new(hash.clone());
+    let paths = std::sync::Mutex::new(paths);
+    let received = std::sync::atomic::AtomicU64::new(0);
+
+    let pool = options.pool();
+    let workers = match options.deterministic {
+        true => 1,
+        false => pool.num_threads(),
+    };
+    pool.run_workers(workers, || {
+        // Every worker clones its own copy once, not every file under the lock
+        let local = prototype.lock().unwrap().clone();
+
+        while let Some(path) = receive(&paths, options) {
+            received.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+            let result = hash_file_with(&path, &local, options)
+                .map(|(result, _)| result);
+            on_result(path, result);
+        }
+    });
+
+    options.check_cancelled()?;
+    Ok(received.into_inner())
+}
+
+/// Next path of **paths**, `None` once all senders are dropped or the run is cancelled
+///
+/// The receiver is polled, so a cancelled run doesn't wait for a sender that never sends.
+/// The lock is released before hashing
+fn receive(
+    paths: &std::sync::Mutex<std::sync::mpsc::Receiver<PathBuf>>,
+    options: &HashOptions,
+) -> Option<PathBuf> {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    /// Longest wait before cancellation is checked again
+    const POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
+    while !options.is_cancelled() {
+        match paths.lock().unwrap().recv_timeout(POLL) {
+            Ok(path) => return Some(path),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+
+    None
+}
+
+/// Get hash of **each file** separately
+///
+/// Every file gets a copy of `hash`, so the results do not depend on each other.
+/// Files under a limited root (see [HashOptions::root_threads]) get their own thread pool.
+/// Files left after cancellation get the **IOErrorKind::Interrupted** error
+pub(crate) fn get_hash_each_file<HashType, P>(
+    paths: &[P],
+    hash: &HashType,
+    options: &HashOptions,
+) -> Vec<Result<String, IOError>>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    // Every worker gets its own copy, so cloning for each file takes no lock
+    hash_each(
+        paths,
+        hash.clone(),
+        &|prototype: &mut HashType| prototype.clone(),
+        options,
+    )
+    .into_iter()
+    .map(|result| result.map(|(hash, _)| hash))
+    .collect()
+}
+
+/// The same as [get_hash_each_file], but every file gets a digest from **new_hash** and the bytes read from it are returned as well
+pub(crate) fn get_hash_each_file_counted<HashType, F, P>(
+    paths: &[P],
+    new_hash: &F,
+    options: &HashOptions,
+) -> Vec<Result<(String, u64), IOError>>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    hash_each(paths, (), &|_: &mut ()| new_hash(), options)
+}
+
+/// Hash every file of **paths** with a digest from **new_hash**,
+/// which gets the copy of **init** of the worker that hashes the file
+fn hash_each<HashType, S, F, P>(
+    paths: &[P],
+    init: S,
+    new_hash: &F,
+    options: &HashOptions,
+) -> Vec<Result<(String, u64), IOError>>
+where
+    HashType: DynDigest + Clone,
+    S: Clone + std::marker::Send,
+    F: Fn(&mut S) -> HashType + std::marker::Sync,
+    P: AsRef<Path> + std::marker::Sync,
+{
+    let network_mounts = match options.network_threads {
+        Some(_) => crate::fs::get_network_mounts(),
+        None => Vec::new(),
+    };
+
+    let mut groups: HashMap<Option<PathBuf>, (usize, Vec<usize>)> =
+        HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let limit = match options.single_thread {
+            true => None,
+            false => options.limit_for(path.as_ref(), &network_mounts),
+        };
+        let (root, num_threads) = match limit {
+            Some((root, num_threads)) => (Some(root), num_threads),
+            None => (None, options.num_threads),
+        };
+
+        groups
+            .entry(root)
+            .or_insert((num_threads, Vec::new()))
+            .1
+            .push(index);
+    }
+
+    let mut results: Vec<Option<Result<(String, u64), IOError>>> =
+        paths.iter().map(|_| None).collect();
+
+    let groups = groups
+        .into_iter()
+        .map(|group| (group, init.clone()))
+        .collect();
+    let jobs = crate::pool::run_each(
+        groups,
+        |((root, (num_threads, indexes)), init)| {
+            // Roots get their own pools, so roots with the same limit don't share workers
+            let pool = match root {
+                Some(_) => crate::pool::Pool::new(num_threads, options.numa),
+                None => options.pool(),
+            };
+
+            pool.map_with(indexes, init, |init, index| {
+                if let Err(error) = options.check_cancelled() {
+                    return (index, Err(error));
+                }
+
+                let result =
+                    hash_file_with(&paths[index], &new_hash(init), options);
+
+                (index, result)
+            })
+        },
+    );
+
+    for (index, result) in jobs.into_iter().flatten() {
+        results[index] = Some(result);
+    }
+
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+/// Hash **file** with the settings of **options**, the bytes read are returned alongside the hash
+///
+/// Every attempt hashes with its own copy of **hash**
+pub(crate) fn hash_file_with<HashType, P>(
+    path: P,
+    hash: &HashType,
+    options: &HashOptions,
+) -> Result<(String, u64), IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut buf = crate::io::AlignedBuf::new(
+        crate::PAGE_SIZE,
+        options.buffer_alignment,
+    )?;
+
+    let retries = match options.detect_changes {
+        Some(retries) => retries,
+        None => {
+            let mut hash = hash.clone();
+            let file = crate::fs::open_file(path, options)?;
+            let bytes = crate::io::hash_tee_with(
+                file,
+                &mut hash,
+                std::io::sink(),
+                &mut buf,
+            )?;
+
+            return Ok((options.encode(&hash), bytes));
+        }
+    };
+
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            options.notify(ProgressInfo::Retried {
+                path: path.to_path_buf(),
+                attempt,
+            });
+        }
+
+        let mut hash = hash.clone();
+        let mut file = crate::fs::open_file(path, options)?;
+        let before = file.metadata()?;
+        let bytes = crate::io::hash_tee_with(
+            &mut file,
+            &mut hash,
+            std::io::sink(),
+            &mut buf,
+        )?;
+
+        #[cfg(test)]
+        tests::change_during_hash(path);
+
+        // Sizes of procfs and sysfs files don't match their content, so only the metadata is compared
+        if same_state(&before, &std::fs::metadata(path)?) {
+            return Ok((options.encode(&hash), bytes));
+        }
+    }
+
+    Err(IOError::other(format!(
+        "{}: changed during hashing",
+        path.display()
+    )))
+}
+
+/// Whether the file looks the same **before** and **after** hashing
+fn same_state(before: &std::fs::Metadata, after: &std::fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if (
+            before.dev(),
+            before.ino(),
+            before.ctime(),
+            before.ctime_nsec(),
+        ) != (after.dev(), after.ino(), after.ctime(), after.ctime_nsec())
+        {
+            return false;
+        }
+    }
+
+    before.len() == after.len()
+        && before.modified().ok() == after.modified().ok()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ProgressInfo;
+    use super::{HashOptions, ProgressInfo};
     use crate::fs::extra;
     use blake2::{Blake2s256, Digest};
     use std::path::PathBuf;
@@ -134,20 +804,303 @@ mod tests {
     fn get_hash_file() {
         let (_temp_dir, path) = extra::generate_random_file(32);
 
-        let mut hash = Blake2s256::new();
-        let result =
-            super::get_hash_file(&path.to_path_buf(), &mut hash).unwrap();
+        let hash = Blake2s256::new();
+        let result = super::get_hash_file(&path, &hash).unwrap();
 
         println!("result: {}", result);
         assert_eq!(result.len(), 64); // Blake2s256 len == 64
     }
 
+    #[test]
+    fn get_hash_open_file() {
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+
+        let hash = Blake2s256::new();
+        let expected = super::get_hash_file(&path, &hash).unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let hash = Blake2s256::new();
+        assert_eq!(
+            super::get_hash_open_file(&file, &hash).unwrap(),
+            expected
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsFd;
+
+            let file = std::fs::File::open(&path).unwrap();
+            let hash = Blake2s256::new();
+            assert_eq!(
+                super::get_hash_fd(file.as_fd(), &hash).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn get_hash_bytes() {
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+        let data = std::fs::read(&path).unwrap();
+
+        let hash = Blake2s256::new();
+        let expected = super::get_hash_file(&path, &hash).unwrap();
+
+        let hash = Blake2s256::new();
+        assert_eq!(super::get_hash_bytes(&data, &hash), expected);
+    }
+
+    #[test]
+    fn get_hash_file_dyn() {
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+
+        let hash = Blake2s256::new();
+        let expected = super::get_hash_file(&path, &hash).unwrap();
+
+        let hash: Box<dyn digest::DynDigest> = Box::new(Blake2s256::new());
+        assert_eq!(
+            super::get_hash_file_dyn(&path, hash.as_ref()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn get_hash_files_with_factory() {
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+        let options = HashOptions::new(4);
+
+        let result = super::get_hash_files_with_factory(
+            &paths,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        let mut expected = Blake2s256::new();
+        for path in paths.iter() {
+            let hash = Blake2s256::new();
+            let file_hash = super::get_hash_file(path, &hash).unwrap();
+            expected.update(file_hash.as_bytes());
+        }
+        assert_eq!(result, crate::encoding::get_lowerhex(&mut expected));
+
+        let empty: &[PathBuf] = &[];
+        let error = super::get_hash_files_with_factory(
+            empty,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn single_thread() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+        let options = HashOptions::new(8)
+            .root_threads(temp_dir.path(), 2)
+            .single_thread(true);
+
+        let threads = std::sync::Mutex::new(std::collections::HashSet::new());
+        let result = super::get_hash_files_with_factory(
+            &paths,
+            || {
+                threads.lock().unwrap().insert(std::thread::current().id());
+                Blake2s256::new()
+            },
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        let expected = super::get_hash_files_with_factory(
+            &paths,
+            Blake2s256::new,
+            &HashOptions::new(8),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+
+        let threads = threads.into_inner().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert!(threads.contains(&std::thread::current().id()));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn thread_pool() {
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .thread_name(|_| String::from("caller"))
+            .build()
+            .unwrap();
+        let options = HashOptions::new(8).thread_pool(pool.into());
+
+        let names = std::sync::Mutex::new(std::collections::HashSet::new());
+        let result = super::get_hash_files_with_factory(
+            &paths,
+            || {
+                let name = std::thread::current().name().map(String::from);
+                names.lock().unwrap().insert(name);
+                Blake2s256::new()
+            },
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        let expected = super::get_hash_files_with_factory(
+            &paths,
+            Blake2s256::new,
+            &HashOptions::new(8),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+
+        // The combining digest is created on the calling thread
+        let names = names.into_inner().unwrap();
+        let current = std::thread::current().name().map(String::from);
+        assert!(names.contains(&Some(String::from("caller"))));
+        assert!(names.iter().all(
+            |name| name.as_deref() == Some("caller") || *name == current
+        ));
+
+        // Workers of the channel run on the pool as well
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for path in paths.iter() {
+            sender.send(path.to_path_buf()).unwrap();
+        }
+        drop(sender);
+
+        let names = std::sync::Mutex::new(std::collections::HashSet::new());
+        let received = super::get_hash_files_from_channel(
+            receiver,
+            &Blake2s256::new(),
+            &options,
+            |_, _| {
+                let name = std::thread::current().name().map(String::from);
+                names.lock().unwrap().insert(name);
+            },
+        )
+        .unwrap();
+        assert_eq!(received, 20);
+        assert_eq!(
+            names.into_inner().unwrap(),
+            [Some(String::from("caller"))].into()
+        );
+    }
+
+    #[cfg(all(feature = "parallel", target_os = "linux"))]
+    #[test]
+    fn numa() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+        let nodes = crate::numa::get_nodes();
+
+        for options in [
+            HashOptions::new(4).numa(true),
+            HashOptions::new(4).numa(true).root_threads(&temp_dir, 2),
+        ] {
+            assert_ne!(options.pool(), HashOptions::new(4).pool());
+
+            let result = super::get_hash_files_with_factory(
+                &paths,
+                || {
+                    // Workers are pinned to the CPUs of one node
+                    let worker =
+                        std::thread::current().name().is_some_and(|name| {
+                            name.starts_with("file-hashing-worker-")
+                        });
+                    if nodes.len() > 1 && worker {
+                        let cpus = current_cpus();
+                        assert!(nodes.iter().any(|node| cpus
+                            .iter()
+                            .all(|cpu| node.contains(cpu))));
+                    }
+                    Blake2s256::new()
+                },
+                &options,
+                |_| {},
+            )
+            .unwrap();
+
+            let expected = super::get_hash_files_with_factory(
+                &paths,
+                Blake2s256::new,
+                &HashOptions::new(4),
+                |_| {},
+            )
+            .unwrap();
+            assert_eq!(result, expected);
+        }
+    }
+
+    /// CPUs the current thread may run on
+    #[cfg(all(feature = "parallel", target_os = "linux"))]
+    fn current_cpus() -> Vec<usize> {
+        // SAFETY: `set` is a valid cpu_set_t
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set);
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|cpu| libc::CPU_ISSET(*cpu, &set))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn include_sizes() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(5, 32);
+        let mut paths: Vec<PathBuf> =
+            paths.iter().map(|path| path.to_path_buf()).collect();
+        let empty = temp_dir.path().join("empty");
+        std::fs::write(&empty, "").unwrap();
+        paths.push(empty);
+        let options = HashOptions::new(4).include_sizes(true);
+
+        let result = super::get_hash_files_with_factory(
+            &paths,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        let mut expected = Blake2s256::new();
+        for path in paths.iter() {
+            let hash = Blake2s256::new();
+            let file_hash = super::get_hash_file(path, &hash).unwrap();
+            expected.update(file_hash.as_bytes());
+            expected
+                .update(std::fs::metadata(path).unwrap().len().to_le_bytes());
+        }
+        assert_eq!(result, crate::encoding::get_lowerhex(&mut expected));
+
+        let without_sizes = super::get_hash_files_with_factory(
+            &paths,
+            Blake2s256::new,
+            &HashOptions::new(4),
+            |_| {},
+        )
+        .unwrap();
+        assert_ne!(result, without_sizes);
+    }
+
     #[test]
     fn get_hash_files() {
         let (temp_dir, _path) =
             extra::generate_random_folder_with_files(325, 32);
 
-        let walkdir = WalkDir::new(temp_dir.to_path_buf());
+        let walkdir = WalkDir::new(&temp_dir);
         let mut paths: Vec<PathBuf> = Vec::new();
 
         for file in walkdir.into_iter().filter_map(|file| file.ok()) {
@@ -156,17 +1109,309 @@ mod tests {
             }
         }
 
-        let mut hash = Blake2s256::new();
+        let hash = Blake2s256::new();
         let result =
-            super::get_hash_files(&paths, &mut hash, 4, |info| match info {
+            super::get_hash_files(&paths, &hash, 4, |info| match info {
                 ProgressInfo::Yield(done_files) => {
                     println!("done files {}/{}", done_files, paths.len())
                 }
                 ProgressInfo::Error(error) => println!("error: {}", error),
+                _ => {}
             })
             .unwrap();
 
         println!("result: {}", result);
         assert_eq!(result.len(), 64); // Blake2s256 len == 64
     }
+
+    #[test]
+    fn hash_is_not_changed() {
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(5, 32);
+        let content = std::fs::read(&paths[0]).unwrap();
+
+        let mut salted = Blake2s256::new();
+        Digest::update(&mut salted, b"salt");
+        let before = crate::encoding::get_lowerhex(&mut salted);
+
+        // Every call starts from the state of the caller's hash
+        let first = super::get_hash_file(&paths[0], &salted).unwrap();
+        let second = super::get_hash_file(&paths[0], &salted).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            super::get_hash_bytes(
+                &[&b"salt"[..], &content].concat(),
+                &Blake2s256::new()
+            )
+        );
+        assert_eq!(crate::encoding::get_lowerhex(&mut salted), before);
+
+        // Files don't see each other
+        let hash = Blake2s256::new();
+        let result = super::get_hash_files(&paths, &hash, 2, |_| {}).unwrap();
+        assert_eq!(
+            result,
+            super::get_hash_files_with_factory(
+                &paths,
+                Blake2s256::new,
+                &HashOptions::new(2),
+                |_| {}
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            super::get_hash_files(&paths, &hash, 2, |_| {}).unwrap(),
+            result
+        );
+    }
+
+    #[test]
+    fn get_hash_files_iter() {
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let hash = Blake2s256::new();
+        let expected =
+            super::get_hash_files(&paths, &hash, 2, |_| {}).unwrap();
+
+        let hash = Blake2s256::new();
+        let result = super::get_hash_files_iter(
+            paths.iter().map(|path| path.path()),
+            &hash,
+            2,
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(result, expected);
+
+        let error = super::get_hash_files_iter(
+            std::iter::empty::<PathBuf>(),
+            &hash,
+            2,
+            |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn get_hash_files_from_channel() {
+        let (_temp_dir, paths) =
+            extra::generate_random_folder_with_files(20, 32);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let producer = {
+            let paths: Vec<PathBuf> =
+                paths.iter().map(|path| path.to_path_buf()).collect();
+            std::thread::spawn(move || {
+                for path in paths {
+                    sender.send(path).unwrap();
+                }
+            })
+        };
+
+        let results = std::sync::Mutex::new(Vec::new());
+        let received = super::get_hash_files_from_channel(
+            receiver,
+            &Blake2s256::new(),
+            &HashOptions::new(4),
+            |path, result| results.lock().unwrap().push((path, result)),
+        )
+        .unwrap();
+        producer.join().unwrap();
+
+        assert_eq!(received, 20);
+        for (path, result) in results.into_inner().unwrap() {
+            let hash = Blake2s256::new();
+            let expected = super::get_hash_file(&path, &hash).unwrap();
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn get_hash_files_from_channel_cancelled() {
+        let token = crate::options::CancelToken::new();
+        let options = HashOptions::new(4).cancel_token(token.clone());
+        // The sender stays alive and never sends
+        let (_sender, receiver) = std::sync::mpsc::channel::<PathBuf>();
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            token.cancel();
+        });
+
+        let error = super::get_hash_files_from_channel(
+            receiver,
+            &Blake2s256::new(),
+            &options,
+            |_, _| {},
+        )
+        .unwrap_err();
+        canceller.join().unwrap();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn pre_fed_hash() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+        let options = HashOptions::new(4);
+
+        // Data fed before is hashed before every file by every function
+        let mut hash = Blake2s256::new();
+        hash.update(b"salt");
+        let expected: Vec<String> = paths
+            .iter()
+            .map(|path| super::get_hash_file(path, &hash).unwrap())
+            .collect();
+        assert_ne!(
+            expected[0],
+            super::get_hash_file(&paths[0], &Blake2s256::new()).unwrap()
+        );
+
+        let results = super::get_hash_each_file(&paths, &hash, &options);
+        for (result, expected) in results.into_iter().zip(&expected) {
+            assert_eq!(&result.unwrap(), expected);
+        }
+
+        let manifest = crate::manifest::Manifest::create(
+            &temp_dir,
+            &hash,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        let mut cache = crate::cache::HashCache::new();
+        for (path, expected) in paths.iter().zip(&expected) {
+            let name = path.file_name().unwrap();
+            assert_eq!(
+                &manifest.entries[std::path::Path::new(name)],
+                expected
+            );
+            assert_eq!(&cache.get_hash_file(path, &hash).unwrap(), expected);
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        for path in paths.iter() {
+            sender.send(path.to_path_buf()).unwrap();
+        }
+        drop(sender);
+        super::get_hash_files_from_channel(
+            receiver,
+            &hash,
+            &options,
+            |path, result| {
+                let index =
+                    paths.iter().position(|p| p.path() == path).unwrap();
+                assert_eq!(result.unwrap(), expected[index]);
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn get_hash_each_file_with_root_threads() {
+        let (temp_dir1, paths1) =
+            extra::generate_random_folder_with_files(20, 32);
+        let (_temp_dir2, paths2) =
+            extra::generate_random_folder_with_files(20, 32);
+
+        let paths: Vec<_> = paths1.iter().chain(paths2.iter()).collect();
+        let options = HashOptions::new(4).root_threads(&temp_dir1, 1);
+
+        let hash = Blake2s256::new();
+        let results = super::get_hash_each_file(&paths, &hash, &options);
+
+        for (path, result) in paths.iter().zip(results) {
+            let hash = Blake2s256::new();
+            assert_eq!(
+                result.unwrap(),
+                super::get_hash_file(path, &hash).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn hash_file_with_detect_changes() {
+        let (_temp_dir, path) = extra::generate_random_file(1024);
+        let options = HashOptions::new(1).detect_changes(Some(1));
+
+        let hash = Blake2s256::new();
+        let expected = super::get_hash_file(&path, &hash).unwrap();
+
+        let (result, bytes) =
+            super::hash_file_with(path.path(), &hash, &options).unwrap();
+        assert_eq!(result, expected);
+        assert_eq!(bytes, 1024);
+    }
+
+    thread_local! {
+        /// Number of attempts of [super::hash_file_with] on this thread that see the file change
+        static CHANGES: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    }
+
+    /// Append to **path** while it is hashed, see [CHANGES]
+    pub(super) fn change_during_hash(path: &std::path::Path) {
+        use std::io::Write;
+
+        if CHANGES.get() > 0 {
+            CHANGES.set(CHANGES.get() - 1);
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .write_all(b"changed")
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn hash_file_with_retried() {
+        let (_temp_dir, path) = extra::generate_random_file(1024);
+        let retried = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = {
+            let retried = retried.clone();
+            HashOptions::new(1).detect_changes(Some(2)).subscribe(
+                move |info| {
+                    if let ProgressInfo::Retried { attempt, .. } = info {
+                        retried.lock().unwrap().push(*attempt);
+                    }
+                },
+            )
+        };
+
+        // Changed twice, the third attempt sees the file as it stays
+        let mut hash = Blake2s256::new();
+        hash.update(b"salt");
+        CHANGES.set(2);
+        let (result, bytes) =
+            super::hash_file_with(path.path(), &hash, &options).unwrap();
+        assert_eq!(*retried.lock().unwrap(), vec![1, 2]);
+        assert_eq!(bytes, 1024 + 2 * 7);
+        assert_eq!(result, super::get_hash_file(&path, &hash).unwrap());
+
+        retried.lock().unwrap().clear();
+        CHANGES.set(3);
+        let error =
+            super::hash_file_with(path.path(), &hash, &options).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Other);
+        assert_eq!(*retried.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn hash_file_with_procfs() {
+        // Files of procfs have the size 0, but are not empty
+        let options = HashOptions::new(1).detect_changes(Some(0));
+        let (result, bytes) = super::hash_file_with(
+            "/proc/self/status",
+            &Blake2s256::new(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(result.len(), 64); // Blake2s256 len == 64
+        assert!(bytes > 0);
+    }
 }
@@ -1,6 +1,8 @@
 //! Folder functions
 
 use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 /// Get hash from **folder**
@@ -14,11 +16,11 @@ use std::path::PathBuf;
 /// use blake2::{Blake2s256, Digest};
 /// use file_hashing::get_hash_folder;
 ///
-/// let mut hash = Blake2s256::new();
+/// let hash = Blake2s256::new();
 ///
 /// let result = get_hash_folder(
 ///     &PathBuf::from("/home/gladi/Pictures"),
-///     &mut hash,
+///     &hash,
 ///     12,
 ///     |_| {},
 /// )
@@ -30,24 +32,385 @@ use std::path::PathBuf;
 /// # Error
 ///
 /// * If the folder **is empty**, the **IOErrorKind::InvalidInput** error will be returned
+/// * Entries that cannot be read (e.g. folders without permission) are reported through **progress** as [ProgressInfo::Error],
+///   the hash is then incomplete
 pub fn get_hash_folder<HashType, P>(
     dir: P,
-    hash: &mut HashType,
+    hash: &HashType,
     num_threads: usize,
     progress: impl Fn(ProgressInfo),
 ) -> Result<String, IOError>
 where
-    HashType: DynDigest + Clone + std::marker::Send,
+    HashType: DynDigest + Clone + std::marker::Send + std::marker::Sync,
     P: AsRef<Path> + std::marker::Sync,
 {
-    get_hash_files(
-        &fs::get_all_file_from_folder(dir),
-        hash,
-        num_threads,
+    let paths = fs::get_all_file_with_metadata(dir).report_errors(&progress);
+    let paths: Vec<PathBuf> =
+        paths.into_iter().map(|entry| entry.path).collect();
+
+    get_hash_files(&paths, hash, num_threads, progress)
+}
+
+/// The same as [get_hash_folder], but for an algorithm chosen at runtime
+///
+/// # Example
+///
+/// ```no_run
+/// use digest::DynDigest;
+/// use file_hashing::folder::get_hash_folder_dyn;
+///
+/// let hash: Box<dyn DynDigest + Send + Sync> = match std::env::var("ALGORITHM").as_deref() {
+///     Ok("sha256") => Box::new(sha2::Sha256::default()),
+///     _ => Box::new(blake2::Blake2s256::default()),
+/// };
+///
+/// let result =
+///     get_hash_folder_dyn("/home/gladi/Pictures", hash.as_ref(), 12, |_| {}).unwrap();
+/// println!("{}", result);
+/// ```
+pub fn get_hash_folder_dyn<P>(
+    dir: P,
+    hash: &(dyn DynDigest + std::marker::Send + std::marker::Sync),
+    num_threads: usize,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    P: AsRef<Path>,
+{
+    let paths = fs::get_all_file_with_metadata(dir).report_errors(&progress);
+    let paths: Vec<PathBuf> =
+        paths.into_iter().map(|entry| entry.path).collect();
+
+    file::get_hash_files_dyn(&paths, hash, num_threads, progress)
+}
+
+/// Get hash from **folder**, every file is hashed with its own digest from **new_hash**
+///
+/// See [get_hash_files_with_factory](crate::file::get_hash_files_with_factory). Files are taken in the order of their paths,
+/// so the result does not depend on the order of the walk
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{folder::get_hash_folder_with_factory, HashOptions};
+///
+/// let result = get_hash_folder_with_factory(
+///     "/home/gladi/Pictures",
+///     Sha256::new,
+///     &HashOptions::new(12),
+///     |_| {},
+/// )
+/// .unwrap();
+///
+/// assert_eq!(result.len(), 64); // Sha256 len == 64
+/// ```
+///
+/// # Error
+///
+/// * If the folder **is empty**, the **IOErrorKind::InvalidInput** error will be returned
+/// * Entries that cannot be read are reported through **progress** as [ProgressInfo::Error] and are left out
+pub fn get_hash_folder_with_factory<HashType, F, P>(
+    dir: P,
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path>,
+{
+    get_hash_folder_with_stats(dir, new_hash, options, progress)
+        .map(|(result, _)| result)
+}
+
+/// The same as [get_hash_folder_with_factory], but [Stats](crate::stats::Stats) of the run are returned alongside the hash
+///
+/// # Error
+///
+/// The same as [get_hash_folder_with_factory]
+pub fn get_hash_folder_with_stats<HashType, F, P>(
+    dir: P,
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<(String, crate::stats::Stats), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path>,
+{
+    hash_folder(dir, &new_hash, options, progress, |_, _, _| {})
+}
+
+/// File that went into a folder hash, see [get_hash_folder_with_files]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncludedFile {
+    /// Relative to the folder
+    pub path: PathBuf,
+
+    /// Bytes that were hashed
+    pub size: u64,
+}
+
+/// The same as [get_hash_folder_with_factory], but the files that went into the hash are returned alongside it
+///
+/// Files that could not be read are not in the list. It is sorted like the files were combined,
+/// so it documents the hash without walking the folder a second time
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{folder::get_hash_folder_with_files, HashOptions};
+///
+/// let (result, files) =
+///     get_hash_folder_with_files("/home/gladi/Pictures", Sha256::new, &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// println!("{}", result);
+/// for file in files {
+///     println!("{} {}", file.size, file.path.display());
+/// }
+/// ```
+///
+/// # Error
+///
+/// The same as [get_hash_folder_with_factory]
+pub fn get_hash_folder_with_files<HashType, F, P>(
+    dir: P,
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<(String, Vec<IncludedFile>), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path>,
+{
+    let mut files = Vec::new();
+    let (result, _) =
+        hash_folder(dir, &new_hash, options, progress, |path, _, size| {
+            files.push(IncludedFile {
+                path: path.to_path_buf(),
+                size,
+            })
+        })?;
+
+    Ok((result, files))
+}
+
+/// The same as [get_hash_folder_with_factory], but every immediate subfolder also gets a hash of its own
+///
+/// The subtotal of a subfolder equals [get_hash_folder_with_factory] of that subfolder, so a changed hash
+/// can be narrowed down to the top-level folders that changed without hashing anything twice.
+/// Files directly in **dir** only go into the hash of **dir**, subfolders without included files have no subtotal
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{folder::get_hash_folder_with_subtotals, HashOptions};
+///
+/// let (result, subtotals) =
+///     get_hash_folder_with_subtotals("/srv/data", Sha256::new, &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// println!("{}", result);
+/// for (subdir, hash) in subtotals {
+///     println!("{} {}", hash, subdir.display()); // e.g. compare with yesterday's run
+/// }
+/// ```
+///
+/// # Error
+///
+/// The same as [get_hash_folder_with_factory]
+pub fn get_hash_folder_with_subtotals<HashType, F, P>(
+    dir: P,
+    new_hash: F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<(String, BTreeMap<PathBuf, String>), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+    P: AsRef<Path>,
+{
+    let mut subtotals = BTreeMap::new();
+    // Files arrive in path order, so the files of a subfolder are next to each other
+    let mut current: Option<(PathBuf, HashType)> = None;
+
+    let (result, _) = hash_folder(
+        dir,
+        &new_hash,
+        options,
         progress,
+        |path, file_hash, size| {
+            let mut components = path.components();
+            let subdir = match (components.next(), components.next()) {
+                (Some(subdir), Some(_)) => Path::new(subdir.as_os_str()),
+                _ => return,
+            };
+
+            if current.as_ref().map(|(name, _)| name.as_path())
+                != Some(subdir)
+            {
+                let next = (subdir.to_path_buf(), new_hash());
+                if let Some((name, hash)) = current.replace(next) {
+                    subtotals.insert(name, options.encode(&hash));
+                }
+            }
+
+            if let Some((_, hash)) = &mut current {
+                hash.update(file_hash.as_bytes());
+                if options.include_sizes {
+                    hash.update(&size.to_le_bytes());
+                }
+            }
+        },
+    )?;
+
+    if let Some((name, hash)) = current {
+        subtotals.insert(name, options.encode(&hash));
+    }
+
+    Ok((result, subtotals))
+}
+
+/// Walk **dir** and combine its files in path order,
+/// **on_included** gets the relative path, the hash and the hashed bytes of every file that went into the hash
+fn hash_folder<HashType, F>(
+    dir: impl AsRef<Path>,
+    new_hash: &F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+    mut on_included: impl FnMut(&Path, &str, u64),
+) -> Result<(String, crate::stats::Stats), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+{
+    let started = std::time::Instant::now();
+    let dir = fs::resolve_root(dir.as_ref(), options)?;
+    let progress = options.broadcast(progress);
+
+    if options.bounded_memory {
+        return hash_folder_bounded(
+            &dir,
+            new_hash,
+            options,
+            progress,
+            started,
+            on_included,
+        );
+    }
+
+    let walk = fs::walk(&dir, options);
+    let stats = crate::stats::Stats {
+        skipped: walk.skipped.len() as u64,
+        errors: walk.errors.len() as u64,
+        ..Default::default()
+    };
+    let mut paths: Vec<PathBuf> = walk
+        .report_errors(&progress)
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    paths.sort();
+
+    file::combine_with_stats(
+        &paths,
+        new_hash,
+        options,
+        progress,
+        stats,
+        started,
+        |index, file_hash, size| {
+            let path = &paths[index];
+            on_included(
+                path.strip_prefix(&dir).unwrap_or(path),
+                file_hash,
+                size,
+            )
+        },
     )
 }
 
+/// Files hashed at once per thread by [HashOptions::bounded_memory]
+const WINDOW_PER_THREAD: usize = 256;
+
+/// [hash_folder] that reads **dir** while it hashes, see [HashOptions::bounded_memory]
+///
+/// Only one window of paths and results is held at a time, files are combined in the order of the walk,
+/// which is the order of their paths
+fn hash_folder_bounded<HashType, F>(
+    dir: &Path,
+    new_hash: &F,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+    started: std::time::Instant,
+    mut on_included: impl FnMut(&Path, &str, u64),
+) -> Result<(String, crate::stats::Stats), IOError>
+where
+    HashType: DynDigest + Clone,
+    F: Fn() -> HashType + std::marker::Sync,
+{
+    let window = options.num_threads.max(1) * WINDOW_PER_THREAD;
+    let mut walk = fs::walk_sorted(dir, options);
+    let mut combiner =
+        file::Combiner::new(new_hash(), crate::stats::Stats::default());
+    let mut paths = Vec::with_capacity(window);
+
+    loop {
+        paths.clear();
+        for entry in walk.by_ref() {
+            match entry {
+                fs::WalkEntry::File(entry) => paths.push(entry.path),
+                fs::WalkEntry::Skipped(path, reason) => {
+                    combiner.stats.skipped += 1;
+                    progress(ProgressInfo::Skipped { path, reason });
+                }
+                fs::WalkEntry::Error(error) => {
+                    combiner.stats.errors += 1;
+                    progress(ProgressInfo::Error(error));
+                }
+            }
+
+            if paths.len() == window {
+                break;
+            }
+        }
+
+        if paths.is_empty() {
+            break;
+        }
+
+        let results =
+            file::get_hash_each_file_counted(&paths, new_hash, options);
+        options.check_cancelled()?;
+        combiner.add(
+            results,
+            options,
+            &progress,
+            |index, file_hash, size| {
+                let path = &paths[index];
+                on_included(
+                    path.strip_prefix(dir).unwrap_or(path),
+                    file_hash,
+                    size,
+                )
+            },
+        );
+    }
+
+    if combiner.len() == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    Ok(combiner.finish(options, started))
+}
+
 /// Get hash from **folders**
 ///
 /// This function gets all files from a folders recursively and gets their hash
@@ -59,11 +422,11 @@ where
 /// use blake2::{Blake2s256, Digest};
 /// use file_hashing::get_hash_folders;
 ///
-/// let mut hash = Blake2s256::new();
+/// let hash = Blake2s256::new();
 ///
 /// let result = get_hash_folders(
 ///     &vec![PathBuf::from("/home/gladi/Pictures"), PathBuf::from("/home/gladi/Hentai")],
-///     &mut hash,
+///     &hash,
 ///     12,
 ///     |_| {},
 /// )
@@ -75,23 +438,242 @@ where
 /// # Error
 ///
 /// * If the folders **is empty**, the **IOErrorKind::InvalidInput** error will be returned
+/// * Entries that cannot be read (e.g. folders without permission) are reported through **progress** as [ProgressInfo::Error],
+///   the hash is then incomplete
 pub fn get_hash_folders<HashType, P>(
     dirs: &Vec<P>,
-    hash: &mut HashType,
+    hash: &HashType,
     num_threads: usize,
     progress: impl Fn(ProgressInfo),
 ) -> Result<String, IOError>
 where
-    HashType: DynDigest + Clone + std::marker::Send,
+    HashType: DynDigest + Clone + std::marker::Send + std::marker::Sync,
     P: AsRef<Path> + std::marker::Sync,
 {
-    let mut paths: Vec<PathBuf> = vec![];
+    get_hash_folders_iter(dirs, hash, num_threads, progress)
+}
+
+/// The same as [get_hash_folders], but for any iterator of folders
+///
+/// Folders are walked one after another as they come, their files are not collected into one `Vec` first
+///
+/// # Error
+///
+/// * If there are no files in the folders, the **IOErrorKind::InvalidInput** error will be returned
+/// * Entries that cannot be read (e.g. folders without permission) are reported through **progress** as [ProgressInfo::Error],
+///   the hash is then incomplete
+pub fn get_hash_folders_iter<HashType, I>(
+    dirs: I,
+    hash: &HashType,
+    num_threads: usize,
+    progress: impl Fn(ProgressInfo),
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send + std::marker::Sync,
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let paths = dirs.into_iter().flat_map(|dir| {
+        fs::get_all_file_with_metadata(dir)
+            .report_errors(&progress)
+            .into_iter()
+            .map(|entry| entry.path)
+    });
+
+    file::get_hash_files_iter(paths, hash, num_threads, &progress)
+}
+
+/// Get fingerprint of **folder** metadata
+///
+/// Hashes path, type, size and modification time of every entry **without reading files**.
+/// If the fingerprint has not changed, the folder has almost certainly not changed either,
+/// so the expensive [get_hash_folder] can be skipped
+///
+/// Needs the `walk` feature
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::folder::fingerprint_metadata;
+///
+/// let hash = Blake2s256::new();
+/// let result = fingerprint_metadata("/home/gladi/Pictures", &hash).unwrap();
+///
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+#[cfg(feature = "walk")]
+pub fn fingerprint_metadata<HashType, P>(
+    dir: P,
+    hash: &HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    fingerprint_metadata_with(dir, hash, &FingerprintOptions::default())
+}
+
+/// Which metadata [fingerprint_metadata_with] includes and how it is normalized
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::folder::FingerprintOptions;
+///
+/// // The same tree gives the same fingerprint on Linux, macOS and Windows
+/// let options = FingerprintOptions {
+///     mode: true,
+///     portable: true,
+///     ..Default::default()
+/// };
+/// ```
+#[cfg(feature = "walk")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FingerprintOptions {
+    /// Include permission bits (only **Unix** has them)
+    pub mode: bool,
+
+    /// Include uid and gid (only **Unix** has them)
+    pub owner: bool,
+
+    /// Normalize metadata so the fingerprint does not depend on the platform:
+    ///
+    /// * paths use `/` and entries are sorted by their UTF-8 name
+    /// * modification time is clamped to whole seconds
+    /// * size of folders is 0
+    /// * permissions are reduced to `755` (folders and executables) or `644`
+    /// * uid and gid are stripped
+    pub portable: bool,
+}
+
+/// Get fingerprint of **folder** metadata with [FingerprintOptions]
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::folder::{fingerprint_metadata_with, FingerprintOptions};
+///
+/// let hash = Blake2s256::new();
+/// let options = FingerprintOptions {
+///     portable: true,
+///     ..Default::default()
+/// };
+///
+/// let result =
+///     fingerprint_metadata_with("/home/gladi/Pictures", &hash, &options)
+///         .unwrap();
+/// ```
+#[cfg(feature = "walk")]
+pub fn fingerprint_metadata_with<HashType, P>(
+    dir: P,
+    hash: &HashType,
+    options: &FingerprintOptions,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let mut hash = hash.clone();
+    let walkdir = if options.portable {
+        walkdir::WalkDir::new(dir).sort_by(|a, b| {
+            a.file_name()
+                .to_string_lossy()
+                .cmp(&b.file_name().to_string_lossy())
+        })
+    } else {
+        walkdir::WalkDir::new(dir).sort_by_file_name()
+    };
+
+    for entry in walkdir {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+
+        let file_type = if metadata.is_dir() {
+            b'd'
+        } else if metadata.is_symlink() {
+            b'l'
+        } else {
+            b'f'
+        };
 
-    for dir in dirs {
-        paths.append(&mut fs::get_all_file_from_folder(dir));
+        let mut modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let mut size = metadata.len();
+
+        if options.portable {
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+
+            hash.update(relative.join("/").as_bytes());
+            modified = std::time::Duration::from_secs(modified.as_secs());
+
+            if metadata.is_dir() {
+                size = 0;
+            }
+        } else {
+            hash.update(relative.to_string_lossy().as_bytes());
+        }
+
+        hash.update(&[0, file_type]);
+        hash.update(&size.to_le_bytes());
+        hash.update(&modified.as_secs().to_le_bytes());
+        hash.update(&modified.subsec_nanos().to_le_bytes());
+
+        if options.mode {
+            hash.update(&get_mode(&metadata, options.portable).to_le_bytes());
+        }
+
+        if options.owner && !options.portable {
+            let (uid, gid) = get_owner(&metadata);
+            hash.update(&uid.to_le_bytes());
+            hash.update(&gid.to_le_bytes());
+        }
     }
 
-    get_hash_files(&paths, hash, num_threads, progress)
+    Ok(crate::encoding::get_lowerhex(&mut hash))
+}
+
+#[cfg(all(unix, feature = "walk"))]
+fn get_mode(metadata: &std::fs::Metadata, portable: bool) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode() & 0o7777;
+
+    match portable {
+        false => mode,
+        true if metadata.is_dir() || mode & 0o111 != 0 => 0o755,
+        true => 0o644,
+    }
+}
+
+#[cfg(all(not(unix), feature = "walk"))]
+fn get_mode(metadata: &std::fs::Metadata, _portable: bool) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+#[cfg(all(unix, feature = "walk"))]
+fn get_owner(metadata: &std::fs::Metadata) -> (u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.uid(), metadata.gid())
+}
+
+#[cfg(all(not(unix), feature = "walk"))]
+fn get_owner(_metadata: &std::fs::Metadata) -> (u32, u32) {
+    (0, 0)
 }
 
 #[cfg(test)]
@@ -101,25 +683,255 @@ mod tests {
 
     #[test]
     fn get_hash_folder() {
-        let mut hash = Blake2s256::new();
+        let hash = Blake2s256::new();
         let (temp_dir, _path) =
             extra::generate_random_folder_with_files(325, 32);
 
-        let result = super::get_hash_folder(
-            &temp_dir.to_path_buf(),
-            &mut hash,
-            12,
+        let result =
+            super::get_hash_folder(&temp_dir, &hash, 12, |_| {}).unwrap();
+
+        println!("result: {}", result);
+        assert_eq!(result.len(), 64); // Blake2s256 len == 64
+    }
+
+    #[test]
+    fn get_hash_folder_dyn() {
+        let (temp_dir, _path) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let hash = Blake2s256::new();
+        let expected =
+            super::get_hash_folder(&temp_dir, &hash, 1, |_| {}).unwrap();
+
+        let hash: Box<dyn digest::DynDigest + Send + Sync> =
+            Box::new(Blake2s256::new());
+        let result =
+            super::get_hash_folder_dyn(&temp_dir, hash.as_ref(), 1, |_| {})
+                .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn get_hash_folder_with_factory() {
+        let (temp_dir, _path) =
+            extra::generate_random_folder_with_files(10, 32);
+        let options = crate::HashOptions::new(4);
+
+        let first = super::get_hash_folder_with_factory(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
             |_| {},
         )
         .unwrap();
+        let second = super::get_hash_folder_with_factory(
+            &temp_dir,
+            Blake2s256::new,
+            &options.clone().parallel_walk(true),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(first, second);
+    }
 
-        println!("result: {}", result);
-        assert_eq!(result.len(), 64); // Blake2s256 len == 64
+    #[test]
+    fn get_hash_folder_with_stats() {
+        let (temp_dir, _path) =
+            extra::generate_random_folder_with_files(10, 32);
+        let options = crate::HashOptions::new(4);
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(
+            temp_dir.path().join("random_file_0.txt"),
+            temp_dir.path().join("link"),
+        )
+        .unwrap();
+
+        let expected = super::get_hash_folder_with_factory(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        let skipped = std::sync::Mutex::new(Vec::new());
+        let (result, stats) = super::get_hash_folder_with_stats(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
+            |info| {
+                if let crate::ProgressInfo::Skipped { path, reason } = info {
+                    skipped.lock().unwrap().push((path, reason));
+                }
+            },
+        )
+        .unwrap();
+
+        assert_eq!(result, expected);
+        assert_eq!(stats.files, 10);
+        assert_eq!(stats.bytes, 320);
+        assert_eq!(stats.errors, 0);
+        #[cfg(unix)]
+        {
+            assert_eq!(stats.skipped, 1);
+            assert_eq!(
+                skipped.into_inner().unwrap(),
+                vec![(
+                    temp_dir.path().join("link"),
+                    crate::SkipReason::Symlink
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn get_hash_folder_with_files() {
+        let (temp_dir, _path) =
+            extra::generate_random_folder_with_files(10, 32);
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/empty"), "").unwrap();
+        let options = crate::HashOptions::new(4);
+
+        let expected = super::get_hash_folder_with_factory(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        let (result, files) = super::get_hash_folder_with_files(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(result, expected);
+        assert_eq!(files.len(), 11);
+        assert!(files.windows(2).all(|pair| pair[0].path < pair[1].path));
+        assert_eq!(files.iter().map(|file| file.size).sum::<u64>(), 320);
+
+        let empty = files.last().unwrap();
+        assert_eq!(empty.path, std::path::Path::new("sub/empty"));
+        assert_eq!(empty.size, 0);
+    }
+
+    #[test]
+    fn get_hash_folder_with_subtotals() {
+        let (temp_dir, _paths) = extra::generate_random_tree(
+            1,
+            3,
+            2,
+            extra::SizeDistribution::Fixed(16),
+        );
+        std::fs::create_dir(temp_dir.path().join("empty")).unwrap();
+
+        for options in [
+            crate::HashOptions::new(4),
+            crate::HashOptions::new(4).include_sizes(true),
+        ] {
+            let (result, subtotals) = super::get_hash_folder_with_subtotals(
+                &temp_dir,
+                Blake2s256::new,
+                &options,
+                |_| {},
+            )
+            .unwrap();
+
+            let expected = super::get_hash_folder_with_factory(
+                &temp_dir,
+                Blake2s256::new,
+                &options,
+                |_| {},
+            )
+            .unwrap();
+            assert_eq!(result, expected);
+
+            assert_eq!(
+                subtotals.keys().collect::<Vec<_>>(),
+                ["dir_0", "dir_1", "dir_2"]
+                    .map(std::path::Path::new)
+                    .iter()
+                    .collect::<Vec<_>>()
+            );
+            for (subdir, subtotal) in &subtotals {
+                let expected = super::get_hash_folder_with_factory(
+                    temp_dir.path().join(subdir),
+                    Blake2s256::new,
+                    &options,
+                    |_| {},
+                )
+                .unwrap();
+                assert_eq!(subtotal, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_memory() {
+        // More files than one window of a single thread
+        let (temp_dir, paths) = extra::generate_random_tree(
+            2,
+            3,
+            30,
+            extra::SizeDistribution::Fixed(8),
+        );
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(
+            paths[0].path(),
+            temp_dir.path().join("dir_0").join("link"),
+        )
+        .unwrap();
+
+        for num_threads in [1, 4] {
+            let options = crate::HashOptions::new(num_threads);
+            let (expected, expected_files) =
+                super::get_hash_folder_with_files(
+                    &temp_dir,
+                    Blake2s256::new,
+                    &options,
+                    |_| {},
+                )
+                .unwrap();
+
+            let options = options.bounded_memory(true);
+            let (result, files) = super::get_hash_folder_with_files(
+                &temp_dir,
+                Blake2s256::new,
+                &options,
+                |_| {},
+            )
+            .unwrap();
+            let (_, stats) = super::get_hash_folder_with_stats(
+                &temp_dir,
+                Blake2s256::new,
+                &options,
+                |_| {},
+            )
+            .unwrap();
+
+            assert_eq!(result, expected);
+            assert_eq!(files, expected_files);
+            assert_eq!(stats.files, paths.len() as u64);
+            #[cfg(unix)]
+            assert_eq!(stats.skipped, 1);
+        }
+
+        let empty = assert_fs::TempDir::new().unwrap();
+        let error = super::get_hash_folder_with_factory(
+            &empty,
+            Blake2s256::new,
+            &crate::HashOptions::new(1).bounded_memory(true),
+            |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
     }
 
     #[test]
     fn get_hash_folders() {
-        let mut hash = Blake2s256::new();
+        let hash = Blake2s256::new();
         let (temp_dir1, _path1) =
             extra::generate_random_folder_with_files(325, 32);
         let (temp_dir2, _path2) =
@@ -127,7 +939,7 @@ mod tests {
 
         let result = super::get_hash_folders(
             &vec![temp_dir1.to_path_buf(), temp_dir2.to_path_buf()],
-            &mut hash,
+            &hash,
             12,
             |_| {},
         )
@@ -135,5 +947,80 @@ mod tests {
 
         println!("result: {}", result);
         assert_eq!(result.len(), 64); // Blake2s256 len == 64
+
+        let hash = Blake2s256::new();
+        let from_iter = super::get_hash_folders_iter(
+            [temp_dir1.path(), temp_dir2.path()],
+            &hash,
+            12,
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(from_iter, result);
+    }
+
+    #[cfg(feature = "walk")]
+    #[test]
+    fn fingerprint_metadata() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let hash = Blake2s256::new();
+        let before = super::fingerprint_metadata(&temp_dir, &hash).unwrap();
+        let hash = Blake2s256::new();
+        let again = super::fingerprint_metadata(&temp_dir, &hash).unwrap();
+        assert_eq!(before, again);
+
+        std::fs::write(paths[0].path(), "changed").unwrap();
+
+        let hash = Blake2s256::new();
+        let after = super::fingerprint_metadata(&temp_dir, &hash).unwrap();
+        assert_ne!(before, after);
+    }
+
+    #[cfg(feature = "walk")]
+    #[test]
+    fn fingerprint_metadata_portable() {
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+        let options = super::FingerprintOptions {
+            mode: true,
+            owner: true,
+            portable: true,
+        };
+
+        let hash = Blake2s256::new();
+        let before =
+            super::fingerprint_metadata_with(&temp_dir, &hash, &options)
+                .unwrap();
+
+        // Sub-second changes of modification time are ignored
+        let modified = std::fs::metadata(paths[0].path())
+            .unwrap()
+            .modified()
+            .unwrap();
+        let secs = modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(paths[0].path())
+            .unwrap();
+        file.set_modified(
+            std::time::UNIX_EPOCH
+                + std::time::Duration::new(secs, 123_456_789),
+        )
+        .unwrap();
+
+        let hash = Blake2s256::new();
+        let after =
+            super::fingerprint_metadata_with(&temp_dir, &hash, &options)
+                .unwrap();
+        assert_eq!(before, after);
+
+        let hash = Blake2s256::new();
+        let native = super::fingerprint_metadata(&temp_dir, &hash).unwrap();
+        assert_ne!(before, native);
     }
 }
@@ -1,40 +1,1336 @@
 //! Just wrappers for the filesystem
 
-use std::path::{Path, PathBuf};
+use crate::{IOError, ProgressInfo, SkipReason};
+use std::path::{Component, Path, PathBuf};
 
 pub fn get_all_file_from_folder<P>(path: P) -> Vec<PathBuf>
 where
     P: AsRef<Path>,
 {
-    let walkdir = walkdir::WalkDir::new(path);
+    get_all_file_with_metadata(path)
+        .files
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect()
+}
+
+/// File found by [get_all_file_with_metadata]
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub path: PathBuf,
+
+    /// Metadata gathered during the walk, symbolic links are not followed
+    pub metadata: std::fs::Metadata,
+}
+
+impl FileEntry {
+    pub fn size(&self) -> u64 {
+        self.metadata.len()
+    }
+
+    pub fn modified(&self) -> Option<std::time::SystemTime> {
+        self.metadata.modified().ok()
+    }
+}
+
+/// Result of a folder walk
+#[derive(Debug, Default)]
+pub struct Walk {
+    pub files: Vec<FileEntry>,
+
+    /// Entries that could not be read, e.g. folders without permission.
+    /// If there are any, results based on the walk may be incomplete
+    pub errors: Vec<(PathBuf, IOError)>,
+
+    /// Entries that are neither files nor folders (symbolic links, sockets, devices, ...) and were left out
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+}
+
+impl Walk {
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|entry| entry.path.clone()).collect()
+    }
+
+    /// Report errors through **progress** as [ProgressInfo::Error], skipped entries as [ProgressInfo::Skipped]
+    /// and keep only the files
+    pub(crate) fn report_errors(
+        self,
+        progress: &impl Fn(ProgressInfo),
+    ) -> Vec<FileEntry> {
+        for (_, error) in self.errors {
+            progress(ProgressInfo::Error(error));
+        }
+
+        for (path, reason) in self.skipped {
+            progress(ProgressInfo::Skipped { path, reason });
+        }
+
+        self.files
+    }
+
+    /// Leave out the entry at **path** that is neither a file nor a folder
+    pub(crate) fn push_skipped(
+        &mut self,
+        path: PathBuf,
+        file_type: std::fs::FileType,
+    ) {
+        self.skipped.push((path, skip_reason(file_type)));
+    }
+
+    pub(crate) fn push_error(&mut self, path: PathBuf, error: IOError) {
+        let error = error_at(&path, error);
+        self.errors.push((path, error));
+    }
+}
+
+/// **error** with **path** in its message
+fn error_at(path: &Path, error: IOError) -> IOError {
+    IOError::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+/// The same as [get_all_file_from_folder], but with the metadata of every file and the errors of the walk
+///
+/// The metadata comes from the walk itself, so callers don't have to stat every file a second time
+///
+/// Without the `walk` feature the folder is walked with [std::fs::read_dir]
+#[cfg(feature = "walk")]
+pub fn get_all_file_with_metadata<P>(path: P) -> Walk
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut walk = Walk::default();
+
+    for file in walkdir::WalkDir::new(path) {
+        let file = match file {
+            Ok(file) => file,
+            Err(error) => {
+                let error_path = error.path().unwrap_or(path).to_path_buf();
+                walk.push_error(error_path, error.into());
+                continue;
+            }
+        };
+
+        match file.metadata() {
+            Ok(metadata) if metadata.is_file() => {
+                walk.files.push(FileEntry {
+                    path: file.into_path(),
+                    metadata,
+                })
+            }
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(metadata) => {
+                walk.push_skipped(file.into_path(), metadata.file_type())
+            }
+            Err(error) => walk.push_error(file.into_path(), error.into()),
+        }
+    }
+
+    walk
+}
+
+#[cfg(not(feature = "walk"))]
+pub fn get_all_file_with_metadata<P>(path: P) -> Walk
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut walk = Walk::default();
+
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => walk.files.push(FileEntry {
+            path: path.to_path_buf(),
+            metadata,
+        }),
+        Ok(metadata) if metadata.is_dir() => read_dir_into(path, &mut walk),
+        Ok(metadata) => {
+            walk.push_skipped(path.to_path_buf(), metadata.file_type())
+        }
+        Err(error) => walk.push_error(path.to_path_buf(), error),
+    }
+
+    walk
+}
+
+/// Symbolic links below **dir** are not followed, like walkdir does by default
+#[cfg(not(feature = "walk"))]
+fn read_dir_into(dir: &Path, walk: &mut Walk) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => return walk.push_error(dir.to_path_buf(), error),
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                walk.push_error(dir.to_path_buf(), error);
+                continue;
+            }
+        };
+
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => {
+                walk.files.push(FileEntry { path, metadata })
+            }
+            Ok(metadata) if metadata.is_dir() => read_dir_into(&path, walk),
+            Ok(metadata) => walk.push_skipped(path, metadata.file_type()),
+            Err(error) => walk.push_error(path, error),
+        }
+    }
+}
+
+/// Open file for hashing with the settings of **options**
+pub(crate) fn open_file<P: AsRef<Path>>(
+    path: P,
+    options: &crate::HashOptions,
+) -> Result<std::fs::File, std::io::Error> {
+    let path = path.as_ref();
+    let file = match (&options.jail, options.no_follow) {
+        (Some(jail), _) => open_jailed(path, jail)?,
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        (None, true) => crate::openat::open_file(path)?,
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        (None, true) => {
+            return Err(IOError::from(std::io::ErrorKind::Unsupported))
+        }
+        (None, false) => std::fs::File::open(path)?,
+    };
+
+    if options.lock {
+        lock_shared(&file)?;
+    }
+
+    #[cfg(target_os = "macos")]
+    if options.no_cache {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: the descriptor is owned by `file` and valid
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(file)
+}
+
+/// The same as [get_all_file_with_metadata], but folders are read in parallel on **num_threads** threads
+///
+/// Much faster for trees with millions of entries, especially on SSDs and network filesystems.
+/// Files and errors are sorted by path, because the order of a parallel walk is random
+pub fn get_all_file_with_metadata_parallel<P>(
+    path: P,
+    num_threads: usize,
+) -> Walk
+where
+    P: AsRef<Path>,
+{
+    let pool = crate::pool::Pool::ambient(num_threads);
+    walk_in_pool(path.as_ref(), &pool)
+}
+
+/// The same as [get_all_file_with_metadata_parallel], but on the pool of **options**,
+/// e.g. with [HashOptions::numa](crate::HashOptions::numa) or [HashOptions::single_thread](crate::HashOptions::single_thread)
+pub fn get_all_file_with_metadata_parallel_with<P>(
+    path: P,
+    options: &crate::HashOptions,
+) -> Walk
+where
+    P: AsRef<Path>,
+{
+    walk_in_pool(path.as_ref(), &options.pool())
+}
+
+/// [get_all_file_with_metadata_parallel] on **pool**
+fn walk_in_pool(path: &Path, pool: &crate::pool::Pool) -> Walk {
+    let mut walk = Walk::default();
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            walk.files.push(FileEntry {
+                path: path.to_path_buf(),
+                metadata,
+            });
+            return walk;
+        }
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(metadata) => {
+            walk.push_skipped(path.to_path_buf(), metadata.file_type());
+            return walk;
+        }
+        Err(error) => {
+            walk.push_error(path.to_path_buf(), error);
+            return walk;
+        }
+    }
+
+    let mut walk = walk_tree(path, pool);
+    walk.files.sort_by(|a, b| a.path.cmp(&b.path));
+    walk.errors.sort_by(|a, b| a.0.cmp(&b.0));
+    walk
+}
+
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+fn walk_tree(path: &Path, pool: &crate::pool::Pool) -> Walk {
+    if pool.is_inline() {
+        return get_all_file_with_metadata(path);
+    }
+
+    let walk = std::sync::Mutex::new(Walk::default());
+    pool.scope(|scope| walk_parallel(scope, path.to_path_buf(), &walk));
+
+    walk.into_inner().unwrap()
+}
+
+/// There are no worker threads, the walk is sequential
+#[cfg(not(all(feature = "parallel", not(target_family = "wasm"))))]
+fn walk_tree(path: &Path, _pool: &crate::pool::Pool) -> Walk {
+    get_all_file_with_metadata(path)
+}
+
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+fn walk_parallel<'scope>(
+    scope: &rayon::Scope<'scope>,
+    dir: PathBuf,
+    walk: &'scope std::sync::Mutex<Walk>,
+) {
+    let mut local = Walk::default();
+
+    match std::fs::read_dir(&dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        local.push_error(dir.clone(), error);
+                        continue;
+                    }
+                };
+
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => {
+                        let path = entry.path();
+                        scope.spawn(move |scope| {
+                            walk_parallel(scope, path, walk)
+                        });
+                    }
+                    Ok(file_type) if file_type.is_file() => {
+                        match entry.metadata() {
+                            Ok(metadata) => local.files.push(FileEntry {
+                                path: entry.path(),
+                                metadata,
+                            }),
+                            Err(error) => {
+                                local.push_error(entry.path(), error)
+                            }
+                        }
+                    }
+                    Ok(file_type) => {
+                        local.push_skipped(entry.path(), file_type)
+                    }
+                    Err(error) => local.push_error(entry.path(), error),
+                }
+            }
+        }
+        Err(error) => local.push_error(dir, error),
+    }
+
+    let mut walk = walk.lock().unwrap();
+    walk.files.append(&mut local.files);
+    walk.errors.append(&mut local.errors);
+    walk.skipped.append(&mut local.skipped);
+}
+
+/// Write **path** through a temporary file in the same folder and rename it into place
+///
+/// Readers see either the old or the new file, never a truncated one.
+/// Every call has its own temporary file, so concurrent writers of one path don't clobber each other,
+/// and the file keeps the permissions of the one it replaces.
+/// With **sync** the data and the rename are flushed to disk before returning
+pub(crate) fn write_atomic<P, F>(
+    path: P,
+    sync: bool,
+    write: F,
+) -> Result<(), IOError>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut std::io::BufWriter<std::fs::File>) -> Result<(), IOError>,
+{
+    use std::io::Write;
+
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| IOError::from(crate::IOErrorKind::InvalidInput))?;
+
+    static TEMP_COUNTER: std::sync::atomic::AtomicU64 =
+        std::sync::atomic::AtomicU64::new(0);
+    let counter =
+        TEMP_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".tmp-{}-{}", std::process::id(), counter));
+    let temp_path = dir.join(temp_name);
+
+    let result = (|| {
+        let file = std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+
+        match std::fs::metadata(path) {
+            Ok(metadata) => {
+                writer.get_ref().set_permissions(metadata.permissions())?
+            }
+            Err(error) if error.kind() == crate::IOErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+
+        if sync {
+            writer.get_ref().sync_all()?;
+        }
+
+        std::fs::rename(&temp_path, path)
+    })();
+
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(error);
+    }
+
+    #[cfg(unix)]
+    if sync {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Root of a walk the way **options** say, see [HashOptions::canonicalize](crate::HashOptions::canonicalize)
+pub(crate) fn resolve_root(
+    path: &Path,
+    options: &crate::HashOptions,
+) -> Result<PathBuf, IOError> {
+    match options.canonicalize || options.no_follow {
+        true => path.canonicalize(),
+        false => Ok(path.to_path_buf()),
+    }
+}
+
+/// Folder that recorded paths are relative to, see [HashOptions::base](crate::HashOptions::base)
+pub(crate) fn resolve_base(
+    dir: &Path,
+    options: &crate::HashOptions,
+) -> Result<PathBuf, IOError> {
+    match &options.base {
+        Some(base) => resolve_root(base, options),
+        None => Ok(dir.to_path_buf()),
+    }
+}
+
+/// Path of a file as it is recorded: relative to **base** and with `/` separators,
+/// see [HashOptions::native_separators](crate::HashOptions::native_separators)
+pub(crate) fn relative_path(
+    path: &Path,
+    base: &Path,
+    options: &crate::HashOptions,
+) -> PathBuf {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+
+    match options.native_separators || std::path::MAIN_SEPARATOR == '/' {
+        true => relative.to_path_buf(),
+        false => PathBuf::from(
+            relative
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/"),
+        ),
+    }
+}
+
+/// Walk **folder** the way **options** say
+pub(crate) fn walk<P: AsRef<Path>>(
+    path: P,
+    options: &crate::HashOptions,
+) -> Walk {
+    let path = path.as_ref();
+    let mut walk = match options.parallel_walk {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        _ if options.no_follow => crate::openat::walk(path),
+        true => walk_in_pool(path, &options.pool()),
+        false => get_all_file_with_metadata(path),
+    };
+
+    #[cfg(windows)]
+    {
+        let mut visited: Vec<PathBuf> =
+            path.canonicalize().into_iter().collect();
+        apply_reparse_policy(&mut walk, options, &mut visited);
+    }
+
+    if let Some(jail) = &options.jail {
+        let (inside, outside): (Vec<_>, Vec<_>) = walk
+            .files
+            .into_iter()
+            .partition(|entry| jailed_path(&entry.path, jail).is_ok());
+
+        walk.files = inside;
+        for entry in outside {
+            let error = jailed_path(&entry.path, jail).unwrap_err();
+            walk.errors.push((entry.path, error));
+        }
+    }
+
+    if !options.skip_names.is_empty() {
+        let (files, skipped): (Vec<_>, Vec<_>) = walk
+            .files
+            .into_iter()
+            .partition(|entry| !options.skips_file(&entry.path));
+
+        walk.files = files;
+        for entry in skipped {
+            walk.skipped.push((entry.path, SkipReason::Filter));
+        }
+    }
+
+    if options.deterministic {
+        walk.files.sort_by(|a, b| a.path.cmp(&b.path));
+        walk.errors.sort_by(|a, b| a.0.cmp(&b.0));
+        walk.skipped.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    walk
+}
+
+/// Walk **path** in the order of the paths, while reading it if **options** allow it, see [SortedWalk]
+///
+/// With [HashOptions::no_follow](crate::HashOptions::no_follow) or a
+/// [HashOptions::reparse_points](crate::HashOptions::reparse_points) policy other than the default
+/// the folder is walked up front with [walk], errors and skipped entries then come first
+pub(crate) fn walk_sorted(
+    path: &Path,
+    options: &crate::HashOptions,
+) -> Box<dyn Iterator<Item = WalkEntry>> {
+    let up_front = (cfg!(any(target_os = "linux", target_os = "macos"))
+        && options.no_follow)
+        || (cfg!(windows)
+            && options.reparse_points != crate::options::ReparsePolicy::Skip);
+
+    if !up_front {
+        return Box::new(SortedWalk::new(path, options));
+    }
+
+    let mut walk = walk(path, options);
+    walk.files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let errors = walk.errors.into_iter().map(|(_, e)| WalkEntry::Error(e));
+    let skipped = walk
+        .skipped
+        .into_iter()
+        .map(|(path, reason)| WalkEntry::Skipped(path, reason));
+    Box::new(
+        errors
+            .chain(skipped)
+            .chain(walk.files.into_iter().map(WalkEntry::File)),
+    )
+}
+
+/// Entry found by [SortedWalk]
+pub(crate) enum WalkEntry {
+    File(FileEntry),
+    Skipped(PathBuf, SkipReason),
+    Error(IOError),
+}
+
+/// Depth-first walk that yields the files of a folder in path order while it reads it
+///
+/// Only the sorted names of the folders on the way down are held, so memory grows with the depth
+/// of the tree and the size of its largest folder, not with the number of files.
+/// Symbolic links below the folder are not followed, files outside of [HashOptions::jail](crate::HashOptions::jail) are errors
+/// and files of [HashOptions::skip_file_name](crate::HashOptions::skip_file_name) are skipped
+pub(crate) struct SortedWalk {
+    /// Entries left in every open folder, sorted in reverse so the next one is popped
+    stack: Vec<Vec<PathBuf>>,
+    pending: Option<WalkEntry>,
+    options: crate::HashOptions,
+}
+
+impl SortedWalk {
+    pub(crate) fn new(path: &Path, options: &crate::HashOptions) -> Self {
+        let mut walk = Self {
+            stack: Vec::new(),
+            pending: None,
+            options: options.clone(),
+        };
+
+        // Like walkdir, a symbolic link as the root itself is followed
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => walk.enter(path),
+            Ok(metadata) if metadata.is_file() => {
+                walk.pending = Some(walk.file(path.to_path_buf(), metadata))
+            }
+            Ok(metadata) => {
+                walk.pending =
+                    Some(skipped(path.to_path_buf(), metadata.file_type()))
+            }
+            Err(error) => {
+                walk.pending = Some(WalkEntry::Error(error_at(path, error)))
+            }
+        }
+
+        walk
+    }
+
+    fn enter(&mut self, dir: &Path) {
+        let entries = std::fs::read_dir(dir).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        });
+
+        match entries {
+            Ok(mut entries) => {
+                entries.sort_unstable_by(|a, b| b.cmp(a));
+                self.stack.push(entries);
+            }
+            Err(error) => {
+                self.pending = Some(WalkEntry::Error(error_at(dir, error)))
+            }
+        }
+    }
+
+    fn file(&self, path: PathBuf, metadata: std::fs::Metadata) -> WalkEntry {
+        if self.options.skips_file(&path) {
+            return WalkEntry::Skipped(path, SkipReason::Filter);
+        }
+
+        match self
+            .options
+            .jail
+            .as_deref()
+            .map(|jail| jailed_path(&path, jail))
+        {
+            Some(Err(error)) => WalkEntry::Error(error),
+            _ => WalkEntry::File(FileEntry { path, metadata }),
+        }
+    }
+}
+
+impl Iterator for SortedWalk {
+    type Item = WalkEntry;
+
+    fn next(&mut self) -> Option<WalkEntry> {
+        loop {
+            if let Some(entry) = self.pending.take() {
+                return Some(entry);
+            }
+
+            let Some(path) = self.stack.last_mut()?.pop() else {
+                self.stack.pop();
+                continue;
+            };
+
+            match std::fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_file() => {
+                    return Some(self.file(path, metadata))
+                }
+                Ok(metadata) if metadata.is_dir() => self.enter(&path),
+                Ok(metadata) => {
+                    return Some(skipped(path, metadata.file_type()))
+                }
+                Err(error) => {
+                    return Some(WalkEntry::Error(error_at(&path, error)))
+                }
+            }
+        }
+    }
+}
+
+fn skipped(path: PathBuf, file_type: std::fs::FileType) -> WalkEntry {
+    WalkEntry::Skipped(path, skip_reason(file_type))
+}
+
+fn skip_reason(file_type: std::fs::FileType) -> SkipReason {
+    match file_type.is_symlink() {
+        true => SkipReason::Symlink,
+        false => SkipReason::SpecialFile,
+    }
+}
+
+/// Handle the reparse points **walk** left out, see [HashOptions::reparse_points](crate::HashOptions::reparse_points).
+/// **visited** are the canonical folders that are already part of the walk
+#[cfg(windows)]
+fn apply_reparse_policy(
+    walk: &mut Walk,
+    options: &crate::HashOptions,
+    visited: &mut Vec<PathBuf>,
+) {
+    use crate::options::ReparsePolicy;
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+    for (path, reason) in std::mem::take(&mut walk.skipped) {
+        let is_reparse_point =
+            std::fs::symlink_metadata(&path).is_ok_and(|metadata| {
+                metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+            });
+
+        match options.reparse_points {
+            _ if !is_reparse_point => walk.skipped.push((path, reason)),
+            ReparsePolicy::Skip => {
+                walk.skipped.push((path, SkipReason::ReparsePoint))
+            }
+            ReparsePolicy::Error => walk.push_error(
+                path,
+                IOError::new(
+                    std::io::ErrorKind::Unsupported,
+                    "reparse point is not followed",
+                ),
+            ),
+            ReparsePolicy::Follow => {
+                follow_reparse_point(walk, path, options, visited)
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn follow_reparse_point(
+    walk: &mut Walk,
+    path: PathBuf,
+    options: &crate::HashOptions,
+    visited: &mut Vec<PathBuf>,
+) {
+    let target = match path.canonicalize() {
+        Ok(target) => target,
+        Err(error) => return walk.push_error(path, error),
+    };
+
+    let metadata = match std::fs::metadata(&target) {
+        Ok(metadata) => metadata,
+        Err(error) => return walk.push_error(path, error),
+    };
+
+    if metadata.is_file() {
+        walk.files.push(FileEntry { path, metadata });
+        return;
+    }
+
+    if visited.iter().any(|folder| {
+        folder.starts_with(&target) || target.starts_with(folder)
+    }) {
+        walk.skipped.push((path, SkipReason::ReparsePoint));
+        return;
+    }
+    visited.push(target.clone());
+
+    // Walk the target itself, so every walker handles it like a plain folder
+    let mut inner = match options.parallel_walk {
+        true => walk_in_pool(&target, &options.pool()),
+        false => get_all_file_with_metadata(&target),
+    };
+    apply_reparse_policy(&mut inner, options, visited);
+
+    let rebase = |inner: PathBuf| match inner.strip_prefix(&target) {
+        Ok(relative) => path.join(relative),
+        Err(_) => inner,
+    };
+
+    for entry in inner.files {
+        walk.files.push(FileEntry {
+            path: rebase(entry.path),
+            metadata: entry.metadata,
+        });
+    }
+    for (inner, error) in inner.errors {
+        walk.errors.push((rebase(inner), error));
+    }
+    for (inner, reason) in inner.skipped {
+        walk.skipped.push((rebase(inner), reason));
+    }
+}
+
+/// Take a shared advisory lock on **file**, it is released when the file is closed
+fn lock_shared(file: &std::fs::File) -> Result<(), IOError> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: the descriptor is owned by `file` and valid
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } == -1 {
+            return Err(IOError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Storage::FileSystem::LockFileEx;
+        use windows_sys::Win32::System::IO::OVERLAPPED;
+
+        // SAFETY: OVERLAPPED is a plain C struct, all zeroes is offset 0
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the handle is owned by `file` and valid, the call is synchronous
+        // because the file is not opened for overlapped IO.
+        // Flags 0 mean a shared lock that waits, the range is the whole file
+        let ok = unsafe {
+            LockFileEx(
+                file.as_raw_handle() as _,
+                0,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            )
+        };
+        if ok == 0 {
+            return Err(IOError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = file;
+        Err(IOError::from(std::io::ErrorKind::Unsupported))
+    }
+}
+
+/// **path** relative to **jail**, see [HashOptions::jail](crate::HashOptions::jail)
+fn jailed_path<'a>(path: &'a Path, jail: &Path) -> Result<&'a Path, IOError> {
+    path.strip_prefix(jail)
+        .ok()
+        .filter(|relative| {
+            relative.components().all(|c| {
+                matches!(c, Component::Normal(_) | Component::CurDir)
+            })
+        })
+        .ok_or_else(|| {
+            IOError::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} is outside of {}",
+                    path.display(),
+                    jail.display()
+                ),
+            )
+        })
+}
+
+fn open_jailed(path: &Path, jail: &Path) -> Result<std::fs::File, IOError> {
+    let relative = jailed_path(path, jail)?;
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        crate::openat::open_file_beneath(jail, relative).map_err(|error| {
+            match error.raw_os_error() {
+                Some(libc::ELOOP) | Some(libc::ENOTDIR) => IOError::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    format!(
+                        "{} goes through a symbolic link",
+                        path.display()
+                    ),
+                ),
+                _ => error,
+            }
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let real = jail.join(relative).canonicalize()?;
+        jailed_path(&real, &jail.canonicalize()?)?;
+        std::fs::File::open(real)
+    }
+}
+
+/// Mount points of network filesystems
+///
+/// Only **Linux** is supported, on other platforms the list is empty
+pub(crate) fn get_network_mounts() -> Vec<PathBuf> {
+    #[cfg(target_os = "linux")]
+    {
+        const NETWORK_FS: &[&str] = &[
+            "nfs",
+            "nfs4",
+            "cifs",
+            "smb3",
+            "smbfs",
+            "ncpfs",
+            "afs",
+            "9p",
+            "fuse.sshfs",
+            "fuse.rclone",
+            "ceph",
+            "glusterfs",
+        ];
+
+        let mounts =
+            std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+
+        mounts
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let mount_point = fields.nth(1)?;
+                let fs_type = fields.next()?;
+
+                NETWORK_FS
+                    .contains(&fs_type)
+                    .then(|| PathBuf::from(unescape_mount_point(mount_point)))
+            })
+            .collect()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Vec::new()
+    }
+}
+
+/// Decode `\040`-style escapes of `/proc/self/mounts`
+#[cfg(target_os = "linux")]
+fn unescape_mount_point(mount_point: &str) -> String {
+    let bytes = mount_point.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).and_then(|digits| {
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+        });
+
+        match escape {
+            Some(byte) if bytes[i] == b'\\' => {
+                result.push(byte);
+                i += 4;
+            }
+            _ => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Extra mod for **benchmark**
+#[cfg(feature = "extra_fs")]
+pub mod extra {
+    use assert_fs::{fixture::ChildPath, prelude::*};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use std::{cmp, fs::File, io::BufWriter, io::Write, path::Path};
+
+    /// Generator of the fixtures without a seed
+    ///
+    /// Random, unless the `FILE_HASHING_SEED` environment variable holds a seed,
+    /// see [HashOptions::deterministic](crate::HashOptions::deterministic)
+    fn unseeded_rng() -> ChaCha8Rng {
+        rng_from_seed_var(std::env::var("FILE_HASHING_SEED").ok())
+    }
+
+    fn rng_from_seed_var(seed: Option<String>) -> ChaCha8Rng {
+        match seed.and_then(|seed| seed.trim().parse().ok()) {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    pub fn generate_random_file(
+        size: usize,
+    ) -> (assert_fs::TempDir, ChildPath) {
+        generate_random_file_with_rng(size, &mut unseeded_rng())
+    }
+
+    /// The same as [generate_random_file], but the content only depends on **seed**
+    pub fn generate_random_file_seeded(
+        size: usize,
+        seed: u64,
+    ) -> (assert_fs::TempDir, ChildPath) {
+        generate_random_file_with_rng(
+            size,
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        )
+    }
+
+    fn generate_random_file_with_rng<R: Rng>(
+        size: usize,
+        rng: &mut R,
+    ) -> (assert_fs::TempDir, ChildPath) {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let input_file = temp.child("random_file.txt");
+
+        write_random_file(input_file.path(), size, rng);
 
-    let result: Vec<_> = walkdir
-        .into_iter()
-        .filter_map(|file| file.ok())
-        .filter(|normal_file| normal_file.metadata().unwrap().is_file())
-        .map(|x| x.into_path())
-        .collect();
+        (temp, input_file)
+    }
 
-    result
-}
+    pub fn generate_random_folder_with_files(
+        value_files: usize,
+        size: usize,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        generate_random_folder_with_files_with_rng(
+            value_files,
+            size,
+            &mut unseeded_rng(),
+        )
+    }
 
-/// Extra mod for **benchmark**
-#[cfg(feature = "extra_fs")]
-pub mod extra {
-    use assert_fs::{fixture::ChildPath, prelude::*};
-    use rand::Rng;
-    use std::{cmp, fs::File, io::BufWriter, io::Write};
+    /// The same as [generate_random_folder_with_files], but the content only depends on **seed**
+    pub fn generate_random_folder_with_files_seeded(
+        value_files: usize,
+        size: usize,
+        seed: u64,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        generate_random_folder_with_files_with_rng(
+            value_files,
+            size,
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        )
+    }
 
-    pub fn generate_random_file(
+    fn generate_random_folder_with_files_with_rng<R: Rng>(
+        value_files: usize,
         size: usize,
+        rng: &mut R,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut input_files = Vec::with_capacity(value_files);
+
+        for i in 0..value_files {
+            let input_file = temp.child(format!("random_file_{}.txt", i));
+            write_random_file(input_file.path(), size, rng);
+
+            input_files.push(input_file);
+        }
+
+        (temp, input_files)
+    }
+
+    /// Sizes of generated files
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SizeDistribution {
+        /// Every file has the same size
+        Fixed(usize),
+
+        /// Any size in `min..=max` is equally likely
+        Uniform { min: usize, max: usize },
+
+        /// Many small files and few large ones, like real home folders
+        LogUniform { min: usize, max: usize },
+    }
+
+    impl SizeDistribution {
+        pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+            match *self {
+                SizeDistribution::Fixed(size) => size,
+                SizeDistribution::Uniform { min, max } => {
+                    rng.gen_range(min..=max.max(min))
+                }
+                SizeDistribution::LogUniform { min, max } => {
+                    let low = (min.max(1) as f64).ln();
+                    let high = (max.max(min).max(1) as f64).ln();
+
+                    rng.gen_range(low..=high).exp().round() as usize
+                }
+            }
+        }
+    }
+
+    /// Generate nested tree of folders
+    ///
+    /// Every folder has **files_per_dir** files and, above **depth**, **fan_out** subfolders.
+    /// Folders are named `dir_N`, files `random_file_N.txt`
+    pub fn generate_random_tree(
+        depth: usize,
+        fan_out: usize,
+        files_per_dir: usize,
+        sizes: SizeDistribution,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        generate_random_tree_with_rng(
+            depth,
+            fan_out,
+            files_per_dir,
+            sizes,
+            &mut unseeded_rng(),
+        )
+    }
+
+    /// The same as [generate_random_tree], but sizes and content only depend on **seed**
+    pub fn generate_random_tree_seeded(
+        depth: usize,
+        fan_out: usize,
+        files_per_dir: usize,
+        sizes: SizeDistribution,
+        seed: u64,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        generate_random_tree_with_rng(
+            depth,
+            fan_out,
+            files_per_dir,
+            sizes,
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        )
+    }
+
+    fn generate_random_tree_with_rng<R: Rng>(
+        depth: usize,
+        fan_out: usize,
+        files_per_dir: usize,
+        sizes: SizeDistribution,
+        rng: &mut R,
+    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let mut input_files = Vec::new();
+
+        let mut dirs = vec![(temp.path().to_path_buf(), 0)];
+        while let Some((dir, level)) = dirs.pop() {
+            for i in 0..files_per_dir {
+                let input_file = ChildPath::new(
+                    dir.join(format!("random_file_{}.txt", i)),
+                );
+                let size = sizes.sample(rng);
+                write_random_file(input_file.path(), size, rng);
+
+                input_files.push(input_file);
+            }
+
+            if level < depth {
+                for i in 0..fan_out {
+                    let sub_dir = dir.join(format!("dir_{}", i));
+                    std::fs::create_dir(&sub_dir).unwrap();
+
+                    dirs.push((sub_dir, level + 1));
+                }
+            }
+        }
+
+        (temp, input_files)
+    }
+
+    /// Files created by [generate_links]
+    pub struct Links {
+        /// Regular file that the links point to
+        pub file: ChildPath,
+
+        /// Symbolic link to `file`
+        pub symlink: ChildPath,
+
+        /// Symbolic link to a file that does not exist
+        pub dangling_symlink: ChildPath,
+
+        /// Symbolic link in `cycle/` to its own parent, so `cycle/loop/loop/...` never ends
+        pub cyclic_symlink: ChildPath,
+
+        /// Hard link to `file`
+        pub hardlink: ChildPath,
+    }
+
+    /// Generate folder with symbolic links (valid, dangling, cyclic) and a hard link
+    ///
+    /// Creating symbolic links on **Windows** needs developer mode or administrator rights
+    pub fn generate_links(size: usize) -> (assert_fs::TempDir, Links) {
+        let temp = assert_fs::TempDir::new().unwrap();
+
+        let file = temp.child("random_file.txt");
+        write_random_file(file.path(), size, &mut unseeded_rng());
+
+        let symlink = temp.child("symlink.txt");
+        symlink_file(file.path(), symlink.path());
+
+        let dangling_symlink = temp.child("dangling_symlink.txt");
+        symlink_file(
+            &temp.path().join("missing.txt"),
+            dangling_symlink.path(),
+        );
+
+        let cycle = temp.child("cycle");
+        cycle.create_dir_all().unwrap();
+        let cyclic_symlink = cycle.child("loop");
+        symlink_dir(cycle.path(), cyclic_symlink.path());
+
+        let hardlink = temp.child("hardlink.txt");
+        std::fs::hard_link(file.path(), hardlink.path()).unwrap();
+
+        let links = Links {
+            file,
+            symlink,
+            dangling_symlink,
+            cyclic_symlink,
+            hardlink,
+        };
+
+        (temp, links)
+    }
+
+    /// Part of a file generated by [generate_sparse_file]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SparseRegion {
+        /// Hole of that many bytes, reads as zeros and takes no space on disk
+        Hole(u64),
+
+        /// That many bytes of random data
+        Data(usize),
+    }
+
+    /// Generate sparse file with holes and data in the order of **layout**
+    ///
+    /// Filesystems without sparse files (e.g. FAT) store holes as zeros, the content is the same
+    pub fn generate_sparse_file(
+        layout: &[SparseRegion],
+    ) -> (assert_fs::TempDir, ChildPath) {
+        generate_sparse_file_with_rng(layout, &mut unseeded_rng())
+    }
+
+    /// The same as [generate_sparse_file], but the content only depends on **seed**
+    pub fn generate_sparse_file_seeded(
+        layout: &[SparseRegion],
+        seed: u64,
+    ) -> (assert_fs::TempDir, ChildPath) {
+        generate_sparse_file_with_rng(
+            layout,
+            &mut ChaCha8Rng::seed_from_u64(seed),
+        )
+    }
+
+    fn generate_sparse_file_with_rng<R: Rng>(
+        layout: &[SparseRegion],
+        rng: &mut R,
     ) -> (assert_fs::TempDir, ChildPath) {
+        use std::io::{Seek, SeekFrom};
+
         let temp = assert_fs::TempDir::new().unwrap();
-        let input_file = temp.child("random_file.txt");
+        let input_file = temp.child("sparse_file.bin");
+
+        let mut file = File::create(input_file.path()).unwrap();
+        let mut buffer = [0; 1024];
+        let mut len = 0;
+
+        for region in layout {
+            match *region {
+                SparseRegion::Hole(size) => {
+                    file.seek(SeekFrom::Current(size as i64)).unwrap();
+                    len += size;
+                }
+                SparseRegion::Data(size) => {
+                    let mut remaining_size = size;
+
+                    while remaining_size > 0 {
+                        let to_write = cmp::min(remaining_size, buffer.len());
+                        let buffer = &mut buffer[..to_write];
+                        rng.fill(buffer);
+                        file.write_all(buffer).unwrap();
+
+                        remaining_size -= to_write;
+                    }
+
+                    len += size as u64;
+                }
+            }
+        }
+
+        // A hole at the end is not created by seeking alone
+        file.set_len(len).unwrap();
 
-        let f = File::create(input_file.path()).unwrap();
+        (temp, input_file)
+    }
+
+    /// [Read] of pseudo-random bytes that are generated on the fly, nothing is written to disk
+    ///
+    /// Gives the same bytes as [generate_random_file_seeded] with the same **seed**,
+    /// so throughput benchmarks of reader-based functions (e.g. [hash_update](crate::io::hash_update))
+    /// can use many gigabytes without being bound by the speed of the temporary folder
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::{fs::extra::RandomReader, io::hash_update};
+    ///
+    /// let mut hash = Blake2s256::new();
+    /// let reader = RandomReader::new(1024 * 1024, 42);
+    ///
+    /// assert_eq!(hash_update(reader, &mut hash).unwrap(), 1024 * 1024);
+    /// ```
+    pub struct RandomReader {
+        rng: ChaCha8Rng,
+        block: [u8; 1024],
+        position: usize,
+        remaining: u64,
+    }
+
+    impl RandomReader {
+        /// Reader of **len** bytes
+        pub fn new(len: u64, seed: u64) -> Self {
+            Self {
+                rng: ChaCha8Rng::seed_from_u64(seed),
+                block: [0; 1024],
+                position: 1024,
+                remaining: len,
+            }
+        }
+    }
+
+    impl std::io::Read for RandomReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+
+            // Whole blocks like write_random_file, the bytes don't depend on the size of **buf**
+            if self.position == self.block.len() {
+                self.rng.fill(&mut self.block[..]);
+                self.position = 0;
+            }
+
+            let len = cmp::min(buf.len(), self.block.len() - self.position);
+            let len = cmp::min(len as u64, self.remaining) as usize;
+            buf[..len].copy_from_slice(
+                &self.block[self.position..self.position + len],
+            );
+
+            self.position += len;
+            self.remaining -= len as u64;
+            Ok(len)
+        }
+    }
+
+    fn symlink_file(original: &Path, link: &Path) {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(original, link).unwrap();
+
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(original, link).unwrap();
+    }
+
+    fn symlink_dir(original: &Path, link: &Path) {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(original, link).unwrap();
+
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_dir(original, link).unwrap();
+    }
+
+    fn write_random_file<R: Rng>(path: &Path, size: usize, rng: &mut R) {
+        let f = File::create(path).unwrap();
         let mut writer = BufWriter::new(f);
 
-        let mut rng = rand::thread_rng();
         let mut buffer = [0; 1024];
         let mut remaining_size = size;
 
@@ -46,39 +1342,636 @@ pub mod extra {
 
             remaining_size -= to_write;
         }
+    }
+
+    /// [proptest] strategies for directory trees
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::fs::extra::strategy::{tree, TreeParams};
+    /// use proptest::prelude::*;
+    ///
+    /// use sha2::{Digest, Sha256};
+    /// use file_hashing::{folder::get_hash_folder_with_files, HashOptions};
+    ///
+    /// proptest! {
+    ///     #[test]
+    ///     fn every_file_is_hashed(spec in tree(TreeParams::default())) {
+    ///         prop_assume!(!spec.files().is_empty());
+    ///         let temp_dir = spec.create();
+    ///
+    ///         let (_, files) =
+    ///             get_hash_folder_with_files(&temp_dir, Sha256::new, &HashOptions::new(4), |_| {})
+    ///                 .unwrap();
+    ///         prop_assert_eq!(files.len(), spec.files().len());
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "proptest")]
+    pub mod strategy {
+        use proptest::prelude::*;
+        use proptest::sample::Index;
+        use std::collections::BTreeMap;
+        use std::path::{Path, PathBuf};
+
+        /// Entry of a [Tree]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum Node {
+            File(Vec<u8>),
+            Dir(BTreeMap<String, Node>),
+
+            /// Symbolic link to a file of the tree, relative to the root
+            Symlink(PathBuf),
+        }
 
-        (temp, input_file)
+        /// Directory tree that can be created on disk
+        #[derive(Debug, Clone, Default, PartialEq, Eq)]
+        pub struct Tree {
+            pub root: BTreeMap<String, Node>,
+        }
+
+        impl Tree {
+            /// Create the tree in a new temporary folder
+            pub fn create(&self) -> assert_fs::TempDir {
+                let temp = assert_fs::TempDir::new().unwrap();
+                self.create_in(temp.path()).unwrap();
+                temp
+            }
+
+            /// Create the tree in **dir**, which must exist
+            pub fn create_in(&self, dir: &Path) -> std::io::Result<()> {
+                create_entries(dir, dir, &self.root)
+            }
+
+            /// Regular files with their content, relative to the root and sorted
+            pub fn files(&self) -> Vec<(PathBuf, &[u8])> {
+                let mut files = Vec::new();
+                collect_files(Path::new(""), &self.root, &mut files);
+                files
+            }
+
+            /// Symbolic links with their targets, relative to the root and sorted
+            pub fn symlinks(&self) -> Vec<(PathBuf, &Path)> {
+                self.root
+                    .iter()
+                    .filter_map(|(name, node)| match node {
+                        Node::Symlink(target) => {
+                            Some((PathBuf::from(name), target.as_path()))
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            }
+        }
+
+        /// Limits of [tree]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct TreeParams {
+            /// Levels of folders below the root
+            pub max_depth: u32,
+
+            /// Entries of a folder
+            pub max_entries: usize,
+
+            /// Bytes of a file
+            pub max_file_size: usize,
+
+            /// Symbolic links in the root, they always point to a file of the tree
+            pub max_symlinks: usize,
+        }
+
+        impl Default for TreeParams {
+            fn default() -> Self {
+                Self {
+                    max_depth: 3,
+                    max_entries: 5,
+                    max_file_size: 4096,
+                    max_symlinks: 0,
+                }
+            }
+        }
+
+        /// Names that are valid on every supported platform
+        ///
+        /// Lowercase only, so case-insensitive filesystems don't merge entries
+        pub fn name() -> impl Strategy<Value = String> {
+            "[a-z0-9_][a-z0-9_-]{0,11}(\\.[a-z0-9]{1,3})?"
+        }
+
+        /// Content of a file with at most **max_size** bytes
+        pub fn content(max_size: usize) -> impl Strategy<Value = Vec<u8>> {
+            prop::collection::vec(any::<u8>(), 0..=max_size)
+        }
+
+        /// Arbitrary trees within **params**
+        pub fn tree(params: TreeParams) -> impl Strategy<Value = Tree> {
+            let file = content(params.max_file_size).prop_map(Node::File);
+            let node = file.prop_recursive(
+                params.max_depth,
+                (params.max_entries as u32).pow(params.max_depth.min(4)),
+                params.max_entries as u32,
+                move |inner| {
+                    prop::collection::btree_map(
+                        name(),
+                        inner,
+                        0..=params.max_entries,
+                    )
+                    .prop_map(Node::Dir)
+                },
+            );
+
+            let root = prop::collection::btree_map(
+                name(),
+                node,
+                0..=params.max_entries,
+            );
+            let symlinks = prop::collection::vec(
+                (name(), any::<Index>()),
+                0..=params.max_symlinks,
+            );
+
+            (root, symlinks).prop_map(|(root, symlinks)| {
+                let mut tree = Tree { root };
+                let targets: Vec<PathBuf> =
+                    tree.files().into_iter().map(|(path, _)| path).collect();
+
+                if !targets.is_empty() {
+                    for (name, index) in symlinks {
+                        let target = index.get(&targets).clone();
+                        tree.root
+                            .entry(name)
+                            .or_insert(Node::Symlink(target));
+                    }
+                }
+
+                tree
+            })
+        }
+
+        fn create_entries(
+            root: &Path,
+            dir: &Path,
+            entries: &BTreeMap<String, Node>,
+        ) -> std::io::Result<()> {
+            for (name, node) in entries {
+                let path = dir.join(name);
+
+                match node {
+                    Node::File(content) => std::fs::write(&path, content)?,
+                    Node::Dir(entries) => {
+                        std::fs::create_dir(&path)?;
+                        create_entries(root, &path, entries)?;
+                    }
+                    Node::Symlink(target) => {
+                        super::symlink_file(&root.join(target), &path)
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        fn collect_files<'a>(
+            dir: &Path,
+            entries: &'a BTreeMap<String, Node>,
+            files: &mut Vec<(PathBuf, &'a [u8])>,
+        ) {
+            for (name, node) in entries {
+                match node {
+                    Node::File(content) => {
+                        files.push((dir.join(name), content))
+                    }
+                    Node::Dir(entries) => {
+                        collect_files(&dir.join(name), entries, files)
+                    }
+                    Node::Symlink(_) => {}
+                }
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::{tree, TreeParams};
+            use crate::{folder, HashOptions};
+            use proptest::prelude::*;
+            use sha2::{Digest, Sha256};
+
+            proptest! {
+                #![proptest_config(ProptestConfig::with_cases(16))]
+
+                #[test]
+                fn create(spec in tree(TreeParams { max_symlinks: 2, ..TreeParams::default() })) {
+                    let temp_dir = spec.create();
+                    let walk = crate::fs::walk(&temp_dir, &HashOptions::new(2));
+
+                    let mut paths: Vec<_> = walk
+                        .paths()
+                        .into_iter()
+                        .map(|path| path.strip_prefix(&temp_dir).unwrap().to_path_buf())
+                        .collect();
+                    paths.sort();
+                    let expected: Vec<_> =
+                        spec.files().into_iter().map(|(path, _)| path).collect();
+
+                    prop_assert_eq!(paths, expected);
+                    prop_assert_eq!(walk.skipped.len(), spec.symlinks().len());
+                }
+
+                /// The folder hash doesn't depend on traversal order or scheduling
+                #[test]
+                fn folder_hash_invariant(spec in tree(TreeParams::default())) {
+                    prop_assume!(!spec.files().is_empty());
+                    let temp_dir = spec.create();
+
+                    let hash = |options: &HashOptions| {
+                        folder::get_hash_folder_with_factory(&temp_dir, Sha256::new, options, |_| {})
+                            .unwrap()
+                    };
+
+                    let expected = hash(&HashOptions::new(1).deterministic(true));
+                    prop_assert_eq!(hash(&HashOptions::new(4)), expected.clone());
+                    prop_assert_eq!(hash(&HashOptions::new(0)), expected);
+                }
+            }
+        }
     }
 
-    pub fn generate_random_folder_with_files(
-        value_files: usize,
-        size: usize,
-    ) -> (assert_fs::TempDir, Vec<ChildPath>) {
-        let temp = assert_fs::TempDir::new().unwrap();
-        let mut input_files = Vec::with_capacity(value_files);
+    #[cfg(test)]
+    mod tests {
+        use super::SizeDistribution;
 
-        for i in 0..value_files {
-            let input_file = temp.child(format!("random_file_{}.txt", i));
+        #[test]
+        fn rng_from_seed_var() {
+            use rand::Rng;
+
+            let mut a = super::rng_from_seed_var(Some(String::from("42")));
+            let mut b = super::rng_from_seed_var(Some(String::from("42")));
+            assert_eq!(a.gen::<u64>(), b.gen::<u64>());
+
+            let mut c = super::rng_from_seed_var(Some(String::from("x")));
+            let mut d = super::rng_from_seed_var(None);
+            assert_ne!(c.gen::<u64>(), d.gen::<u64>());
+        }
+
+        #[test]
+        fn random_reader() {
+            use std::io::Read;
+
+            let (_temp_dir, path) =
+                super::generate_random_file_seeded(5000, 7);
+            let expected = std::fs::read(&path).unwrap();
+
+            let mut data = Vec::new();
+            super::RandomReader::new(5000, 7)
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, expected);
+
+            // Odd reads give the same bytes
+            let mut reader = super::RandomReader::new(5000, 7);
+            let mut data = Vec::new();
+            let mut buf = [0; 333];
+            loop {
+                match reader.read(&mut buf).unwrap() {
+                    0 => break,
+                    len => data.extend_from_slice(&buf[..len]),
+                }
+            }
+            assert_eq!(data, expected);
+        }
+
+        #[test]
+        fn generate_random_tree() {
+            let (temp_dir, paths) = super::generate_random_tree(
+                2,
+                3,
+                2,
+                SizeDistribution::Uniform { min: 10, max: 100 },
+            );
+
+            // 1 + 3 + 9 folders with 2 files each
+            assert_eq!(paths.len(), 26);
+            assert!(temp_dir
+                .path()
+                .join("dir_2/dir_1/random_file_0.txt")
+                .is_file());
+
+            for path in paths {
+                let size = std::fs::metadata(path.path()).unwrap().len();
+                assert!((10..=100).contains(&size));
+            }
+        }
+
+        #[test]
+        fn generate_links() {
+            let (temp_dir, links) = super::generate_links(100);
+
+            assert!(links.symlink.path().is_file());
+            assert!(!links.dangling_symlink.path().exists());
+            assert!(links.cyclic_symlink.path().join("loop/loop").is_dir());
+            assert_eq!(
+                std::fs::read(links.hardlink.path()).unwrap(),
+                std::fs::read(links.file.path()).unwrap()
+            );
+
+            // Symbolic links are not followed
+            let paths = crate::fs::get_all_file_from_folder(&temp_dir);
+            assert_eq!(paths.len(), 2);
+        }
+
+        #[test]
+        fn generate_sparse_file() {
+            use super::SparseRegion;
+
+            let (_temp_dir, path) = super::generate_sparse_file(&[
+                SparseRegion::Data(100),
+                SparseRegion::Hole(1 << 20),
+                SparseRegion::Data(100),
+                SparseRegion::Hole(1 << 20),
+            ]);
+
+            let data = std::fs::read(path.path()).unwrap();
+            assert_eq!(data.len(), 200 + (2 << 20));
+            assert!(data[100..100 + (1 << 20)].iter().all(|byte| *byte == 0));
+            assert!(data[200 + (1 << 20)..].iter().all(|byte| *byte == 0));
+        }
+
+        #[test]
+        fn seeded() {
+            let read = |path: &std::path::Path| std::fs::read(path).unwrap();
 
-            let f = File::create(input_file.path()).unwrap();
-            let mut writer = BufWriter::new(f);
+            let (_temp_dir1, path1) =
+                super::generate_random_file_seeded(5000, 42);
+            let (_temp_dir2, path2) =
+                super::generate_random_file_seeded(5000, 42);
+            let (_temp_dir3, path3) =
+                super::generate_random_file_seeded(5000, 43);
+            assert_eq!(read(path1.path()), read(path2.path()));
+            assert_ne!(read(path1.path()), read(path3.path()));
+
+            let sizes = SizeDistribution::LogUniform {
+                min: 1,
+                max: 10_000,
+            };
+            let (_temp_dir1, paths1) =
+                super::generate_random_tree_seeded(2, 2, 2, sizes, 7);
+            let (_temp_dir2, paths2) =
+                super::generate_random_tree_seeded(2, 2, 2, sizes, 7);
+            for (path1, path2) in paths1.iter().zip(paths2.iter()) {
+                assert_eq!(read(path1.path()), read(path2.path()));
+            }
+        }
 
+        #[test]
+        fn size_distribution() {
             let mut rng = rand::thread_rng();
-            let mut buffer = [0; 1024];
-            let mut remaining_size = size;
 
-            while remaining_size > 0 {
-                let to_write = cmp::min(remaining_size, buffer.len());
-                let buffer = &mut buffer[..to_write];
-                rng.fill(buffer);
-                writer.write_all(buffer).unwrap();
+            for _ in 0..100 {
+                let size = SizeDistribution::LogUniform {
+                    min: 1,
+                    max: 1 << 20,
+                }
+                .sample(&mut rng);
+                assert!((1..=1 << 20).contains(&size));
+            }
+
+            assert_eq!(SizeDistribution::Fixed(7).sample(&mut rng), 7);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extra;
+
+    #[test]
+    fn write_atomic() {
+        use std::io::Write;
 
-                remaining_size -= to_write;
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let path = temp_dir.path().join("file");
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    crate::fs::write_atomic(path, false, |writer| {
+                        writer.write_all(&[i; 4096])
+                    })
+                    .unwrap()
+                });
             }
+        });
 
-            input_files.push(input_file);
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(data.len(), 4096);
+        assert!(data.iter().all(|byte| *byte == data[0]));
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::Permissions::from_mode(0o640);
+            std::fs::set_permissions(&path, mode).unwrap();
+            crate::fs::write_atomic(&path, true, |writer| {
+                writer.write_all(b"new")
+            })
+            .unwrap();
+
+            let metadata = std::fs::metadata(&path).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+            assert_eq!(std::fs::read(&path).unwrap(), b"new");
         }
+    }
 
-        (temp, input_files)
+    #[test]
+    fn get_all_file_with_metadata() {
+        let (temp_dir, paths) = extra::generate_random_tree(
+            1,
+            2,
+            3,
+            extra::SizeDistribution::Fixed(100),
+        );
+
+        let entries = crate::fs::get_all_file_with_metadata(&temp_dir).files;
+        assert_eq!(entries.len(), paths.len());
+        assert!(entries.iter().all(|entry| entry.size() == 100));
+        assert!(entries.iter().all(|entry| entry.modified().is_some()));
+    }
+
+    #[test]
+    fn deterministic_walk() {
+        let (temp_dir, _paths) = extra::generate_random_tree(
+            2,
+            3,
+            3,
+            extra::SizeDistribution::Fixed(1),
+        );
+        let options = crate::HashOptions::new(4).deterministic(true);
+
+        let walk = crate::fs::walk(&temp_dir, &options);
+        let mut expected = walk.paths();
+        expected.sort();
+        assert_eq!(walk.paths(), expected);
+    }
+
+    #[test]
+    fn get_all_file_with_metadata_parallel() {
+        let (temp_dir, _paths) = extra::generate_random_tree(
+            3,
+            3,
+            2,
+            extra::SizeDistribution::Fixed(10),
+        );
+
+        let mut expected = crate::fs::get_all_file_from_folder(&temp_dir);
+        expected.sort();
+
+        let walk =
+            crate::fs::get_all_file_with_metadata_parallel(&temp_dir, 4);
+        assert_eq!(walk.paths(), expected);
+        assert!(walk.errors.is_empty());
+
+        let options = crate::HashOptions::new(4).numa(true);
+        let walk = crate::fs::get_all_file_with_metadata_parallel_with(
+            &temp_dir, &options,
+        );
+        assert_eq!(walk.paths(), expected);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn open_file_with_lock() {
+        use std::os::unix::io::AsRawFd;
+
+        let try_flock = |file: &std::fs::File, operation| {
+            // SAFETY: the descriptor is owned by `file` and valid
+            unsafe {
+                libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) == 0
+            }
+        };
+
+        let (_temp_dir, path) = extra::generate_random_file(10);
+        let options = crate::HashOptions::new(1).lock(true);
+
+        let file = crate::fs::open_file(path.path(), &options).unwrap();
+        let writer = std::fs::File::open(path.path()).unwrap();
+        assert!(!try_flock(&writer, libc::LOCK_EX));
+        assert!(try_flock(&writer, libc::LOCK_SH));
+
+        drop(file);
+        assert!(try_flock(&writer, libc::LOCK_UN));
+        assert!(try_flock(&writer, libc::LOCK_EX));
+    }
+
+    #[test]
+    fn relative_path() {
+        let options = crate::HashOptions::new(1);
+        let base = std::path::Path::new("root");
+        let path = base.join("photos").join("cat.png");
+
+        assert_eq!(
+            crate::fs::relative_path(&path, base, &options).to_str(),
+            Some("photos/cat.png")
+        );
+        assert_eq!(
+            crate::fs::relative_path(
+                &path,
+                base,
+                &options.native_separators(true)
+            ),
+            std::path::Path::new("photos").join("cat.png")
+        );
+    }
+
+    #[test]
+    fn jail() {
+        let root = assert_fs::TempDir::new().unwrap();
+        let upload = root.path().join("upload");
+        std::fs::create_dir(&upload).unwrap();
+        std::fs::write(upload.join("file"), "file").unwrap();
+        std::fs::write(root.path().join("secret"), "secret").unwrap();
+
+        let options = crate::HashOptions::new(1).jail(&upload);
+        let denied = |path: std::path::PathBuf| {
+            let error = crate::fs::open_file(path, &options).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        };
+
+        assert!(crate::fs::open_file(upload.join("file"), &options).is_ok());
+        denied(upload.join("../secret"));
+        denied(root.path().join("secret"));
+
+        #[cfg(unix)]
+        {
+            let secret = root.path().join("secret");
+            std::os::unix::fs::symlink(secret, upload.join("link")).unwrap();
+            denied(upload.join("link"));
+        }
+
+        let walk = crate::fs::walk(upload.join(".."), &options);
+        assert!(walk.files.is_empty());
+        assert!(walk.errors.iter().all(|(_, error)| {
+            error.kind() == std::io::ErrorKind::PermissionDenied
+        }));
+        assert_eq!(walk.errors.len(), 2);
+    }
+
+    #[test]
+    fn reparse_points() {
+        use crate::options::ReparsePolicy;
+
+        let root = assert_fs::TempDir::new().unwrap();
+        std::fs::create_dir(root.path().join("dir")).unwrap();
+        std::fs::write(root.path().join("dir/file"), "file").unwrap();
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(
+            root.path().join("dir"),
+            root.path().join("link"),
+        )
+        .unwrap();
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_dir(
+            root.path().join("dir"),
+            root.path().join("link"),
+        )
+        .unwrap();
+
+        let walk_with = |policy| {
+            let options = crate::HashOptions::new(1).reparse_points(policy);
+            crate::fs::walk(root.path(), &options)
+        };
+
+        let walk = walk_with(ReparsePolicy::Skip);
+        assert_eq!(walk.files.len(), 1);
+        assert_eq!(walk.skipped.len(), 1);
+
+        let walk = walk_with(ReparsePolicy::Follow);
+        #[cfg(windows)]
+        assert_eq!(walk.files.len(), 2);
+        #[cfg(unix)]
+        assert_eq!(
+            walk.skipped,
+            vec![(root.path().join("link"), crate::SkipReason::Symlink)]
+        );
+    }
+
+    #[test]
+    fn walk_errors() {
+        let (temp_dir, _path) = extra::generate_random_file(10);
+        let missing = temp_dir.path().join("missing");
+
+        let walk = crate::fs::get_all_file_with_metadata(&missing);
+        assert!(walk.files.is_empty());
+        assert_eq!(walk.errors.len(), 1);
+        assert_eq!(walk.errors[0].0, missing);
+        assert_eq!(walk.errors[0].1.kind(), std::io::ErrorKind::NotFound);
+
+        let walk =
+            crate::fs::get_all_file_with_metadata_parallel(&missing, 2);
+        assert_eq!(walk.errors.len(), 1);
     }
 }
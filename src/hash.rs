@@ -0,0 +1,289 @@
+//! Typed digests
+
+use super::*;
+use crate::encoding::{Encoder, Encoding};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Digest together with the name of its algorithm
+///
+/// Two hashes are only equal if both the algorithm and the bytes are,
+/// so digests of different algorithms are never mixed up.
+/// The text form is `ALGORITHM:hex`, e.g. `SHA-256:e3b0c442...`, or only `hex` if the algorithm is unknown
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::hash::FileHash;
+///
+/// let result = FileHash::from_file("/home/gladi/test-hashing.txt", &Sha256::new()).unwrap();
+/// assert_eq!(result.algorithm(), Some("SHA-256"));
+///
+/// let expected: FileHash = std::fs::read_to_string("/home/gladi/test-hashing.txt.sum")
+///     .unwrap()
+///     .parse()
+///     .unwrap();
+/// assert_eq!(result, expected);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileHash {
+    algorithm: Option<String>,
+    bytes: Vec<u8>,
+}
+
+impl FileHash {
+    /// Hash of **algorithm** with the digest **bytes**
+    ///
+    /// Names of the algorithms of [selftest](crate::selftest) are normalized, e.g. `sha256` becomes `SHA-256`
+    pub fn new(algorithm: Option<&str>, bytes: Vec<u8>) -> Self {
+        let algorithm = algorithm.map(|name| {
+            crate::selftest::canonical_name(name)
+                .map_or_else(|| name.to_string(), String::from)
+        });
+
+        Self { algorithm, bytes }
+    }
+
+    /// Finish a copy of **hash**, the algorithm is recognized with [identify](crate::selftest::identify)
+    pub fn from_hasher<HashType>(hash: &HashType) -> Self
+    where
+        HashType: DynDigest + Clone,
+    {
+        let bytes = hash.box_clone().finalize().to_vec();
+        Self::new(crate::selftest::identify(hash), bytes)
+    }
+
+    /// Hash **file** with a copy of **hash**, see [get_hash_file]
+    pub fn from_file<HashType, P>(
+        path: P,
+        hash: &HashType,
+    ) -> Result<Self, IOError>
+    where
+        HashType: DynDigest + Clone,
+        P: AsRef<Path>,
+    {
+        Self::from_reader(std::fs::File::open(path)?, hash)
+    }
+
+    /// Hash everything from **reader** with a copy of **hash**
+    pub fn from_reader<HashType, R>(
+        reader: R,
+        hash: &HashType,
+    ) -> Result<Self, IOError>
+    where
+        HashType: DynDigest + Clone,
+        R: Read,
+    {
+        let mut hash = hash.clone();
+        crate::io::hash_update(reader, &mut hash)?;
+        Ok(Self::from_hasher(&hash))
+    }
+
+    /// Parse **text** as the digest of **algorithm** in **hex**, either case
+    ///
+    /// # Error
+    ///
+    /// * If **text** is not hex, the **IOErrorKind::InvalidData** error will be returned
+    pub fn from_hex(
+        algorithm: Option<&str>,
+        text: &str,
+    ) -> Result<Self, IOError> {
+        let bytes = data_encoding::HEXLOWER_PERMISSIVE
+            .decode(text.trim().as_bytes())
+            .map_err(|error| IOError::new(IOErrorKind::InvalidData, error))?;
+
+        Ok(Self::new(algorithm, bytes))
+    }
+
+    /// Name of the algorithm, `None` if it is unknown
+    pub fn algorithm(&self) -> Option<&str> {
+        self.algorithm.as_deref()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The digest without the algorithm as text with **encoder**, e.g. [Encoding::Base64]
+    pub fn encode(&self, encoder: &dyn Encoder) -> String {
+        encoder.encode(&self.bytes)
+    }
+
+    /// The digest without the algorithm as **hex lower**, like [get_hash_file] returns it
+    pub fn to_hex(&self) -> String {
+        self.encode(&Encoding::HexLower)
+    }
+
+    /// The same as `==`, but the time does not depend on where the digests differ
+    ///
+    /// Use it when hashes act as authenticators, e.g. keyed BLAKE3 or HMAC.
+    /// Only the bytes are compared in constant time, the algorithm and the length are not secret
+    pub fn ct_eq(&self, other: &FileHash) -> bool {
+        self.algorithm == other.algorithm && ct_eq(&self.bytes, &other.bytes)
+    }
+
+    /// Check this hash against **expected** with [FileHash::ct_eq]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use sha2::{Digest, Sha256};
+    /// use file_hashing::hash::FileHash;
+    ///
+    /// let expected: FileHash = "SHA-256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    ///     .parse()
+    ///     .unwrap();
+    ///
+    /// FileHash::from_file("/home/gladi/upload.bin", &Sha256::new())
+    ///     .unwrap()
+    ///     .verify(&expected)
+    ///     .expect("upload was tampered with");
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * If the hashes differ, the **IOErrorKind::InvalidData** error will be returned
+    pub fn verify(&self, expected: &FileHash) -> Result<(), IOError> {
+        match self.ct_eq(expected) {
+            true => Ok(()),
+            false => {
+                Err(IOError::new(IOErrorKind::InvalidData, "hash mismatch"))
+            }
+        }
+    }
+}
+
+/// Compare **a** and **b** in time that only depends on their length
+///
+/// # Example
+///
+/// ```
+/// use file_hashing::hash::ct_eq;
+///
+/// assert!(ct_eq(b"digest", b"digest"));
+/// assert!(!ct_eq(b"digest", b"digesT"));
+/// ```
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y));
+
+    // Keeps the compiler from turning the fold into an early-exit loop
+    std::hint::black_box(diff) == 0
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.algorithm {
+            Some(algorithm) => write!(f, "{}:{}", algorithm, self.to_hex()),
+            None => write!(f, "{}", self.to_hex()),
+        }
+    }
+}
+
+impl FromStr for FileHash {
+    type Err = IOError;
+
+    /// Parse `ALGORITHM:hex` or `hex`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().rsplit_once(':') {
+            Some((algorithm, hex)) => Self::from_hex(Some(algorithm), hex),
+            None => Self::from_hex(None, text),
+        }
+    }
+}
+
+impl Serialize for FileHash {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileHash {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileHash;
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+    use sha2::Sha256;
+
+    #[test]
+    fn file_hash() {
+        let (_temp_dir, path) = extra::generate_random_file(1000);
+
+        let result = FileHash::from_file(&path, &Sha256::new()).unwrap();
+        assert_eq!(result.algorithm(), Some("SHA-256"));
+        assert_eq!(
+            result.to_hex(),
+            crate::get_hash_file(&path, &Sha256::new()).unwrap()
+        );
+
+        let text = result.to_string();
+        assert!(text.starts_with("SHA-256:"));
+        assert_eq!(text.parse::<FileHash>().unwrap(), result);
+        assert_eq!(
+            format!("sha256:{}", result.to_hex().to_uppercase())
+                .parse::<FileHash>()
+                .unwrap(),
+            result
+        );
+
+        // The same bytes of another algorithm are another hash
+        let other = FileHash::new(Some("BLAKE3"), result.as_bytes().to_vec());
+        assert_ne!(other, result);
+        let bare: FileHash = result.to_hex().parse().unwrap();
+        assert_eq!(bare.algorithm(), None);
+        assert_ne!(bare, result);
+
+        let blake = FileHash::from_file(&path, &Blake2s256::new()).unwrap();
+        assert_eq!(blake.algorithm(), Some("BLAKE2s-256"));
+        assert_ne!(blake, result);
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(json, format!("\"{}\"", text));
+        assert_eq!(serde_json::from_str::<FileHash>(&json).unwrap(), result);
+
+        let error = "SHA-256:xyz".parse::<FileHash>().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ct_eq() {
+        assert!(super::ct_eq(b"", b""));
+        assert!(super::ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!super::ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!super::ct_eq(&[1, 2, 3], &[1, 2]));
+
+        let hash = FileHash::new(Some("SHA-256"), vec![0xab; 32]);
+        assert!(hash.ct_eq(&hash.clone()));
+        assert!(hash.verify(&hash.clone()).is_ok());
+
+        let mut tampered = hash.clone();
+        tampered.bytes[31] ^= 1;
+        assert!(!hash.ct_eq(&tampered));
+        assert_eq!(
+            hash.verify(&tampered).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        let other = FileHash::new(Some("BLAKE3"), vec![0xab; 32]);
+        assert!(!hash.ct_eq(&other));
+    }
+}
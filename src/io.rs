@@ -0,0 +1,485 @@
+//! IO building blocks
+//!
+//! The same chunked loop that [get_hash_file](crate::get_hash_file) uses, for any [Read]
+
+use super::*;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+
+/// Feed everything from **reader** into `hash`
+///
+/// Returns the number of bytes read. `hash` is not finalized, so you can keep updating it
+///
+/// # Example
+///
+/// ```
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::io::hash_update;
+///
+/// let mut hash = Blake2s256::new();
+/// let bytes = hash_update(&b"hello "[..], &mut hash).unwrap()
+///     + hash_update(&b"world"[..], &mut hash).unwrap();
+///
+/// assert_eq!(bytes, 11);
+/// ```
+pub fn hash_update<HashType, R>(
+    reader: R,
+    hash: &mut HashType,
+) -> Result<u64, IOError>
+where
+    HashType: DynDigest + ?Sized,
+    R: Read,
+{
+    hash_tee(reader, hash, std::io::sink())
+}
+
+/// Feed everything from **reader** into `hash` and **writer** in one pass
+fn hash_tee<HashType, R, W>(
+    reader: R,
+    hash: &mut HashType,
+    writer: W,
+) -> Result<u64, IOError>
+where
+    HashType: DynDigest + ?Sized,
+    R: Read,
+    W: Write,
+{
+    let mut buf = AlignedBuf::new(PAGE_SIZE, DEFAULT_ALIGNMENT)?;
+    hash_tee_with(reader, hash, writer, &mut buf)
+}
+
+/// [hash_tee] with a caller-provided buffer
+///
+/// The buffer is filled completely before it is fed to `hash`,
+/// so every update except the last one is a whole number of blocks
+pub(crate) fn hash_tee_with<HashType, R, W>(
+    mut reader: R,
+    hash: &mut HashType,
+    mut writer: W,
+    buf: &mut [u8],
+) -> Result<u64, IOError>
+where
+    HashType: DynDigest + ?Sized,
+    R: Read,
+    W: Write,
+{
+    let mut bytes = 0;
+
+    loop {
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            match reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(i) => filled += i,
+                Err(error) if error.kind() == IOErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        hash.update(&buf[0..filled]);
+        writer.write_all(&buf[0..filled])?;
+        bytes += filled as u64;
+
+        if filled < buf.len() {
+            writer.flush()?;
+            return Ok(bytes);
+        }
+    }
+}
+
+/// Alignment of read buffers, enough for SIMD implementations of hashes
+pub(crate) const DEFAULT_ALIGNMENT: usize = 64;
+
+/// Heap buffer with the start aligned to a power of two
+pub(crate) struct AlignedBuf {
+    ptr: std::ptr::NonNull<u8>,
+    layout: std::alloc::Layout,
+}
+
+impl AlignedBuf {
+    /// # Error
+    ///
+    /// * If the **alignment** is not a power of two or the **size** is 0, the **IOErrorKind::InvalidInput** error will be returned
+    pub(crate) fn new(
+        size: usize,
+        alignment: usize,
+    ) -> Result<Self, IOError> {
+        let layout = std::alloc::Layout::from_size_align(size, alignment)
+            .ok()
+            .filter(|layout| layout.size() > 0)
+            .ok_or_else(|| IOError::from(IOErrorKind::InvalidInput))?;
+
+        // SAFETY: the size of the layout is not zero
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        let ptr = std::ptr::NonNull::new(ptr)
+            .unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+
+        Ok(Self { ptr, layout })
+    }
+}
+
+impl std::ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `layout.size()` initialized bytes owned by `self`
+        unsafe {
+            std::slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size())
+        }
+    }
+}
+
+impl std::ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `ptr` points to `layout.size()` initialized bytes owned by `self`
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.ptr.as_ptr(),
+                self.layout.size(),
+            )
+        }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `AlignedBuf::new` with the same layout
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+// SAFETY: `AlignedBuf` owns its memory like `Vec<u8>`
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+/// Hash everything from **reader**
+///
+/// Returns the number of bytes read and the hash as **hex lower**,
+/// exactly like [get_hash_file](crate::get_hash_file) does for a file
+///
+/// # Example
+///
+/// ```
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::io::hash_copy;
+///
+/// let mut hash = Blake2s256::new();
+/// let (bytes, result) = hash_copy(&b"hello world"[..], &mut hash).unwrap();
+///
+/// assert_eq!(bytes, 11);
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub fn hash_copy<HashType, R>(
+    reader: R,
+    hash: &mut HashType,
+) -> Result<(u64, String), IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+{
+    let bytes = hash_update(reader, hash)?;
+    Ok((bytes, crate::encoding::get_lowerhex(hash)))
+}
+
+/// Copy **file** to **dst** and get its hash in the same pass
+///
+/// The file is read only once. `dst` is created or truncated
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::io::copy_and_hash;
+///
+/// let mut hash = Blake2s256::new();
+/// let result = copy_and_hash(
+///     "/home/gladi/photo.png",
+///     "/mnt/backup/photo.png",
+///     &mut hash,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub fn copy_and_hash<HashType, P, Q>(
+    src: P,
+    dst: Q,
+    hash: &mut HashType,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let reader = File::open(src)?;
+    let writer = BufWriter::new(File::create(dst)?);
+
+    let (_, result) = copy_and_hash_writer(reader, writer, hash)?;
+    Ok(result)
+}
+
+/// Copy **reader** to **writer** and get the hash in the same pass
+///
+/// Returns the number of bytes copied and the hash as **hex lower**
+pub fn copy_and_hash_writer<HashType, R, W>(
+    reader: R,
+    writer: W,
+    hash: &mut HashType,
+) -> Result<(u64, String), IOError>
+where
+    HashType: DynDigest + Clone,
+    R: Read,
+    W: Write,
+{
+    let bytes = hash_tee(reader, hash, writer)?;
+    Ok((bytes, crate::encoding::get_lowerhex(hash)))
+}
+
+/// [Write] adapter that hashes everything written through it
+///
+/// # Example
+///
+/// ```
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::io::HashingWriter;
+/// use std::io::Write;
+///
+/// let mut writer = HashingWriter::new(Vec::new(), Blake2s256::new());
+/// writer.write_all(b"hello world").unwrap();
+///
+/// let (data, result) = writer.finalize();
+/// assert_eq!(data, b"hello world");
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub struct HashingWriter<W, HashType> {
+    inner: W,
+    hash: HashType,
+    bytes: u64,
+}
+
+impl<W, HashType> HashingWriter<W, HashType>
+where
+    W: Write,
+    HashType: DynDigest + Clone,
+{
+    pub fn new(inner: W, hash: HashType) -> Self {
+        Self {
+            inner,
+            hash,
+            bytes: 0,
+        }
+    }
+
+    /// Hash of the bytes written so far as **hex lower**
+    pub fn hash(&mut self) -> String {
+        self.hash_with(&crate::encoding::Encoding::HexLower)
+    }
+
+    /// Hash of the bytes written so far formatted with **encoder**
+    pub fn hash_with(
+        &mut self,
+        encoder: &dyn crate::encoding::Encoder,
+    ) -> String {
+        crate::encoding::get_encoded_with(&mut self.hash, encoder)
+    }
+
+    /// Number of bytes written so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get the inner writer back together with the final hash
+    pub fn finalize(mut self) -> (W, String) {
+        let result = self.hash();
+        (self.inner, result)
+    }
+}
+
+impl<W, HashType> Write for HashingWriter<W, HashType>
+where
+    W: Write,
+    HashType: DynDigest + Clone,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let i = self.inner.write(buf)?;
+        self.hash.update(&buf[0..i]);
+        self.bytes += i as u64;
+        Ok(i)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// [Read] adapter that hashes everything read through it
+///
+/// # Example
+///
+/// ```
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::io::HashingReader;
+/// use std::io::Read;
+///
+/// let mut reader = HashingReader::new(&b"hello world"[..], Blake2s256::new());
+///
+/// let mut data = String::new();
+/// reader.read_to_string(&mut data).unwrap(); // e.g. a parser consumes the data
+///
+/// let (_, result) = reader.finalize();
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+pub struct HashingReader<R, HashType> {
+    inner: R,
+    hash: HashType,
+    bytes: u64,
+}
+
+impl<R, HashType> HashingReader<R, HashType>
+where
+    R: Read,
+    HashType: DynDigest + Clone,
+{
+    pub fn new(inner: R, hash: HashType) -> Self {
+        Self {
+            inner,
+            hash,
+            bytes: 0,
+        }
+    }
+
+    /// Hash of the bytes read so far as **hex lower**
+    pub fn hash(&mut self) -> String {
+        self.hash_with(&crate::encoding::Encoding::HexLower)
+    }
+
+    /// Hash of the bytes read so far formatted with **encoder**
+    pub fn hash_with(
+        &mut self,
+        encoder: &dyn crate::encoding::Encoder,
+    ) -> String {
+        crate::encoding::get_encoded_with(&mut self.hash, encoder)
+    }
+
+    /// Number of bytes read so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get the inner reader back together with the final hash
+    pub fn finalize(mut self) -> (R, String) {
+        let result = self.hash();
+        (self.inner, result)
+    }
+}
+
+impl<R, HashType> Read for HashingReader<R, HashType>
+where
+    R: Read,
+    HashType: DynDigest + Clone,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let i = self.inner.read(buf)?;
+        self.hash.update(&buf[0..i]);
+        self.bytes += i as u64;
+        Ok(i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+
+    #[test]
+    fn aligned_buf() {
+        for alignment in [64, 4096] {
+            let buf = super::AlignedBuf::new(10_000, alignment).unwrap();
+
+            assert_eq!(buf.len(), 10_000);
+            assert_eq!(buf.as_ptr() as usize % alignment, 0);
+        }
+
+        assert!(super::AlignedBuf::new(4096, 3).is_err());
+        assert!(super::AlignedBuf::new(0, 64).is_err());
+    }
+
+    #[test]
+    fn hash_copy() {
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+        let data = std::fs::read(&path).unwrap();
+
+        let mut hash = Blake2s256::new();
+        let (bytes, result) = super::hash_copy(&data[..], &mut hash).unwrap();
+
+        let hash = Blake2s256::new();
+        assert_eq!(bytes, 10_000);
+        assert_eq!(result, crate::get_hash_file(&path, &hash).unwrap());
+    }
+
+    #[test]
+    fn copy_and_hash() {
+        let (temp_dir, path) = extra::generate_random_file(10_000);
+        let dst = temp_dir.path().join("copy.txt");
+
+        let mut hash = Blake2s256::new();
+        let result = super::copy_and_hash(&path, &dst, &mut hash).unwrap();
+
+        let hash = Blake2s256::new();
+        assert_eq!(result, crate::get_hash_file(&dst, &hash).unwrap());
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            std::fs::read(&dst).unwrap()
+        );
+    }
+
+    #[test]
+    fn hashing_writer() {
+        let (temp_dir, path) = extra::generate_random_file(10_000);
+        let dst = temp_dir.path().join("copy.txt");
+
+        let file = std::fs::File::create(&dst).unwrap();
+        let mut writer = super::HashingWriter::new(file, Blake2s256::new());
+        std::io::copy(&mut std::fs::File::open(&path).unwrap(), &mut writer)
+            .unwrap();
+        assert_eq!(writer.bytes(), 10_000);
+
+        let base64 = writer.hash_with(&crate::encoding::Encoding::Base64);
+        let expected =
+            crate::hash::FileHash::from_file(&path, &Blake2s256::new())
+                .unwrap()
+                .encode(&crate::encoding::Encoding::Base64);
+        assert_eq!(base64, expected);
+
+        let (_file, result) = writer.finalize();
+        let hash = Blake2s256::new();
+        assert_eq!(result, crate::get_hash_file(&dst, &hash).unwrap());
+    }
+
+    #[test]
+    fn hashing_reader() {
+        let (_temp_dir, path) = extra::generate_random_file(10_000);
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut reader = super::HashingReader::new(file, Blake2s256::new());
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(reader.bytes(), 10_000);
+        assert_eq!(
+            reader.hash_with(&crate::encoding::Encoding::HexUpper),
+            reader.hash().to_uppercase()
+        );
+
+        let (_file, result) = reader.finalize();
+        let hash = Blake2s256::new();
+        assert_eq!(result, crate::get_hash_file(&path, &hash).unwrap());
+    }
+}
@@ -9,31 +9,127 @@
 //!
 //! let path = PathBuf::from("/home/gladi/test-hashing.txt");
 //!
-//! let mut hash = Blake2s256::new();
-//! let result = get_hash_file(&path, &mut hash).unwrap();
+//! let hash = Blake2s256::new();
+//! let result = get_hash_file(&path, &hash).unwrap();
 //!
 //! assert_eq!(result.len(), 64); // Blake2s256 len == 64
 //! ```
 //!
+//! # Hashers
+//!
+//! Every function takes the hasher by reference and never changes it. Each file (or block, chunk, ...)
+//! is hashed with its own copy, so data already fed to the hasher (e.g. a salt) is hashed before every
+//! one of them, the same way by [get_hash_file], folders, manifests, snapshots, caches and stores
+//!
+//! # Encoding
+//!
+//! Functions that take [HashOptions] format their hashes with [HashOptions::encoder] (**hex lower** by default).
+//! The rest always return **hex lower**:
+//!
+//! * functions without options, like [get_hash_file], [get_hash_files], [get_hash_folder] and the helpers of [io].
+//!   Use [FileHash] (or `hash_with` of the [io] adapters) to format a single hash with any [Encoder](encoding::Encoder)
+//! * formats read by other tools: BagIt, OCFL, the content-addressed store and the checksum files of `Manifest::create_nested`
+//! * hashes that are only compared, never shown: chunk hashes, block hashes of [delta] signatures and ETags
+//!
+//! # Threads
+//!
+//! Thread pools are built on first use for each number of threads and kept for later calls,
+//! so calling e.g. [get_hash_folder] in a loop doesn't spawn and join threads every time.
+//! Calls with the same number of threads share the workers of one pool, and only the pools of the few most
+//! recently used numbers of threads are kept. Pass a pool of your own with `HashOptions::thread_pool`
+//! to keep its workers to yourself and decide when its threads exit.
+//! With [HashOptions::single_thread] the functions that take options run on the calling thread only
+//!
+//! # Memory
+//!
+//! Files are read in buffers of a fixed size, so a file of any size takes the same memory to hash.
+//! Folder functions walk the folder first and hold every path and result until the files are combined,
+//! which is fine up to a few million files. With [HashOptions::bounded_memory] folders are read while they
+//! are hashed, and only one window of a few hundred paths per thread is held at a time
+//!
+//! # Minimal builds
+//!
+//! The default features `parallel` (rayon) and `walk` (walkdir) can be dropped with `--no-default-features`.
+//! Files are then hashed on scoped threads of the standard library and folders are walked with [std::fs::read_dir],
+//! which is enough for [get_hash_file], [get_hash_files] and the [manifest] parser
+//!
+//! # WebAssembly
+//!
+//! The crate builds for `wasm32-wasip1` without the default features (`--no-default-features`),
+//! files are then reached through the WASI preopens. There are no threads, so everything is hashed on the current thread
+//!
 //! P.S. If the examples from the documentation **do not work**, then you need to look at the **unit tests**
 
-pub(crate) mod encoding;
+#[cfg(any(
+    feature = "sha2",
+    feature = "blake2",
+    feature = "blake3",
+    feature = "insecure-legacy"
+))]
+pub mod algorithms;
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "bagit")]
+pub mod bagit;
+pub mod cache;
+#[cfg(feature = "cas")]
+pub mod cas;
+#[cfg(feature = "chunking")]
+pub mod chunking;
+pub mod cid;
+#[cfg(feature = "cloud")]
+pub mod cloud;
+pub mod dedup;
+pub mod delta;
+pub mod diagnostics;
+pub mod encoding;
+#[cfg(target_os = "linux")]
+mod extents;
 pub mod file;
 pub mod folder;
 pub mod fs;
+pub mod hash;
+pub mod io;
+pub mod manifest;
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+mod numa;
+#[cfg(feature = "ocfl")]
+pub mod ocfl;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod openat;
+pub mod options;
+#[cfg(all(windows, feature = "overlapped"))]
+pub mod overlapped;
+mod pool;
+pub mod selftest;
+pub mod snapshot;
+pub mod stats;
+pub mod verify;
 
 use digest::DynDigest;
 use std::io::Error as IOError;
 use std::io::ErrorKind as IOErrorKind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub use file::{get_hash_file, get_hash_files};
+pub use file::{get_hash_bytes, get_hash_file, get_hash_files};
 pub use folder::{get_hash_folder, get_hash_folders};
+pub use hash::FileHash;
+pub use options::HashOptions;
+
+#[cfg(any(
+    feature = "sha2",
+    feature = "blake2",
+    feature = "blake3",
+    feature = "insecure-legacy"
+))]
+pub use algorithms::*;
 
 const PAGE_SIZE: usize = 4096;
 
 /// Information about progress
 ///
+/// New kinds of information may be added, so matches need a wildcard arm
+///
 /// # Example
 ///
 /// ```no_run
@@ -45,12 +141,47 @@ const PAGE_SIZE: usize = 4096;
 /// match info {
 ///     ProgressInfo::Yield(done_files) => println!("done files {}/{}", done_files, value_files),
 ///     ProgressInfo::Error(error) => println!("error: {}", error),
+///     _ => {}
 /// }
 /// ```
+#[derive(Debug)]
+#[non_exhaustive]
 pub enum ProgressInfo {
     /// How many files have we processed
     Yield(u64),
 
     /// Runtime error log
     Error(IOError),
+
+    /// A file was left out on purpose
+    Skipped { path: PathBuf, reason: SkipReason },
+
+    /// A file is hashed again, e.g. because it changed mid-read (see [HashOptions::detect_changes]).
+    /// **attempt** starts at 1.
+    /// Retries happen on the threads that hash, so only subscribers of [HashOptions::subscribe] get them
+    Retried { path: PathBuf, attempt: usize },
+
+    /// The hash of a file was taken from a cache instead of reading the file,
+    /// see [HashCache::get_hash_file_with](crate::cache::HashCache::get_hash_file_with)
+    CacheHit(PathBuf),
+}
+
+/// Why a file was left out, see [ProgressInfo::Skipped]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// Symbolic links are not followed
+    Symlink,
+
+    /// Sockets, FIFOs, devices, ...
+    SpecialFile,
+
+    /// Excluded by a filter
+    Filter,
+
+    /// Over a limit of the run, see [HashOptions::max_files] and [HashOptions::max_bytes]
+    Limit,
+
+    /// NTFS junctions and other reparse points, see [HashOptions::reparse_points]
+    ReparsePoint,
 }
@@ -0,0 +1,1956 @@
+//! Checksum manifests
+//!
+//! A manifest is a list of files with their hashes in the format of `sha256sum` and friends:
+//!
+//! ```text
+//! 8c5a0b0e3c...  photos/cat.png
+//! 1f3d5e2a9b...  photos/dog.png
+//! ```
+//!
+//! BSD-tagged manifests (`sha256sum --tag`) and the JSON of [Manifest] are read as well, see [ManifestFormat]
+//!
+//! Paths with a backslash, a newline or a carriage return are escaped the way coreutils does it:
+//! the line starts with `\` and the characters become `\\`, `\n` and `\r`
+
+use super::*;
+use crate::options::Outcome;
+use crate::verify::DiffReport;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::Write;
+use std::path::{Component, PathBuf};
+
+/// Files with their hashes
+///
+/// Paths are relative to the folder the manifest describes and use `/` separators,
+/// see [HashOptions::native_separators](crate::HashOptions::native_separators)
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{manifest::Manifest, HashOptions};
+///
+/// let hash = Blake2s256::new();
+/// let options = HashOptions::new(12);
+///
+/// let manifest =
+///     Manifest::create("/home/gladi/Pictures", &hash, &options, |_| {}).unwrap();
+/// manifest.save("/home/gladi/B2SUMS").unwrap();
+///
+/// let manifest = Manifest::load("/home/gladi/B2SUMS").unwrap();
+/// let report = manifest
+///     .verify("/home/gladi/Pictures", &hash, &options, |_| {})
+///     .unwrap();
+///
+/// assert!(report.is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: BTreeMap<PathBuf, String>,
+}
+
+/// Format of a manifest file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ManifestFormat {
+    /// `hash  path` or `hash *path`, the output of GNU `sha256sum`
+    #[default]
+    Gnu,
+
+    /// `SHA256 (path) = hash`, the output of BSD `sha256` and `sha256sum --tag`
+    Bsd,
+
+    /// JSON of [Manifest]
+    Json,
+
+    /// `hash  path` records ending with NUL instead of a newline, the output of `sha256sum --zero`.
+    /// Paths are not escaped, so they may contain newlines
+    Zero,
+}
+
+impl ManifestFormat {
+    /// Guess the format of **text**
+    ///
+    /// JSON starts with `{`, text with NUL is [ManifestFormat::Zero], BSD is recognized by its first line,
+    /// everything else is [ManifestFormat::Gnu]
+    pub fn detect(text: &str) -> ManifestFormat {
+        let text = strip_bom(text);
+        if text.trim_start().starts_with('{') {
+            return ManifestFormat::Json;
+        }
+
+        if text.contains('\0') {
+            return ManifestFormat::Zero;
+        }
+
+        match text.lines().find(|line| !is_blank_or_comment(line)) {
+            Some(line)
+                if parse_bsd_line(
+                    line.strip_prefix('\\').unwrap_or(line),
+                )
+                .is_some() =>
+            {
+                ManifestFormat::Bsd
+            }
+            _ => ManifestFormat::Gnu,
+        }
+    }
+}
+
+/// Writes manifest entries one by one, e.g. while files are being hashed
+///
+/// Memory does not grow with the number of entries. Entries are written in the order they are pushed,
+/// the buffer is flushed every [ManifestWriter::flush_every] entries, so an interrupted run leaves a usable prefix.
+/// JSON is written compactly and is only complete after [ManifestWriter::finish]
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::manifest::{ManifestFormat, ManifestWriter};
+///
+/// let file = std::fs::File::create("/home/gladi/SHA256SUMS").unwrap();
+/// let mut writer = ManifestWriter::new(file, ManifestFormat::Gnu).unwrap();
+///
+/// writer.push("photos/cat.png", "8c5a0b0e3c").unwrap();
+/// writer.finish().unwrap();
+/// ```
+pub struct ManifestWriter<W: Write> {
+    writer: std::io::BufWriter<W>,
+    format: ManifestFormat,
+    entries: u64,
+    flush_every: u64,
+
+    /// Entries since the last flush
+    unflushed: u64,
+}
+
+impl<W: Write> ManifestWriter<W> {
+    /// # Error
+    ///
+    /// * If **format** is [ManifestFormat::Bsd], which needs the name of the algorithm, the **IOErrorKind::Unsupported** error will be returned
+    pub fn new(writer: W, format: ManifestFormat) -> Result<Self, IOError> {
+        let mut writer = std::io::BufWriter::new(writer);
+
+        match format {
+            ManifestFormat::Bsd => return Err(bsd_unsupported()),
+            ManifestFormat::Json => writer.write_all(b"{\"entries\":{")?,
+            ManifestFormat::Gnu | ManifestFormat::Zero => {}
+        }
+
+        Ok(Self {
+            writer,
+            format,
+            entries: 0,
+            flush_every: 1024,
+            unflushed: 0,
+        })
+    }
+
+    /// Flush after every **entries** entries, `0` flushes only in [ManifestWriter::finish]. 1024 by default
+    pub fn flush_every(mut self, entries: u64) -> Self {
+        self.flush_every = entries;
+        self
+    }
+
+    /// Write the entry of **path**
+    pub fn push<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        hash: &str,
+    ) -> Result<(), IOError> {
+        let path = path.as_ref();
+
+        match self.format {
+            ManifestFormat::Json => {
+                if self.entries > 0 {
+                    self.writer.write_all(b",")?;
+                }
+                serde_json::to_writer(&mut self.writer, &path)?;
+                self.writer.write_all(b":")?;
+                serde_json::to_writer(&mut self.writer, hash)?;
+            }
+            format => write_line(&mut self.writer, format, path, hash)?,
+        }
+
+        self.entries += 1;
+        self.unflushed += 1;
+        if self.unflushed == self.flush_every {
+            self.writer.flush()?;
+            self.unflushed = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Entries written so far
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Complete the manifest, flush it and give back the writer
+    pub fn finish(mut self) -> Result<W, IOError> {
+        if self.format == ManifestFormat::Json {
+            self.writer.write_all(b"}}")?;
+        }
+
+        self.writer.into_inner().map_err(|error| error.into_error())
+    }
+}
+
+/// What [Manifest::merge] does when both manifests have the same path with different hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the hash of `self`
+    KeepExisting,
+
+    /// Take the hash of the other manifest, e.g. it is from a newer incremental run
+    Overwrite,
+
+    /// Fail with the **IOErrorKind::InvalidData** error
+    Error,
+}
+
+/// What [Manifest::verify_entries] does with files that cannot be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report the error through **progress** as [ProgressInfo::Error] and skip the file
+    Report,
+
+    /// Fail the whole verification with the first error
+    Fail,
+}
+
+/// Progress of [Manifest::verify_resumable]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Files that are already verified
+    pub verified: BTreeSet<PathBuf>,
+
+    /// Differences found in them
+    pub report: DiffReport,
+}
+
+impl Checkpoint {
+    /// Load checkpoint from file
+    ///
+    /// # Error
+    ///
+    /// * If the checkpoint is malformed, the **IOErrorKind::InvalidData** error will be returned
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, IOError> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data).map_err(IOError::from)
+    }
+
+    /// Save checkpoint to file, atomically and flushed to disk
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IOError> {
+        let data = serde_json::to_vec(self)?;
+        fs::write_atomic(path, true, |writer| writer.write_all(&data))
+    }
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create manifest of **folder**
+    ///
+    /// Each file is hashed with a copy of `hash`
+    ///
+    /// # Error
+    ///
+    /// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned, see [Manifest::create_partial]
+    pub fn create<HashType, P>(
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<Manifest, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let (manifest, _) =
+            Self::create_partial(dir, hash, options, progress)?;
+        options.check_cancelled()?;
+
+        Ok(manifest)
+    }
+
+    /// Create manifest of **folder** that survives cancellation
+    ///
+    /// If the run is cancelled with a [CancelToken](crate::options::CancelToken),
+    /// the manifest of the files hashed so far is returned together with the files that were pending
+    pub fn create_partial<HashType, P>(
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<(Manifest, Vec<PathBuf>), IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let base = fs::resolve_base(dir, options)?;
+        let progress = options.broadcast(progress);
+        let paths: Vec<PathBuf> = fs::walk(dir, options)
+            .report_errors(&progress)
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+
+        Ok(Self::hash_paths(&paths, &base, hash, options, &progress))
+    }
+
+    /// Create manifest of **folder** within the limits of **options**
+    ///
+    /// Files are taken in the order of their paths, see [HashOptions::max_files] and [HashOptions::max_bytes].
+    /// Useful for bounded scans in request handlers
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::{manifest::Manifest, options::Outcome, HashOptions};
+    ///
+    /// let options = HashOptions::new(4)
+    ///     .max_files(Some(10_000))
+    ///     .max_bytes(Some(1 << 30));
+    ///
+    /// match Manifest::create_bounded("/home/gladi", &Blake2s256::new(), &options, |_| {}).unwrap() {
+    ///     Outcome::Complete(manifest) => println!("{} files", manifest.len()),
+    ///     Outcome::LimitReached { partial, limit } => {
+    ///         println!("stopped at {:?} after {} files", limit, partial.len())
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+    pub fn create_bounded<HashType, P>(
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<Outcome<Manifest>, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let base = fs::resolve_base(dir, options)?;
+        let progress = options.broadcast(progress);
+
+        let mut entries = fs::walk(dir, options).report_errors(&progress);
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let (entries, limit) = options.apply_limits(entries, &progress);
+        let paths: Vec<PathBuf> =
+            entries.into_iter().map(|entry| entry.path).collect();
+
+        let (manifest, _) =
+            Self::hash_paths(&paths, &base, hash, options, &progress);
+        options.check_cancelled()?;
+
+        Ok(match limit {
+            Some(limit) => Outcome::LimitReached {
+                partial: manifest,
+                limit,
+            },
+            None => Outcome::Complete(manifest),
+        })
+    }
+
+    /// Hash **folder** straight into **writer**, without keeping the manifest in memory
+    ///
+    /// The folder is read while it is hashed, like with [HashOptions::bounded_memory]: files are hashed in batches
+    /// in the order of their paths and every batch is written as soon as it is hashed,
+    /// so memory doesn't grow with the number of files.
+    /// Returns the number of written entries
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::{manifest::{Manifest, ManifestFormat, ManifestWriter}, HashOptions};
+    ///
+    /// let file = std::fs::File::create("/mnt/archive/B2SUMS").unwrap();
+    /// let mut writer = ManifestWriter::new(file, ManifestFormat::Gnu).unwrap();
+    ///
+    /// let entries = Manifest::create_streaming(
+    ///     "/mnt/archive",
+    ///     &Blake2s256::new(),
+    ///     &HashOptions::new(12),
+    ///     &mut writer,
+    ///     |_| {},
+    /// )
+    /// .unwrap();
+    /// writer.finish().unwrap();
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+    /// * If writing fails, the error of **writer** will be returned
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned, the hashed batches are already written
+    pub fn create_streaming<HashType, P, W>(
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        writer: &mut ManifestWriter<W>,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<u64, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+        W: Write,
+    {
+        /// Files hashed before their results are written
+        const BATCH: usize = 4096;
+
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let base = fs::resolve_base(dir, options)?;
+        let progress = options.broadcast(progress);
+
+        let mut walk = fs::walk_sorted(dir, options);
+        let mut batch = Vec::with_capacity(BATCH);
+        let written = writer.len();
+        let mut done_files = 0;
+
+        loop {
+            batch.clear();
+            for entry in walk.by_ref() {
+                match entry {
+                    fs::WalkEntry::File(entry) => batch.push(entry.path),
+                    fs::WalkEntry::Skipped(path, reason) => {
+                        progress(ProgressInfo::Skipped { path, reason })
+                    }
+                    fs::WalkEntry::Error(error) => {
+                        progress(ProgressInfo::Error(error))
+                    }
+                }
+
+                if batch.len() == BATCH {
+                    break;
+                }
+            }
+
+            if batch.is_empty() {
+                break;
+            }
+
+            options.check_cancelled()?;
+            let results = file::get_hash_each_file(&batch, hash, options);
+
+            for (path, result) in batch.iter().zip(results) {
+                match result {
+                    Ok(result) => {
+                        let relative =
+                            fs::relative_path(path, &base, options);
+                        writer.push(relative, &result)?;
+                        done_files += 1;
+                        progress(ProgressInfo::Yield(done_files));
+                    }
+                    Err(error)
+                        if error.kind() == IOErrorKind::Interrupted
+                            && options.is_cancelled() => {}
+                    Err(error) => {
+                        done_files += 1;
+                        progress(ProgressInfo::Error(error));
+                    }
+                }
+            }
+        }
+
+        options.check_cancelled()?;
+        Ok(writer.len() - written)
+    }
+
+    /// Write a manifest named **file_name** (e.g. `SHA256SUMS`) into every folder of **dir** that has files,
+    /// covering only the files directly in it, like the checksum files of mirrors and archives
+    ///
+    /// Files named **file_name** are left out of the hashes, see [HashOptions::skip_file_name],
+    /// so running it again replaces the manifests instead of hashing them.
+    /// Every manifest is saved with [Manifest::save] and can be checked with `sha256sum --check` from its folder,
+    /// so the hashes are always **hex lower** whatever [HashOptions::encoder] says.
+    /// Returns the written manifests in the order of their paths
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use sha2::{Digest, Sha256};
+    /// use file_hashing::{manifest::Manifest, HashOptions};
+    ///
+    /// let written = Manifest::create_nested(
+    ///     "/srv/mirror",
+    ///     "SHA256SUMS",
+    ///     &Sha256::new(),
+    ///     &HashOptions::new(12),
+    ///     |_| {},
+    /// )
+    /// .unwrap();
+    ///
+    /// println!("{} checksum files", written.len());
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+    /// * If a manifest cannot be saved, its error will be returned, manifests saved before it are kept
+    /// * If **file_name** is not a plain file name, the **IOErrorKind::InvalidInput** error will be returned
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned and nothing is written
+    pub fn create_nested<HashType, P>(
+        dir: P,
+        file_name: &str,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<Vec<PathBuf>, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let mut components = Path::new(file_name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            return Err(IOError::new(
+                IOErrorKind::InvalidInput,
+                format!("{:?} is not a file name", file_name),
+            ));
+        }
+
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let options = options.for_layout().skip_file_name(file_name);
+        let progress = options.broadcast(progress);
+        let paths: Vec<PathBuf> = fs::walk(&dir, &options)
+            .report_errors(&progress)
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+
+        let results = file::get_hash_each_file(&paths, hash, &options);
+        options.check_cancelled()?;
+
+        let mut manifests: BTreeMap<&Path, Manifest> = BTreeMap::new();
+        let mut done_files = 0;
+
+        for (path, result) in paths.iter().zip(results) {
+            done_files += 1;
+
+            match (path.parent(), result) {
+                (Some(folder), Ok(result)) => {
+                    let relative = fs::relative_path(path, folder, &options);
+                    manifests
+                        .entry(folder)
+                        .or_default()
+                        .insert(relative, result);
+                    progress(ProgressInfo::Yield(done_files));
+                }
+                (None, Ok(_)) => {}
+                (_, Err(error)) => progress(ProgressInfo::Error(error)),
+            }
+        }
+
+        let mut written = Vec::with_capacity(manifests.len());
+        for (folder, manifest) in manifests {
+            let path = folder.join(file_name);
+            manifest.save(&path)?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+
+    /// Hash **paths** into a manifest relative to **base**, files left by a cancellation are pending
+    fn hash_paths<HashType>(
+        paths: &[PathBuf],
+        base: &Path,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: &impl Fn(ProgressInfo),
+    ) -> (Manifest, Vec<PathBuf>)
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+    {
+        let results = file::get_hash_each_file(paths, hash, options);
+
+        let mut manifest = Manifest::new();
+        let mut pending = Vec::new();
+        let mut done_files = 0;
+
+        for (path, result) in paths.iter().zip(results) {
+            let relative = fs::relative_path(path, base, options);
+
+            match result {
+                Ok(result) => {
+                    done_files += 1;
+                    manifest.insert(&relative, result);
+                    progress(ProgressInfo::Yield(done_files));
+                }
+                Err(error)
+                    if error.kind() == IOErrorKind::Interrupted
+                        && options.is_cancelled() =>
+                {
+                    pending.push(relative)
+                }
+                Err(error) => {
+                    done_files += 1;
+                    progress(ProgressInfo::Error(error));
+                }
+            }
+        }
+
+        (manifest, pending)
+    }
+
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P, hash: String) {
+        self.entries.insert(path.as_ref().to_path_buf(), hash);
+    }
+
+    /// Add the entries of **other**, e.g. a manifest of another subtree or of an incremental run
+    ///
+    /// Hashes are compared case-insensitively, the same path with the same hash is not a conflict
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::manifest::{ConflictPolicy, Manifest};
+    ///
+    /// let mut manifest = Manifest::load("/home/gladi/SHA256SUMS").unwrap();
+    /// let incremental = Manifest::load("/home/gladi/SHA256SUMS.new").unwrap();
+    ///
+    /// manifest.merge(incremental, ConflictPolicy::Overwrite).unwrap();
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * If there is a conflict and **policy** is [ConflictPolicy::Error], the **IOErrorKind::InvalidData** error will be returned and `self` is left unchanged
+    pub fn merge(
+        &mut self,
+        other: Manifest,
+        policy: ConflictPolicy,
+    ) -> Result<(), IOError> {
+        if policy == ConflictPolicy::Error {
+            let conflict = other.entries.iter().find(|(path, hash)| {
+                self.entries
+                    .get(*path)
+                    .is_some_and(|old| !old.eq_ignore_ascii_case(hash))
+            });
+
+            if let Some((path, _)) = conflict {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidData,
+                    format!("conflicting hashes for {}", path.display()),
+                ));
+            }
+        }
+
+        for (path, hash) in other.entries {
+            match policy {
+                ConflictPolicy::KeepExisting => {
+                    self.entries.entry(path).or_insert(hash);
+                }
+                ConflictPolicy::Overwrite | ConflictPolicy::Error => {
+                    self.entries.insert(path, hash);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare with a **newer** manifest, no files are read
+    ///
+    /// `self` is treated as the baseline. Hashes are compared case-insensitively
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::manifest::Manifest;
+    ///
+    /// let old = Manifest::load("/home/gladi/release-1.0/SHA256SUMS").unwrap();
+    /// let new = Manifest::load("/home/gladi/release-1.1/SHA256SUMS").unwrap();
+    ///
+    /// println!("{}", old.compare(&new));
+    /// ```
+    pub fn compare(&self, other: &Manifest) -> DiffReport {
+        let mut report = DiffReport::default();
+
+        for (path, new) in other.entries.iter() {
+            match self.entries.get(path) {
+                None => report.push_added(path.clone(), new.clone()),
+                Some(old) if !old.eq_ignore_ascii_case(new) => report
+                    .push_modified(path.clone(), old.clone(), new.clone()),
+                Some(_) => {}
+            }
+        }
+
+        for (path, old) in self.entries.iter() {
+            if !other.entries.contains_key(path) {
+                report.push_removed(path.clone(), old.clone());
+            }
+        }
+
+        report.sort();
+        report
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Parse manifest from text, the format is detected with [ManifestFormat::detect]
+    ///
+    /// Both `hash  path` and `hash *path` (binary mode) lines are accepted
+    ///
+    /// # Error
+    ///
+    /// * If a line is malformed, the **IOErrorKind::InvalidData** error with the line number will be returned
+    pub fn parse(text: &str) -> Result<Manifest, IOError> {
+        Manifest::parse_as(text, ManifestFormat::detect(text))
+    }
+
+    /// Parse manifest from text in **format**
+    ///
+    /// Real-world manifests are messy, so a UTF-8 BOM, Windows line endings (`\r\n`),
+    /// blank lines and comment lines starting with `#` are accepted. Line numbers in errors count all of them
+    ///
+    /// # Error
+    ///
+    /// * If a line or the JSON is malformed, the **IOErrorKind::InvalidData** error will be returned
+    pub fn parse_as(
+        text: &str,
+        format: ManifestFormat,
+    ) -> Result<Manifest, IOError> {
+        let text = strip_bom(text);
+        let parse_line = match format {
+            ManifestFormat::Gnu | ManifestFormat::Zero => parse_line,
+            ManifestFormat::Bsd => parse_bsd_line,
+            ManifestFormat::Json => {
+                return serde_json::from_str(text).map_err(IOError::from)
+            }
+        };
+        let lines: Box<dyn Iterator<Item = &str>> = match format {
+            ManifestFormat::Zero => Box::new(text.split_terminator('\0')),
+            _ => Box::new(text.lines()),
+        };
+        let mut manifest = Manifest::new();
+
+        for (number, line) in lines.enumerate() {
+            if is_blank_or_comment(line) {
+                continue;
+            }
+
+            let line = match format {
+                ManifestFormat::Zero => line,
+                _ => line.strip_suffix('\r').unwrap_or(line),
+            };
+            let escaped = match line.strip_prefix('\\') {
+                Some(rest) if format != ManifestFormat::Zero => Some(rest),
+                _ => None,
+            };
+
+            let entry = match escaped {
+                Some(line) => parse_line(line)
+                    .and_then(|(hash, path)| Some((hash, unescape(path)?))),
+                None => parse_line(line)
+                    .map(|(hash, path)| (hash, path.to_string())),
+            };
+            let (hash, path) = entry.ok_or_else(|| {
+                IOError::new(
+                    IOErrorKind::InvalidData,
+                    format!(
+                        "malformed manifest line {}: {:?}",
+                        number + 1,
+                        line
+                    ),
+                )
+            })?;
+
+            manifest.insert(path, hash.to_string());
+        }
+
+        Ok(manifest)
+    }
+
+    /// Load manifest from file, the format is detected with [ManifestFormat::detect]
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::manifest::{Manifest, ManifestFormat};
+    ///
+    /// // Whatever was downloaded next to the release
+    /// let manifest = Manifest::load("/home/gladi/Downloads/SHA256SUMS").unwrap();
+    ///
+    /// // A GNU manifest whose first path happens to look like a BSD line
+    /// let manifest = Manifest::load_as("/home/gladi/SUMS", ManifestFormat::Gnu).unwrap();
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest, IOError> {
+        Manifest::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load manifest from file in **format**
+    pub fn load_as<P: AsRef<Path>>(
+        path: P,
+        format: ManifestFormat,
+    ) -> Result<Manifest, IOError> {
+        Manifest::parse_as(&std::fs::read_to_string(path)?, format)
+    }
+
+    /// Write manifest as text
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), IOError> {
+        self.write_as(writer, ManifestFormat::Gnu)
+    }
+
+    /// Write manifest in **format**
+    ///
+    /// # Error
+    ///
+    /// * If **format** is [ManifestFormat::Bsd], which needs the name of the algorithm, the **IOErrorKind::Unsupported** error will be returned
+    pub fn write_as<W: Write>(
+        &self,
+        mut writer: W,
+        format: ManifestFormat,
+    ) -> Result<(), IOError> {
+        match format {
+            ManifestFormat::Gnu | ManifestFormat::Zero => {
+                for (path, hash) in self.entries.iter() {
+                    write_line(&mut writer, format, path, hash)?;
+                }
+            }
+            ManifestFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?
+            }
+            ManifestFormat::Bsd => return Err(bsd_unsupported()),
+        }
+
+        writer.flush()
+    }
+
+    /// Save manifest to file
+    ///
+    /// The file is replaced atomically, an interrupted save leaves the old manifest in place
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), IOError> {
+        fs::write_atomic(path, false, |writer| self.write(writer))
+    }
+
+    /// The same as [Manifest::save], but in **format**
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::manifest::{Manifest, ManifestFormat};
+    ///
+    /// let manifest = Manifest::load("/home/gladi/SHA256SUMS").unwrap();
+    /// // Check with `sha256sum --check --zero SHA256SUMS.zero`
+    /// manifest.save_as("/home/gladi/SHA256SUMS.zero", ManifestFormat::Zero).unwrap();
+    /// ```
+    pub fn save_as<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ManifestFormat,
+    ) -> Result<(), IOError> {
+        fs::write_atomic(path, false, |writer| self.write_as(writer, format))
+    }
+
+    /// The same as [Manifest::save], but the manifest is flushed to disk (`fsync`) before returning
+    pub fn save_synced<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), IOError> {
+        fs::write_atomic(path, true, |writer| self.write(writer))
+    }
+
+    /// Verify **folder** against this manifest
+    ///
+    /// Files are hashed in parallel with fresh copies of `hash`.
+    /// Hashes are compared case-insensitively
+    ///
+    /// # Error
+    ///
+    /// Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are skipped
+    pub fn verify<HashType, P>(
+        &self,
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let progress = options.broadcast(progress);
+        let paths: Vec<PathBuf> = fs::walk(dir, options)
+            .report_errors(&progress)
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+
+        let base = fs::resolve_base(dir, options)?;
+        self.verify_paths(&base, &paths, hash, options, progress)
+    }
+
+    /// Verify the files listed in this manifest, the folder is not walked
+    ///
+    /// Files are hashed in parallel with fresh copies of `hash` on the same threads as [Manifest::create].
+    /// Listed files that do not exist are reported as removed, files that are not listed are ignored.
+    /// Hashes are compared case-insensitively
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use sha2::{Digest, Sha256};
+    /// use file_hashing::manifest::{ErrorPolicy, Manifest};
+    /// use file_hashing::{HashOptions, ProgressInfo};
+    ///
+    /// let manifest = Manifest::load("/home/gladi/mirror/SHA256SUMS").unwrap();
+    /// let report = manifest
+    ///     .verify_entries(
+    ///         "/home/gladi/mirror",
+    ///         &Sha256::new(),
+    ///         &HashOptions::new(12),
+    ///         ErrorPolicy::Report,
+    ///         |info| {
+    ///             if let ProgressInfo::Yield(done_files) = info {
+    ///                 println!("{}/{}", done_files, manifest.len());
+    ///             }
+    ///         },
+    ///     )
+    ///     .unwrap();
+    ///
+    /// println!("{}", report);
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * With [ErrorPolicy::Fail] the first error of a file other than **IOErrorKind::NotFound** will be returned
+    /// * If an entry is absolute or contains `..`, it is not opened and the **IOErrorKind::InvalidData** error
+    ///   is handled like the error of a file
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+    pub fn verify_entries<HashType, P>(
+        &self,
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        policy: ErrorPolicy,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let progress = options.broadcast(progress);
+        let mut checkpoint = Checkpoint::default();
+        let paths: Vec<&PathBuf> = self.entries.keys().collect();
+
+        self.verify_batch(
+            &dir,
+            &paths,
+            hash,
+            options,
+            policy,
+            &progress,
+            &mut checkpoint,
+        )?;
+        options.check_cancelled()?;
+
+        checkpoint.report.sort();
+        Ok(checkpoint.report)
+    }
+
+    /// The same as [Manifest::verify_entries], but the progress is saved to **checkpoint** every **interval** files
+    ///
+    /// If the **checkpoint** exists, the files verified in it are not hashed again and the run continues where it stopped,
+    /// e.g. after a reboot or a cancellation. The checkpoint is removed once the whole manifest is verified
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use sha2::{Digest, Sha256};
+    /// use file_hashing::manifest::{ErrorPolicy, Manifest};
+    /// use file_hashing::HashOptions;
+    ///
+    /// let manifest = Manifest::load("/mnt/archive/SHA256SUMS").unwrap();
+    ///
+    /// // Safe to kill and start again
+    /// let report = manifest
+    ///     .verify_resumable(
+    ///         "/mnt/archive",
+    ///         &Sha256::new(),
+    ///         &HashOptions::new(4),
+    ///         ErrorPolicy::Report,
+    ///         "/var/lib/archive/verify.checkpoint",
+    ///         10_000,
+    ///         |_| {},
+    ///     )
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// * If the **interval** is 0, the **IOErrorKind::InvalidInput** error will be returned
+    /// * If the **checkpoint** is malformed, the **IOErrorKind::InvalidData** error will be returned
+    /// * If the run is cancelled, the checkpoint is saved and the **IOErrorKind::Interrupted** error will be returned
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_resumable<HashType, P, C>(
+        &self,
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        policy: ErrorPolicy,
+        checkpoint_path: C,
+        interval: usize,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+        C: AsRef<Path>,
+    {
+        if interval == 0 {
+            return Err(IOError::from(IOErrorKind::InvalidInput));
+        }
+
+        let checkpoint_path = checkpoint_path.as_ref();
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let progress = options.broadcast(progress);
+
+        let mut checkpoint = match checkpoint_path.exists() {
+            true => Checkpoint::load(checkpoint_path)?,
+            false => Checkpoint::default(),
+        };
+
+        let paths: Vec<&PathBuf> = self
+            .entries
+            .keys()
+            .filter(|path| !checkpoint.verified.contains(*path))
+            .collect();
+
+        for batch in paths.chunks(interval) {
+            self.verify_batch(
+                &dir,
+                batch,
+                hash,
+                options,
+                policy,
+                &progress,
+                &mut checkpoint,
+            )?;
+            checkpoint.save(checkpoint_path)?;
+            options.check_cancelled()?;
+        }
+
+        if checkpoint_path.exists() {
+            std::fs::remove_file(checkpoint_path)?;
+        }
+
+        checkpoint.report.sort();
+        Ok(checkpoint.report)
+    }
+
+    /// Verify the files of **paths** and add them to **checkpoint**
+    ///
+    /// Files left pending by a cancellation are not added
+    #[allow(clippy::too_many_arguments)]
+    fn verify_batch<HashType>(
+        &self,
+        dir: &Path,
+        paths: &[&PathBuf],
+        hash: &HashType,
+        options: &HashOptions,
+        policy: ErrorPolicy,
+        progress: &impl Fn(ProgressInfo),
+        checkpoint: &mut Checkpoint,
+    ) -> Result<(), IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+    {
+        let mut inside = Vec::with_capacity(paths.len());
+        for path in paths {
+            match check_entry(path) {
+                Ok(()) => inside.push(*path),
+                Err(error) => match policy {
+                    ErrorPolicy::Report => {
+                        checkpoint.verified.insert(path.to_path_buf());
+                        progress(ProgressInfo::Error(error));
+                    }
+                    ErrorPolicy::Fail => return Err(error),
+                },
+            }
+        }
+
+        let files: Vec<PathBuf> =
+            inside.iter().map(|path| dir.join(path)).collect();
+        let results = file::get_hash_each_file(&files, hash, options);
+
+        for (path, result) in inside.iter().zip(results) {
+            let old = &self.entries[*path];
+            let report = &mut checkpoint.report;
+
+            match result {
+                Ok(new) if !old.eq_ignore_ascii_case(&new) => {
+                    report.push_modified(path.to_path_buf(), old.clone(), new)
+                }
+                Ok(_) => {}
+                Err(error) if error.kind() == IOErrorKind::NotFound => {
+                    report.push_removed(path.to_path_buf(), old.clone())
+                }
+                Err(error)
+                    if error.kind() == IOErrorKind::Interrupted
+                        && options.is_cancelled() =>
+                {
+                    continue
+                }
+                Err(error) => match policy {
+                    ErrorPolicy::Report => {
+                        checkpoint.verified.insert(path.to_path_buf());
+                        progress(ProgressInfo::Error(error));
+                        continue;
+                    }
+                    ErrorPolicy::Fail => return Err(error),
+                },
+            }
+
+            checkpoint.verified.insert(path.to_path_buf());
+            progress(ProgressInfo::Yield(checkpoint.verified.len() as u64));
+        }
+
+        Ok(())
+    }
+
+    /// Verify only **paths** against this manifest, paths are made relative to **folder**
+    ///
+    /// **progress** is not broadcast to the subscribers of **options**, the caller does that
+    pub(crate) fn verify_paths<HashType>(
+        &self,
+        dir: &Path,
+        paths: &[PathBuf],
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+    {
+        let results = file::get_hash_each_file(paths, hash, options);
+        options.check_cancelled()?;
+
+        let mut report = DiffReport::default();
+        let mut seen = HashSet::with_capacity(paths.len());
+        let mut done_files = 0;
+
+        for (path, result) in paths.iter().zip(results) {
+            done_files += 1;
+
+            let relative = fs::relative_path(path, dir, options);
+            seen.insert(relative.clone());
+
+            let new = match result {
+                Ok(new) => new,
+                Err(error) => {
+                    progress(ProgressInfo::Error(error));
+                    continue;
+                }
+            };
+
+            match self.entries.get(&relative) {
+                None => report.push_added(relative, new),
+                Some(old) if !old.eq_ignore_ascii_case(&new) => {
+                    report.push_modified(relative, old.clone(), new)
+                }
+                Some(_) => {}
+            }
+
+            progress(ProgressInfo::Yield(done_files));
+        }
+
+        for (path, old) in self.entries.iter() {
+            if !seen.contains(path) {
+                report.push_removed(path.clone(), old.clone());
+            }
+        }
+
+        report.sort();
+        Ok(report)
+    }
+}
+
+/// Make sure the entry at **path** stays inside of the folder it is verified in,
+/// manifests may come from anywhere
+fn check_entry(path: &Path) -> Result<(), IOError> {
+    let inside = path.components().all(|component| {
+        matches!(component, Component::Normal(_) | Component::CurDir)
+    });
+
+    match inside {
+        true => Ok(()),
+        false => Err(IOError::new(
+            IOErrorKind::InvalidData,
+            format!("{} is outside of the folder", path.display()),
+        )),
+    }
+}
+
+/// Split `hash  path` or `hash *path`
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let (hash, rest) = line.split_once(' ')?;
+    let path = rest
+        .strip_prefix(' ')
+        .or_else(|| rest.strip_prefix('*'))
+        .unwrap_or(rest);
+
+    (!hash.is_empty() && !path.is_empty()).then_some((hash, path))
+}
+
+/// Write `hash  path` of [ManifestFormat::Gnu] or [ManifestFormat::Zero]
+fn write_line<W: Write>(
+    writer: &mut W,
+    format: ManifestFormat,
+    path: &Path,
+    hash: &str,
+) -> Result<(), IOError> {
+    let path = path.to_string_lossy();
+
+    match format {
+        ManifestFormat::Gnu if needs_escape(&path) => {
+            writeln!(writer, "\\{}  {}", hash, escape(&path))
+        }
+        ManifestFormat::Zero => write!(writer, "{}  {}\0", hash, path),
+        _ => writeln!(writer, "{}  {}", hash, path),
+    }
+}
+
+fn bsd_unsupported() -> IOError {
+    IOError::new(
+        IOErrorKind::Unsupported,
+        "BSD manifests need the name of the algorithm",
+    )
+}
+
+fn strip_bom(text: &str) -> &str {
+    text.strip_prefix('\u{feff}').unwrap_or(text)
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+fn needs_escape(path: &str) -> bool {
+    path.contains(['\\', '\n', '\r'])
+}
+
+/// Escape a path like coreutils, the line then needs a leading `\`
+fn escape(path: &str) -> String {
+    path.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo [escape], `None` for an unknown escape sequence
+fn unescape(path: &str) -> Option<String> {
+    let mut result = String::with_capacity(path.len());
+    let mut chars = path.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            '\\' => result.push('\\'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            _ => return None,
+        }
+    }
+
+    Some(result)
+}
+
+/// Split `ALGORITHM (path) = hash`
+fn parse_bsd_line(line: &str) -> Option<(&str, &str)> {
+    let (algorithm, rest) = line.split_once(" (")?;
+    let (path, hash) = rest.rsplit_once(") = ")?;
+
+    let valid = !algorithm.is_empty()
+        && !algorithm.contains(' ')
+        && !path.is_empty()
+        && !hash.is_empty()
+        && hash.bytes().all(|byte| byte.is_ascii_alphanumeric());
+    valid.then_some((hash, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Checkpoint, ConflictPolicy, ErrorPolicy, Manifest, ManifestFormat,
+        ManifestWriter,
+    };
+    use crate::fs::extra;
+    use crate::options::{CancelToken, Limit};
+    use crate::{HashOptions, ProgressInfo, SkipReason};
+    use blake2::{Blake2s256, Digest};
+    use std::path::PathBuf;
+
+    #[test]
+    fn create_save_load_verify() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let manifest =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+        assert_eq!(manifest.len(), 10);
+
+        let manifest_dir = assert_fs::TempDir::new().unwrap();
+        let manifest_path = manifest_dir.path().join("B2SUMS");
+        manifest.save(&manifest_path).unwrap();
+        manifest.save_synced(&manifest_path).unwrap();
+        // Only the manifest, no temporary files
+        assert_eq!(std::fs::read_dir(&manifest_dir).unwrap().count(), 1);
+        let manifest = Manifest::load(&manifest_path).unwrap();
+
+        let report =
+            manifest.verify(&temp_dir, &hash, &options, |_| {}).unwrap();
+        assert!(report.is_empty());
+
+        std::fs::write(paths[3].path(), "changed").unwrap();
+
+        let report =
+            manifest.verify(&temp_dir, &hash, &options, |_| {}).unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(
+            report.modified[0].path,
+            PathBuf::from("random_file_3.txt")
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let manifest = Manifest::parse("aa  a.txt\nbb *dir/b.txt\n").unwrap();
+        assert_eq!(manifest.entries[&PathBuf::from("a.txt")], "aa");
+        assert_eq!(manifest.entries[&PathBuf::from("dir/b.txt")], "bb");
+
+        let error = Manifest::parse("aa  a.txt\nbroken\n").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn detect_format() {
+        let gnu = "aa  a.txt\nbb *dir/b (1).txt\n";
+        let bsd = "SHA256 (a.txt) = aa\nSHA256 (dir/b (1).txt) = bb\n";
+        let expected = Manifest::parse(gnu).unwrap();
+        let json = serde_json::to_string(&expected).unwrap();
+
+        assert_eq!(ManifestFormat::detect(gnu), ManifestFormat::Gnu);
+        assert_eq!(ManifestFormat::detect(bsd), ManifestFormat::Bsd);
+        assert_eq!(ManifestFormat::detect(&json), ManifestFormat::Json);
+
+        assert_eq!(Manifest::parse(bsd).unwrap(), expected);
+        assert_eq!(Manifest::parse(&json).unwrap(), expected);
+        assert_eq!(expected.entries[&PathBuf::from("dir/b (1).txt")], "bb");
+
+        // The override wins over detection
+        let tricky = "aa  SHA256 (x) = bb\n";
+        assert_eq!(
+            Manifest::parse_as(tricky, ManifestFormat::Gnu)
+                .unwrap()
+                .entries[&PathBuf::from("SHA256 (x) = bb")],
+            "aa"
+        );
+        let error = Manifest::parse_as(gnu, ManifestFormat::Bsd).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn create_streaming() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(10, 32);
+        let expected =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+
+        for format in [
+            ManifestFormat::Gnu,
+            ManifestFormat::Zero,
+            ManifestFormat::Json,
+        ] {
+            let mut writer = ManifestWriter::new(Vec::new(), format)
+                .unwrap()
+                .flush_every(3);
+            let written = Manifest::create_streaming(
+                &temp_dir,
+                &hash,
+                &options,
+                &mut writer,
+                |_| {},
+            )
+            .unwrap();
+            assert_eq!(written, 10);
+            assert_eq!(writer.len(), 10);
+
+            let data = writer.finish().unwrap();
+            let text = String::from_utf8(data).unwrap();
+            assert_eq!(Manifest::parse_as(&text, format).unwrap(), expected);
+        }
+
+        let mut gnu = Vec::new();
+        expected.write(&mut gnu).unwrap();
+        let mut writer =
+            ManifestWriter::new(Vec::new(), ManifestFormat::Gnu).unwrap();
+        for (path, hash) in expected.entries.iter() {
+            writer.push(path, hash).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), gnu);
+
+        let error = ManifestWriter::new(Vec::new(), ManifestFormat::Bsd)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn create_nested() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, _paths) = extra::generate_random_tree(
+            1,
+            2,
+            3,
+            extra::SizeDistribution::Fixed(32),
+        );
+        std::fs::create_dir(temp_dir.path().join("empty")).unwrap();
+        let before = crate::folder::get_hash_folder_with_factory(
+            &temp_dir,
+            Blake2s256::new,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            let written = Manifest::create_nested(
+                &temp_dir,
+                "B2SUMS",
+                &hash,
+                &options,
+                |_| {},
+            )
+            .unwrap();
+            assert_eq!(
+                written,
+                vec![
+                    temp_dir.path().join("B2SUMS"),
+                    temp_dir.path().join("dir_0/B2SUMS"),
+                    temp_dir.path().join("dir_1/B2SUMS"),
+                ]
+            );
+
+            for path in &written {
+                let folder = path.parent().unwrap();
+                let manifest = Manifest::load(path).unwrap();
+                assert_eq!(manifest.len(), 3);
+                assert!(manifest
+                    .entries
+                    .keys()
+                    .all(|entry| entry.components().count() == 1));
+                assert!(manifest
+                    .verify_entries(
+                        folder,
+                        &hash,
+                        &options,
+                        ErrorPolicy::Fail,
+                        |_| {}
+                    )
+                    .unwrap()
+                    .is_empty());
+            }
+        }
+
+        // The checksum files change the folder hash unless they are skipped
+        let after = |options: &HashOptions| {
+            crate::folder::get_hash_folder_with_factory(
+                &temp_dir,
+                Blake2s256::new,
+                options,
+                |_| {},
+            )
+            .unwrap()
+        };
+        assert_ne!(after(&options), before);
+        assert_eq!(after(&options.clone().skip_file_name("B2SUMS")), before);
+        assert_eq!(
+            after(&options.skip_file_name("B2SUMS").bounded_memory(true)),
+            before
+        );
+    }
+
+    #[test]
+    fn create_nested_checks() {
+        let hash = Blake2s256::new();
+        let options =
+            HashOptions::new(4).encoder(crate::encoding::Encoding::Base64);
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(3, 32);
+
+        for file_name in ["", ".", "..", "sub/B2SUMS", "/B2SUMS"] {
+            let error = Manifest::create_nested(
+                &temp_dir,
+                file_name,
+                &hash,
+                &options,
+                |_| {},
+            )
+            .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+        assert!(!temp_dir.path().join("sub").exists());
+
+        let written = Manifest::create_nested(
+            &temp_dir,
+            "B2SUMS",
+            &hash,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        let manifest = Manifest::load(&written[0]).unwrap();
+        let name = paths[0].file_name().unwrap();
+        assert_eq!(
+            manifest.entries[std::path::Path::new(name)],
+            crate::file::get_hash_file(&paths[0], &hash).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_messy() {
+        let text = "\u{feff}# SHA256 checksums\r\n\r\naa  a.txt\r\n  \n\
+                    # generated by hand\nbb *dir/b.txt\r";
+        let manifest = Manifest::parse(text).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest.entries[&PathBuf::from("a.txt")], "aa");
+        assert_eq!(manifest.entries[&PathBuf::from("dir/b.txt")], "bb");
+
+        let bsd = "\u{feff}SHA256 (a.txt) = aa\r\n";
+        assert_eq!(ManifestFormat::detect(bsd), ManifestFormat::Bsd);
+        assert_eq!(Manifest::parse(bsd).unwrap().len(), 1);
+
+        let json =
+            format!("\u{feff}{}", serde_json::to_string(&manifest).unwrap());
+        assert_eq!(Manifest::parse(&json).unwrap(), manifest);
+
+        // Skipped lines still count
+        let error =
+            Manifest::parse("# header\r\n\r\naa  a.txt\r\nbroken\r\n")
+                .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 4"));
+        assert!(error.to_string().contains("broken"));
+    }
+
+    #[test]
+    fn escaping() {
+        let mut manifest = Manifest::new();
+        manifest.insert("line\nbreak.txt", String::from("aa"));
+        manifest.insert("back\\slash.txt", String::from("bb"));
+        manifest.insert("plain.txt", String::from("cc"));
+
+        let mut data = Vec::new();
+        manifest.write(&mut data).unwrap();
+        let text = String::from_utf8(data).unwrap();
+        // The same as `sha256sum` prints
+        assert_eq!(
+            text,
+            "\\bb  back\\\\slash.txt\n\\aa  line\\nbreak.txt\ncc  plain.txt\n"
+        );
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+
+        let bsd = "\\SHA256 (line\\nbreak.txt) = aa\n";
+        assert_eq!(ManifestFormat::detect(bsd), ManifestFormat::Bsd);
+        assert_eq!(
+            Manifest::parse(bsd).unwrap().entries
+                [&PathBuf::from("line\nbreak.txt")],
+            "aa"
+        );
+
+        let error = Manifest::parse("\\aa  bad\\x.txt\n").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zero_format() {
+        let mut manifest = Manifest::new();
+        manifest.insert("line\nbreak.txt", String::from("aa"));
+        manifest.insert("back\\slash.txt", String::from("bb"));
+
+        let mut data = Vec::new();
+        manifest.write_as(&mut data, ManifestFormat::Zero).unwrap();
+        assert_eq!(data, b"bb  back\\slash.txt\0aa  line\nbreak.txt\0");
+
+        let text = String::from_utf8(data).unwrap();
+        assert_eq!(ManifestFormat::detect(&text), ManifestFormat::Zero);
+        assert_eq!(Manifest::parse(&text).unwrap(), manifest);
+
+        let mut json = Vec::new();
+        manifest.write_as(&mut json, ManifestFormat::Json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert_eq!(Manifest::parse(&json).unwrap(), manifest);
+
+        let error = manifest
+            .write_as(Vec::new(), ManifestFormat::Bsd)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn create_partial() {
+        let hash = Blake2s256::new();
+        let token = CancelToken::new();
+        let options = HashOptions::new(1).cancel_token(token.clone());
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        token.cancel();
+
+        let (manifest, pending) =
+            Manifest::create_partial(&temp_dir, &hash, &options, |_| {})
+                .unwrap();
+        assert!(manifest.is_empty());
+        assert_eq!(pending.len(), 10);
+
+        let error =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn create_canonicalized() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4).canonicalize(true);
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(3, 32);
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+
+        let dir = temp_dir.path().join("sub").join("..");
+        let manifest =
+            Manifest::create(&dir, &hash, &options, |_| {}).unwrap();
+        assert_eq!(manifest.len(), 3);
+        assert!(manifest
+            .entries
+            .contains_key(&PathBuf::from("random_file_0.txt")));
+
+        let report = manifest.verify(&dir, &hash, &options, |_| {}).unwrap();
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn create_with_base() {
+        let hash = Blake2s256::new();
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(3, 32);
+        let options =
+            HashOptions::new(4).base(temp_dir.path().parent().unwrap());
+
+        let manifest =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+        let name = temp_dir.path().file_name().unwrap();
+        assert!(manifest
+            .entries
+            .contains_key(&PathBuf::from(name).join("random_file_0.txt")));
+
+        let report =
+            manifest.verify(&temp_dir, &hash, &options, |_| {}).unwrap();
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn create_bounded() {
+        let hash = Blake2s256::new();
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(3, 32);
+        let create = |options: HashOptions| {
+            Manifest::create_bounded(&temp_dir, &hash, &options, |_| {})
+                .unwrap()
+        };
+
+        let outcome = create(HashOptions::new(4).max_files(Some(2)));
+        assert_eq!(outcome.limit(), Some(Limit::Files(2)));
+        assert_eq!(outcome.into_inner().len(), 2);
+
+        let skipped = std::sync::Mutex::new(Vec::new());
+        Manifest::create_bounded(
+            &temp_dir,
+            &hash,
+            &HashOptions::new(4).max_files(Some(1)),
+            |info| {
+                if let ProgressInfo::Skipped { path, reason } = info {
+                    skipped.lock().unwrap().push((path, reason));
+                }
+            },
+        )
+        .unwrap();
+        let skipped = skipped.into_inner().unwrap();
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped
+            .iter()
+            .all(|(_, reason)| *reason == SkipReason::Limit));
+
+        let outcome = create(HashOptions::new(4).max_bytes(Some(70)));
+        assert_eq!(outcome.limit(), Some(Limit::Bytes(70)));
+        let partial = outcome.into_inner();
+        assert_eq!(partial.len(), 2);
+        assert!(partial
+            .entries
+            .contains_key(&PathBuf::from("random_file_0.txt")));
+
+        let outcome = create(HashOptions::new(4).max_bytes(Some(96)));
+        assert!(outcome.is_complete());
+        assert_eq!(outcome.into_inner().len(), 3);
+    }
+
+    #[test]
+    fn merge() {
+        let manifest = Manifest::parse("aa  a.txt\nbb  b.txt\n").unwrap();
+        let other = Manifest::parse("BB  b.txt\ncc  c.txt\n").unwrap();
+        let conflict = Manifest::parse("dd  a.txt\n").unwrap();
+
+        let mut merged = manifest.clone();
+        merged.merge(other.clone(), ConflictPolicy::Error).unwrap();
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged.entries[&PathBuf::from("c.txt")], "cc");
+
+        let error = merged
+            .merge(conflict.clone(), ConflictPolicy::Error)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(merged.entries[&PathBuf::from("a.txt")], "aa");
+
+        merged
+            .merge(conflict.clone(), ConflictPolicy::KeepExisting)
+            .unwrap();
+        assert_eq!(merged.entries[&PathBuf::from("a.txt")], "aa");
+
+        merged.merge(conflict, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(merged.entries[&PathBuf::from("a.txt")], "dd");
+    }
+
+    #[test]
+    fn compare() {
+        let old = Manifest::parse("aa  a.txt\nbb  b.txt\n").unwrap();
+        let new =
+            Manifest::parse("AA  a.txt\ncc  b.txt\ndd  d.txt\n").unwrap();
+
+        let report = old.compare(&new);
+        assert_eq!(report.added.len(), 1);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].path, PathBuf::from("b.txt"));
+        assert!(report.removed.is_empty());
+
+        let report = new.compare(&old);
+        assert_eq!(report.removed[0].path, PathBuf::from("d.txt"));
+        assert!(old.compare(&old).is_empty());
+    }
+
+    #[test]
+    fn verify_entries() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let mut manifest =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+        std::fs::write(temp_dir.path().join("unlisted.txt"), "new").unwrap();
+        manifest.insert("missing.txt", String::from("aa"));
+        std::fs::write(paths[0].path(), "changed").unwrap();
+
+        let done = std::cell::Cell::new(0);
+        let report = manifest
+            .verify_entries(
+                &temp_dir,
+                &hash,
+                &options,
+                ErrorPolicy::Fail,
+                |_| done.set(done.get() + 1),
+            )
+            .unwrap();
+        assert!(report.added.is_empty());
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.removed[0].path, PathBuf::from("missing.txt"));
+        assert_eq!(done.get(), 11);
+    }
+
+    #[test]
+    fn verify_entries_outside() {
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4);
+        let (temp_dir, _paths) =
+            extra::generate_random_folder_with_files(2, 32);
+        let outside = extra::generate_random_file(32);
+
+        let mut manifest =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+        let good = manifest.clone();
+        let outside_hash =
+            crate::get_hash_file(outside.1.path(), &hash).unwrap();
+        manifest.insert(outside.1.path(), outside_hash.clone());
+        let name = outside.1.path().file_name().unwrap();
+        manifest.insert(PathBuf::from("..").join(name), outside_hash);
+
+        let errors = std::cell::Cell::new(0);
+        let report = manifest
+            .verify_entries(
+                &temp_dir,
+                &hash,
+                &options,
+                ErrorPolicy::Report,
+                |info| {
+                    if let ProgressInfo::Error(error) = info {
+                        assert_eq!(
+                            error.kind(),
+                            std::io::ErrorKind::InvalidData
+                        );
+                        errors.set(errors.get() + 1);
+                    }
+                },
+            )
+            .unwrap();
+        assert!(report.is_empty());
+        assert_eq!(errors.get(), 2);
+
+        let error = manifest
+            .verify_entries(
+                &temp_dir,
+                &hash,
+                &options,
+                ErrorPolicy::Fail,
+                |_| {},
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(good
+            .verify_entries(
+                &temp_dir,
+                &hash,
+                &options,
+                ErrorPolicy::Fail,
+                |_| {}
+            )
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn verify_resumable() {
+        let hash = Blake2s256::new();
+        let token = CancelToken::new();
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+        let checkpoint_dir = assert_fs::TempDir::new().unwrap();
+        let checkpoint_path = checkpoint_dir.path().join("checkpoint");
+
+        let manifest =
+            Manifest::create(&temp_dir, &hash, &HashOptions::new(4), |_| {})
+                .unwrap();
+        std::fs::write(paths[0].path(), "changed").unwrap();
+        std::fs::write(paths[1].path(), "changed").unwrap();
+
+        token.cancel();
+        let options = HashOptions::new(4).cancel_token(token);
+        let error = manifest
+            .verify_resumable(
+                &temp_dir,
+                &hash,
+                &options,
+                ErrorPolicy::Fail,
+                &checkpoint_path,
+                3,
+                |_| {},
+            )
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Interrupted);
+        assert!(Checkpoint::load(&checkpoint_path)
+            .unwrap()
+            .verified
+            .is_empty());
+
+        // As if the first file was verified before the interruption
+        let mut checkpoint = Checkpoint::default();
+        checkpoint
+            .verified
+            .insert(PathBuf::from("random_file_0.txt"));
+        checkpoint.save(&checkpoint_path).unwrap();
+
+        let done = std::cell::Cell::new(0);
+        let report = manifest
+            .verify_resumable(
+                &temp_dir,
+                &hash,
+                &HashOptions::new(4),
+                ErrorPolicy::Fail,
+                &checkpoint_path,
+                3,
+                |_| done.set(done.get() + 1),
+            )
+            .unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(done.get(), 9);
+        assert!(!checkpoint_path.exists());
+    }
+
+    #[test]
+    fn create_with_encoder() {
+        struct Short;
+
+        impl crate::encoding::Encoder for Short {
+            fn encode(&self, digest: &[u8]) -> String {
+                data_encoding::HEXUPPER.encode(&digest[..4])
+            }
+        }
+
+        let hash = Blake2s256::new();
+        let options = HashOptions::new(4).encoder(Short);
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(3, 32);
+
+        let manifest =
+            Manifest::create(&temp_dir, &hash, &options, |_| {}).unwrap();
+        let full = Blake2s256::new();
+        let full = crate::get_hash_file(&paths[0], &full).unwrap();
+        assert_eq!(
+            manifest.entries[&PathBuf::from("random_file_0.txt")],
+            full[..8].to_uppercase()
+        );
+
+        let report =
+            manifest.verify(&temp_dir, &hash, &options, |_| {}).unwrap();
+        assert!(report.is_empty());
+    }
+}
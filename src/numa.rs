@@ -0,0 +1,139 @@
+//! NUMA topology
+//!
+//! Only **Linux** is supported, on other platforms the machine looks like one node
+
+use super::*;
+
+/// CPUs of every NUMA node
+pub(crate) fn get_nodes() -> Vec<Vec<usize>> {
+    #[cfg(target_os = "linux")]
+    {
+        let entries = match std::fs::read_dir("/sys/devices/system/node") {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut nodes: Vec<(usize, Vec<usize>)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name();
+                let id = name.to_str()?.strip_prefix("node")?.parse().ok()?;
+                let cpus =
+                    std::fs::read_to_string(entry.path().join("cpulist"))
+                        .ok()?;
+
+                Some((id, parse_cpu_list(&cpus)))
+            })
+            .filter(|(_, cpus)| !cpus.is_empty())
+            .collect();
+
+        nodes.sort();
+        nodes.into_iter().map(|(_, cpus)| cpus).collect()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Vec::new()
+    }
+}
+
+/// Parse list like `0-3,8-11`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+
+        if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse()) {
+            cpus.extend(start..=end);
+        }
+    }
+
+    cpus
+}
+
+/// Pin the current thread to **cpus**
+///
+/// Memory the thread allocates afterwards is then local to their node.
+/// CPUs the kernel interface cannot address (`CPU_SETSIZE` and above) are left out
+///
+/// # Error
+///
+/// * If none of **cpus** can be addressed, the **IOErrorKind::InvalidInput** error will be returned
+/// * If the thread cannot be pinned, the error of `sched_setaffinity` will be returned
+pub(crate) fn pin_current_thread(cpus: &[usize]) -> Result<(), IOError> {
+    #[cfg(target_os = "linux")]
+    // SAFETY: `set` is a valid cpu_set_t for the current thread and
+    // only CPUs below CPU_SETSIZE are added to it
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        let mut count = 0;
+        for cpu in
+            cpus.iter().filter(|cpu| **cpu < libc::CPU_SETSIZE as usize)
+        {
+            libc::CPU_SET(*cpu, &mut set);
+            count += 1;
+        }
+
+        if count == 0 {
+            return Err(IOError::new(
+                IOErrorKind::InvalidInput,
+                "no CPU of the node can be pinned",
+            ));
+        }
+
+        if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0
+        {
+            return Err(IOError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = cpus;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_cpu_list() {
+        assert_eq!(
+            super::parse_cpu_list("0-3,8-9\n"),
+            vec![0, 1, 2, 3, 8, 9]
+        );
+        assert_eq!(super::parse_cpu_list("5"), vec![5]);
+        assert!(super::parse_cpu_list("").is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pin_current_thread() {
+        std::thread::spawn(|| {
+            let error =
+                super::pin_current_thread(&[libc::CPU_SETSIZE as usize])
+                    .unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+            // SAFETY: `set` is a valid cpu_set_t
+            let cpu = unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                libc::sched_getaffinity(
+                    0,
+                    std::mem::size_of_val(&set),
+                    &mut set,
+                );
+                (0..libc::CPU_SETSIZE as usize)
+                    .find(|cpu| libc::CPU_ISSET(*cpu, &set))
+                    .unwrap()
+            };
+            super::pin_current_thread(&[cpu, usize::MAX]).unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! [OCFL](https://ocfl.io) inventories
+//!
+//! An OCFL object keeps its content in version folders and lists it in `inventory.json`,
+//! where content paths are keyed by their digest:
+//!
+//! ```text
+//! object/
+//! ├── inventory.json
+//! ├── inventory.json.sha512
+//! └── v1/
+//!     └── content/
+//!         └── ...
+//! ```
+
+use super::*;
+use crate::manifest::Manifest;
+use crate::verify::DiffReport;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const INVENTORY_TYPE: &str = "https://ocfl.io/1.1/spec/#inventory";
+
+/// One version of an OCFL object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// Creation time in RFC 3339
+    pub created: String,
+
+    /// Logical paths keyed by digest
+    pub state: BTreeMap<String, Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// OCFL `inventory.json`
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha512};
+/// use file_hashing::{ocfl::Inventory, HashOptions};
+///
+/// let hash = Sha512::new();
+/// let options = HashOptions::new(12);
+///
+/// // The content is already in /home/gladi/object/v1/content
+/// let inventory = Inventory::create(
+///     "/home/gladi/object",
+///     "ark:/12345/bcd987",
+///     "sha512",
+///     &hash,
+///     &options,
+///     |_| {},
+/// )
+/// .unwrap();
+/// inventory.save("/home/gladi/object", &hash).unwrap();
+///
+/// let report = inventory
+///     .validate("/home/gladi/object", &hash, &options, |_| {})
+///     .unwrap();
+/// assert!(report.is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inventory {
+    pub id: String,
+
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// Algorithm of all digests, e.g. `sha512`
+    pub digest_algorithm: String,
+
+    /// The latest version, e.g. `v1`
+    pub head: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_directory: Option<String>,
+
+    /// Content paths keyed by digest
+    pub manifest: BTreeMap<String, Vec<String>>,
+
+    pub versions: BTreeMap<String, Version>,
+}
+
+impl Inventory {
+    /// Create inventory with one version from a [Manifest] of its content
+    ///
+    /// The manifest paths become the logical paths of `v1`
+    pub fn from_manifest(
+        id: &str,
+        digest_algorithm: &str,
+        content: &Manifest,
+    ) -> Self {
+        let mut manifest: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut state: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for (path, digest) in content.entries.iter() {
+            let logical = path.to_string_lossy().replace('\\', "/");
+
+            manifest
+                .entry(digest.clone())
+                .or_default()
+                .push(format!("v1/content/{}", logical));
+            state.entry(digest.clone()).or_default().push(logical);
+        }
+
+        let mut versions = BTreeMap::new();
+        versions.insert(
+            String::from("v1"),
+            Version {
+                created: rfc3339(SystemTime::now()),
+                state,
+                message: None,
+            },
+        );
+
+        Self {
+            id: id.to_string(),
+            kind: INVENTORY_TYPE.to_string(),
+            digest_algorithm: digest_algorithm.to_string(),
+            head: String::from("v1"),
+            content_directory: None,
+            manifest,
+            versions,
+        }
+    }
+
+    /// Create inventory for the content in `object_root/v1/content`
+    ///
+    /// Each file is hashed with a copy of `hash`, **digest_algorithm** is its OCFL name
+    pub fn create<HashType, P>(
+        object_root: P,
+        id: &str,
+        digest_algorithm: &str,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<Inventory, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let content_dir = object_root.as_ref().join("v1").join("content");
+        let options = &options.for_layout();
+        let content = Manifest::create(content_dir, hash, options, progress)?;
+
+        Ok(Self::from_manifest(id, digest_algorithm, &content))
+    }
+
+    /// Load `inventory.json` from the object root
+    pub fn load<P: AsRef<Path>>(
+        object_root: P,
+    ) -> Result<Inventory, IOError> {
+        let data =
+            std::fs::read(object_root.as_ref().join("inventory.json"))?;
+        serde_json::from_slice(&data).map_err(IOError::from)
+    }
+
+    /// Save `inventory.json` and its sidecar `inventory.json.<algorithm>` to the object root
+    pub fn save<HashType, P>(
+        &self,
+        object_root: P,
+        hash: &HashType,
+    ) -> Result<(), IOError>
+    where
+        HashType: DynDigest + Clone,
+        P: AsRef<Path>,
+    {
+        let object_root = object_root.as_ref();
+        let data = serde_json::to_vec_pretty(self)?;
+
+        crate::fs::write_atomic(
+            object_root.join("inventory.json"),
+            false,
+            |writer| writer.write_all(&data),
+        )?;
+
+        let (_, digest) = crate::io::hash_copy(&data[..], &mut hash.clone())?;
+
+        crate::fs::write_atomic(
+            object_root
+                .join(format!("inventory.json.{}", self.digest_algorithm)),
+            false,
+            |writer| writeln!(writer, "{} inventory.json", digest),
+        )
+    }
+
+    /// Content paths with their digests as a [Manifest]
+    pub fn content(&self) -> Manifest {
+        let mut content = Manifest::new();
+
+        for (digest, paths) in self.manifest.iter() {
+            for path in paths {
+                content.insert(path, digest.clone());
+            }
+        }
+
+        content
+    }
+
+    /// Validate the content of the object and the inventory sidecar
+    ///
+    /// Only files inside the content folders of versions are checked.
+    /// Paths in the report are content paths, e.g. `v1/content/photo.png`
+    ///
+    /// # Error
+    ///
+    /// Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are skipped
+    pub fn validate<HashType, P>(
+        &self,
+        object_root: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let options = &options.for_layout();
+        let object_root = fs::resolve_root(object_root.as_ref(), options)?;
+        let object_root = object_root.as_path();
+        let content_directory =
+            self.content_directory.as_deref().unwrap_or("content");
+
+        let progress = options.broadcast(progress);
+        let paths: Vec<PathBuf> = fs::walk(object_root, options)
+            .report_errors(&progress)
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|path| {
+                let mut components = path
+                    .strip_prefix(object_root)
+                    .unwrap_or(path)
+                    .components();
+
+                components.next().is_some()
+                    && components.next().map(|c| c.as_os_str())
+                        == Some(content_directory.as_ref())
+            })
+            .collect();
+
+        let mut report = self.content().verify_paths(
+            object_root,
+            &paths,
+            hash,
+            options,
+            progress,
+        )?;
+
+        let sidecar = object_root
+            .join(format!("inventory.json.{}", self.digest_algorithm));
+        let expected = std::fs::read_to_string(sidecar)?;
+        let expected = expected.split_whitespace().next().unwrap_or_default();
+
+        let actual =
+            crate::get_hash_file(object_root.join("inventory.json"), hash)?;
+
+        if !actual.eq_ignore_ascii_case(expected) {
+            report.push_modified(
+                PathBuf::from("inventory.json"),
+                expected.to_string(),
+                actual,
+            );
+        }
+
+        Ok(report)
+    }
+}
+
+/// Format time as RFC 3339 in UTC
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Inventory;
+    use crate::HashOptions;
+    use blake2::{Blake2b512, Digest};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn create_save_validate() {
+        let hash = Blake2b512::new();
+        let options = HashOptions::new(4);
+        let object_root = assert_fs::TempDir::new().unwrap();
+        let content_dir = object_root.path().join("v1/content");
+
+        std::fs::create_dir_all(content_dir.join("photos")).unwrap();
+        std::fs::write(content_dir.join("photos/cat.png"), "meow").unwrap();
+        std::fs::write(content_dir.join("copy.png"), "meow").unwrap();
+
+        let inventory = Inventory::create(
+            &object_root,
+            "test",
+            "blake2b-512",
+            &hash,
+            &options,
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(inventory.manifest.len(), 1); // same content, same digest
+        inventory.save(&object_root, &hash).unwrap();
+
+        let inventory = Inventory::load(&object_root).unwrap();
+        let report = inventory
+            .validate(&object_root, &hash, &options, |_| {})
+            .unwrap();
+        assert!(report.is_empty(), "{}", report);
+
+        std::fs::write(content_dir.join("copy.png"), "woof").unwrap();
+
+        let report = inventory
+            .validate(&object_root, &hash, &options, |_| {})
+            .unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(
+            report.modified[0].path,
+            PathBuf::from("v1/content/copy.png")
+        );
+    }
+
+    #[test]
+    fn rfc3339() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(super::rfc3339(time), "2023-11-14T22:13:20Z");
+    }
+}
@@ -0,0 +1,267 @@
+//! Traversal relative to directory file descriptors
+//!
+//! Every component of a path is opened with `openat` and `O_NOFOLLOW`,
+//! so a symbolic link swapped into the tree between the walk and the open fails with `ELOOP` (or `ENOTDIR`)
+//! instead of redirecting the read outside of the tree.
+//! Only **Linux** and **macOS** are supported
+
+use crate::fs::{FileEntry, Walk};
+use crate::{IOError, IOErrorKind, SkipReason};
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path};
+
+/// Walk **root** without following symbolic links anywhere below it
+///
+/// **root** itself must not contain symbolic links, e.g. it is canonical
+pub(crate) fn walk(root: &Path) -> Walk {
+    let mut walk = Walk::default();
+
+    match open_path(None, root, libc::O_DIRECTORY) {
+        Ok(dir) => walk_dir(dir, root, &mut walk),
+        Err(error) if error.raw_os_error() == Some(libc::ENOTDIR) => {
+            match std::fs::symlink_metadata(root) {
+                Ok(metadata) if metadata.is_file() => {
+                    walk.files.push(FileEntry {
+                        path: root.to_path_buf(),
+                        metadata,
+                    })
+                }
+                Ok(metadata) => walk
+                    .push_skipped(root.to_path_buf(), metadata.file_type()),
+                Err(error) => walk.push_error(root.to_path_buf(), error),
+            }
+        }
+        Err(error) => walk.push_error(root.to_path_buf(), error),
+    }
+
+    walk
+}
+
+/// Open the regular file at **path** without following symbolic links
+///
+/// # Error
+///
+/// * If a component of **path** is a symbolic link, the `ELOOP` or `ENOTDIR` error will be returned
+/// * If **path** is not a regular file, the **IOErrorKind::InvalidInput** error will be returned
+pub(crate) fn open_file(path: &Path) -> Result<File, IOError> {
+    // FIFOs would block the open until a writer shows up
+    regular_file(open_path(None, path, libc::O_NONBLOCK)?, path)
+}
+
+/// The same as [open_file], but **relative** is opened inside **root**.
+/// **root** itself may be a symbolic link
+pub(crate) fn open_file_beneath(
+    root: &Path,
+    relative: &Path,
+) -> Result<File, IOError> {
+    let root_dir = File::open(root)?;
+    let path = open_path(Some(root_dir.into()), relative, libc::O_NONBLOCK)?;
+
+    regular_file(path, &root.join(relative))
+}
+
+fn regular_file(fd: OwnedFd, path: &Path) -> Result<File, IOError> {
+    let file = File::from(fd);
+
+    if !file.metadata()?.is_file() {
+        return Err(IOError::new(
+            IOErrorKind::InvalidInput,
+            format!("{} is not a regular file", path.display()),
+        ));
+    }
+
+    Ok(file)
+}
+
+fn walk_dir(dir: OwnedFd, path: &Path, walk: &mut Walk) {
+    let fd = dir.into_raw_fd();
+
+    // SAFETY: `fd` is an open directory, on success the stream owns it
+    let stream = unsafe { libc::fdopendir(fd) };
+    if stream.is_null() {
+        let error = IOError::last_os_error();
+        // SAFETY: `fd` is still owned by us
+        unsafe { libc::close(fd) };
+        walk.push_error(path.to_path_buf(), error);
+        return;
+    }
+
+    loop {
+        // SAFETY: errno is thread-local, readdir only sets it on error
+        unsafe { *errno() = 0 };
+        // SAFETY: `stream` is open until closedir below
+        let entry = unsafe { libc::readdir(stream) };
+
+        if entry.is_null() {
+            if unsafe { *errno() } != 0 {
+                walk.push_error(path.to_path_buf(), IOError::last_os_error());
+            }
+            break;
+        }
+
+        // SAFETY: `d_name` of a returned entry is a NUL-terminated string
+        let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+        if name.to_bytes() == b"." || name.to_bytes() == b".." {
+            continue;
+        }
+
+        let entry_path = path.join(OsStr::from_bytes(name.to_bytes()));
+        // SAFETY: `stream` is open
+        let dir_fd = unsafe { libc::dirfd(stream) };
+
+        // SAFETY: `stat` is plain data, fstatat fills it on success
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe {
+            libc::fstatat(
+                dir_fd,
+                name.as_ptr(),
+                &mut stat,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        } == -1
+        {
+            walk.push_error(entry_path, IOError::last_os_error());
+            continue;
+        }
+
+        match stat.st_mode & libc::S_IFMT {
+            libc::S_IFDIR => match openat(dir_fd, name, libc::O_DIRECTORY) {
+                Ok(child) => walk_dir(child, &entry_path, walk),
+                Err(error) => walk.push_error(entry_path, error),
+            },
+            libc::S_IFREG => match std::fs::symlink_metadata(&entry_path) {
+                // Hashing opens the file again with O_NOFOLLOW, the metadata is only informational
+                Ok(metadata) if metadata.is_file() => {
+                    walk.files.push(FileEntry {
+                        path: entry_path,
+                        metadata,
+                    })
+                }
+                Ok(metadata) => {
+                    walk.push_skipped(entry_path, metadata.file_type())
+                }
+                Err(error) => walk.push_error(entry_path, error),
+            },
+            libc::S_IFLNK => {
+                walk.skipped.push((entry_path, SkipReason::Symlink))
+            }
+            // Sockets, devices, ...
+            _ => walk.skipped.push((entry_path, SkipReason::SpecialFile)),
+        }
+    }
+
+    // SAFETY: `stream` is open and not used afterwards
+    unsafe { libc::closedir(stream) };
+}
+
+/// Open every component of **path** relative to the previous one, starting at **dir** or the current folder
+fn open_path(
+    mut dir: Option<OwnedFd>,
+    path: &Path,
+    flags: libc::c_int,
+) -> Result<OwnedFd, IOError> {
+    let mut components: Vec<&OsStr> = Vec::new();
+
+    for component in path.components() {
+        match component {
+            Component::RootDir => components.push(OsStr::new("/")),
+            Component::Normal(name) => components.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidInput,
+                    format!("{} is not canonical", path.display()),
+                ))
+            }
+        }
+    }
+
+    let (last, parents) = match components.split_last() {
+        Some(split) => split,
+        None => (&OsStr::new("."), &[][..]),
+    };
+
+    for name in parents {
+        let fd = dir.as_ref().map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd());
+        dir = Some(openat(fd, &c_name(name)?, libc::O_DIRECTORY)?);
+    }
+
+    let fd = dir.as_ref().map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd());
+    openat(fd, &c_name(last)?, flags)
+}
+
+fn openat(
+    dir: libc::c_int,
+    name: &CStr,
+    flags: libc::c_int,
+) -> Result<OwnedFd, IOError> {
+    let flags = flags | libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+    // SAFETY: `name` is NUL-terminated, `dir` is open or AT_FDCWD
+    match unsafe { libc::openat(dir, name.as_ptr(), flags) } {
+        -1 => Err(IOError::last_os_error()),
+        // SAFETY: the descriptor was just opened and is owned by nobody else
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
+
+fn c_name(name: &OsStr) -> Result<CString, IOError> {
+    CString::new(name.as_bytes())
+        .map_err(|error| IOError::new(IOErrorKind::InvalidInput, error))
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__error()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    #[test]
+    fn walk_and_open_without_symlinks() {
+        let outside = assert_fs::TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret"), "secret").unwrap();
+
+        let root = assert_fs::TempDir::new().unwrap();
+        let root = root.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("dir")).unwrap();
+        std::fs::write(root.join("dir/file"), "file").unwrap();
+        symlink(outside.path().join("secret"), root.join("link")).unwrap();
+        symlink(outside.path(), root.join("dir/outside")).unwrap();
+
+        let walk = super::walk(&root);
+        assert!(walk.errors.is_empty(), "{:?}", walk.errors);
+        assert_eq!(walk.paths(), vec![root.join("dir/file")]);
+
+        assert!(super::open_file(&root.join("dir/file")).is_ok());
+        for path in [root.join("link"), root.join("dir/outside/secret")] {
+            let error = super::open_file(&path).unwrap_err();
+            assert!(
+                matches!(
+                    error.raw_os_error(),
+                    Some(libc::ELOOP) | Some(libc::ENOTDIR)
+                ),
+                "{:?}: {}",
+                path,
+                error
+            );
+        }
+
+        let error = super::open_file(&root.join("dir")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let error = super::open_file(&PathBuf::from("../file")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,742 @@
+//! Settings of hashing runs
+
+use crate::encoding::{Encoder, Encoding};
+use crate::{DynDigest, IOError, IOErrorKind, ProgressInfo, SkipReason};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Settings of a hashing run
+///
+/// # Example
+///
+/// ```no_run
+/// use file_hashing::HashOptions;
+///
+/// let options = HashOptions::new(12)
+///     .root_threads("/mnt/smb-share", 2) // slow network share
+///     .network_threads(Some(2)) // and every other detected network filesystem
+///     .subscribe(|info| eprintln!("{:?}", info)); // log besides the progress closure
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashOptions {
+    pub(crate) num_threads: usize,
+    pub(crate) root_threads: Vec<(PathBuf, usize)>,
+    pub(crate) network_threads: Option<usize>,
+    pub(crate) subscribers: Subscribers,
+    pub(crate) cancel_token: Option<CancelToken>,
+    pub(crate) no_cache: bool,
+    pub(crate) numa: bool,
+    pub(crate) buffer_alignment: usize,
+    pub(crate) parallel_walk: bool,
+    pub(crate) lock: bool,
+    pub(crate) detect_changes: Option<usize>,
+    pub(crate) canonicalize: bool,
+    pub(crate) no_follow: bool,
+    pub(crate) reparse_points: ReparsePolicy,
+    pub(crate) jail: Option<PathBuf>,
+    pub(crate) max_files: Option<usize>,
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) include_sizes: bool,
+    pub(crate) shared_extents: bool,
+    pub(crate) base: Option<PathBuf>,
+    pub(crate) native_separators: bool,
+    pub(crate) encoder: Option<EncoderRef>,
+    pub(crate) thread_pool: Option<crate::pool::Pool>,
+    pub(crate) deterministic: bool,
+    pub(crate) single_thread: bool,
+    pub(crate) bounded_memory: bool,
+    pub(crate) skip_names: Vec<OsString>,
+}
+
+/// Token to cancel a hashing run from another thread
+///
+/// Files that are being hashed are finished, the rest are left pending
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{manifest::Manifest, options::CancelToken, HashOptions};
+///
+/// let token = CancelToken::new();
+/// let options = HashOptions::new(12).cancel_token(token.clone());
+///
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(8 * 60 * 60));
+///     token.cancel();
+/// });
+///
+/// let (manifest, pending) =
+///     Manifest::create_partial("/home/gladi", &Blake2s256::new(), &options, |_| {})
+///         .unwrap();
+/// println!("{} files hashed, {} pending", manifest.len(), pending.len());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+/// What a folder walk does with NTFS junctions and symbolic links, see [HashOptions::reparse_points]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReparsePolicy {
+    /// Leave them out, they are reported as [ProgressInfo::Skipped]
+    #[default]
+    Skip,
+
+    /// Walk the folders and files they point to.
+    /// A target that is already part of the walk (e.g. a junction to a parent folder) is skipped instead
+    Follow,
+
+    /// Report them as **IOErrorKind::Unsupported** errors
+    Error,
+}
+
+/// Limit of a run, see [HashOptions::max_files] and [HashOptions::max_bytes]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Files(usize),
+    Bytes(u64),
+}
+
+/// Result of a run with limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome<T> {
+    /// Every file was processed
+    Complete(T),
+
+    /// The run stopped at **limit**, **partial** covers only the files before it
+    LimitReached { partial: T, limit: Limit },
+}
+
+impl<T> Outcome<T> {
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Outcome::Complete(_))
+    }
+
+    /// The limit that was reached, if any
+    pub fn limit(&self) -> Option<Limit> {
+        match self {
+            Outcome::Complete(_) => None,
+            Outcome::LimitReached { limit, .. } => Some(*limit),
+        }
+    }
+
+    /// The result, complete or partial
+    pub fn into_inner(self) -> T {
+        match self {
+            Outcome::Complete(result) => result,
+            Outcome::LimitReached { partial, .. } => partial,
+        }
+    }
+}
+
+type Subscriber = Arc<dyn Fn(&ProgressInfo) + Send + Sync>;
+
+/// Progress consumers registered with [HashOptions::subscribe]
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers(Vec<Subscriber>);
+
+impl std::fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Subscribers({})", self.0.len())
+    }
+}
+
+impl PartialEq for Subscribers {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Subscribers {}
+
+/// Encoder set with [HashOptions::encoder]
+#[derive(Clone)]
+pub(crate) struct EncoderRef(Arc<dyn Encoder>);
+
+impl std::fmt::Debug for EncoderRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncoderRef")
+    }
+}
+
+impl PartialEq for EncoderRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for EncoderRef {}
+
+/// Number of threads the process can run in parallel
+///
+/// Unlike the raw core count this honors CPU affinity and cgroup CPU quotas on **Linux**,
+/// so a container limited to half a CPU gets 1 thread instead of one per core of the host
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+impl HashOptions {
+    /// Use **num_threads** threads for hashing, `0` means [default_threads]
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            num_threads,
+            root_threads: Vec::new(),
+            network_threads: None,
+            subscribers: Subscribers::default(),
+            cancel_token: None,
+            no_cache: false,
+            numa: false,
+            buffer_alignment: crate::io::DEFAULT_ALIGNMENT,
+            parallel_walk: false,
+            lock: false,
+            detect_changes: None,
+            canonicalize: false,
+            no_follow: false,
+            reparse_points: ReparsePolicy::Skip,
+            jail: None,
+            max_files: None,
+            max_bytes: None,
+            include_sizes: false,
+            shared_extents: false,
+            base: None,
+            native_separators: false,
+            encoder: None,
+            thread_pool: None,
+            deterministic: false,
+            single_thread: false,
+            bounded_memory: false,
+            skip_names: Vec::new(),
+        }
+    }
+
+    /// Use [default_threads] threads for hashing
+    pub fn auto() -> Self {
+        Self::new(default_threads())
+    }
+
+    /// Run everything on the calling thread, without a pool and without spawning threads
+    ///
+    /// For environments that forbid spawning threads, e.g. plugins or signal handlers.
+    /// **num_threads**, [HashOptions::thread_pool], [HashOptions::root_threads] and [HashOptions::network_threads] are then ignored
+    pub fn single_thread(mut self, single_thread: bool) -> Self {
+        self.single_thread = single_thread;
+        self
+    }
+
+    /// Hash folders without holding the paths of all of their files, e.g. for trees with tens of millions of files
+    ///
+    /// Folders are read depth-first in path order while their files are hashed in windows of
+    /// a few hundred files per thread, so memory grows with the number of threads,
+    /// the depth of the tree and the size of its largest folder instead of the number of files.
+    /// The combined hash is the same as without it.
+    /// [HashOptions::parallel_walk] is then ignored, with [HashOptions::no_follow] or a
+    /// [HashOptions::reparse_points] policy other than [ReparsePolicy::Skip] folders are still walked up front
+    /// and only the hashing is done in windows
+    pub fn bounded_memory(mut self, bounded_memory: bool) -> Self {
+        self.bounded_memory = bounded_memory;
+        self
+    }
+
+    /// Leave out files named **name** in every folder of a walk, e.g. the `SHA256SUMS` of
+    /// [Manifest::create_nested](crate::manifest::Manifest::create_nested)
+    ///
+    /// They are reported through **progress** as [ProgressInfo::Skipped] with [SkipReason::Filter].
+    /// Can be called several times to leave out several names
+    pub fn skip_file_name(mut self, name: impl Into<OsString>) -> Self {
+        self.skip_names.push(name.into());
+        self
+    }
+
+    /// Whether the file at **path** is left out, see [HashOptions::skip_file_name]
+    pub(crate) fn skips_file(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| {
+            self.skip_names.iter().any(|skip| skip == name)
+        })
+    }
+
+    /// Use at most **num_threads** threads for files under **root**
+    ///
+    /// If roots are nested, the longest one wins
+    pub fn root_threads<P: AsRef<Path>>(
+        mut self,
+        root: P,
+        num_threads: usize,
+    ) -> Self {
+        self.root_threads
+            .push((root.as_ref().to_path_buf(), num_threads));
+        self
+    }
+
+    /// Use at most **num_threads** threads for each detected network filesystem (NFS, SMB, SSHFS, ...)
+    ///
+    /// Detection is only supported on **Linux**, elsewhere use [HashOptions::root_threads]
+    pub fn network_threads(mut self, num_threads: Option<usize>) -> Self {
+        self.network_threads = num_threads;
+        self
+    }
+
+    /// Also send progress of the run to **subscriber**
+    ///
+    /// Every subscriber gets every [ProgressInfo] before the progress closure of the function does,
+    /// so a logger, a progress bar and a metrics exporter can watch the same run
+    pub fn subscribe(
+        mut self,
+        subscriber: impl Fn(&ProgressInfo) + Send + Sync + 'static,
+    ) -> Self {
+        self.subscribers.0.push(Arc::new(subscriber));
+        self
+    }
+
+    /// Bypass the page cache when reading files
+    ///
+    /// Large verification runs then don't push everything else out of the cache.
+    /// Only **macOS** (`F_NOCACHE`) is supported, elsewhere the option is ignored
+    pub fn no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    /// Make runs reproducible, e.g. for fuzzing and golden-output tests
+    ///
+    /// * files, errors and skipped entries of folder walks are sorted by path, whatever the filesystem returns
+    /// * [get_hash_files_from_channel](crate::file::get_hash_files_from_channel) hashes on one worker,
+    ///   so results arrive in the order of the channel
+    ///
+    /// Combined hashes and progress of the other functions don't depend on scheduling anyway.
+    /// Fixtures of [extra](crate::fs::extra) are made reproducible with the `FILE_HASHING_SEED` environment variable
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Hash on **pool** instead of pools of the crate
+    ///
+    /// For applications that budget their threads themselves, the run then spawns no threads of its own
+    /// except for [HashOptions::root_threads] and [HashOptions::network_threads].
+    /// Functions without options use the pool they are called from (e.g. inside `pool.install`) the same way,
+    /// functions with options never do, so the threads of [HashOptions::new] hold even inside a `par_iter`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::HashOptions;
+    /// use std::sync::Arc;
+    ///
+    /// let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap());
+    /// let options = HashOptions::new(4).thread_pool(pool);
+    /// ```
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.thread_pool = Some(crate::pool::Pool::from_rayon(pool));
+        self
+    }
+
+    /// Pool of the run, see [HashOptions::thread_pool] and [HashOptions::single_thread]
+    pub(crate) fn pool(&self) -> crate::pool::Pool {
+        if self.single_thread {
+            return crate::pool::Pool::inline();
+        }
+
+        match &self.thread_pool {
+            Some(pool) => pool.clone(),
+            None => crate::pool::Pool::shared(self.num_threads, self.numa),
+        }
+    }
+
+    /// Pin hashing threads to NUMA nodes, round-robin
+    ///
+    /// Buffers of a thread are then allocated on its node, which helps on multi-socket servers.
+    /// Only **Linux** is supported, elsewhere the option is ignored
+    pub fn numa(mut self, numa: bool) -> Self {
+        self.numa = numa;
+        self
+    }
+
+    /// Align read buffers to **alignment** bytes (64 by default)
+    ///
+    /// The default suits SIMD implementations of BLAKE2, BLAKE3 and SHA-NI.
+    /// Use the block size of the device (usually 4096) for `O_DIRECT`.
+    /// If the **alignment** is not a power of two, hashing fails with **IOErrorKind::InvalidInput**
+    pub fn buffer_alignment(mut self, alignment: usize) -> Self {
+        self.buffer_alignment = alignment;
+        self
+    }
+
+    /// Read folders in parallel, see [get_all_file_with_metadata_parallel](crate::fs::get_all_file_with_metadata_parallel)
+    ///
+    /// Worth it for trees with millions of entries, where the walk takes longer than hashing
+    pub fn parallel_walk(mut self, parallel_walk: bool) -> Self {
+        self.parallel_walk = parallel_walk;
+        self
+    }
+
+    /// Hold a shared advisory lock (`flock` on Unix, `LockFileEx` on Windows) on every file while hashing it
+    ///
+    /// Writers that take an exclusive lock then can't change the file mid-hash.
+    /// Hashing waits until such a writer releases its lock.
+    /// Only **Linux**, **macOS** and **Windows** are supported, elsewhere opening files fails with **IOErrorKind::Unsupported**
+    pub fn lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Compare size and modification time of every file before and after hashing it
+    ///
+    /// A file that changed mid-read is hashed again up to **retries** times, every retry is sent to the subscribers
+    /// (see [HashOptions::subscribe]) as [ProgressInfo::Retried].
+    /// If it is still changing, its result is the **IOErrorKind::Other** error instead of a torn hash.
+    /// `None` (the default) turns the check off
+    pub fn detect_changes(mut self, retries: Option<usize>) -> Self {
+        self.detect_changes = retries;
+        self
+    }
+
+    /// Canonicalize the folder before walking it (resolve `..`, symbolic links and, on Windows, the case of the drive letter)
+    ///
+    /// Paths of the walk are then absolute and the same for every spelling of the folder,
+    /// so the same physical file never shows up twice
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Walk folders and open files relative to directory file descriptors (`openat` with `O_NOFOLLOW`)
+    ///
+    /// Symbolic links below the folder are never followed, even if one is swapped in between the walk and the open,
+    /// so hashing an untrusted tree can't be redirected to files outside of it.
+    /// The folder is canonicalized first and [HashOptions::parallel_walk] is ignored.
+    /// Only **Linux** and **macOS** are supported, elsewhere opening files fails with **IOErrorKind::Unsupported**
+    pub fn no_follow(mut self, no_follow: bool) -> Self {
+        self.no_follow = no_follow;
+        self
+    }
+
+    /// Handling of NTFS junctions, symbolic links and other name-surrogate reparse points during folder walks
+    ///
+    /// Following them can create cycles or pull in other volumes, so they are skipped by default.
+    /// Other reparse points (e.g. deduplicated or cloud files) are hashed like regular files.
+    /// Only **Windows** is supported, elsewhere symbolic links are always skipped
+    pub fn reparse_points(mut self, policy: ReparsePolicy) -> Self {
+        self.reparse_points = policy;
+        self
+    }
+
+    /// Never open or include a file outside of **root**, e.g. for hashing untrusted uploads
+    ///
+    /// Files of the walk outside of **root** (e.g. through `..`) and files opened through a symbolic link
+    /// are reported as **IOErrorKind::PermissionDenied** errors and left out.
+    /// On **Linux** and **macOS** every component below **root** is opened with `O_NOFOLLOW`,
+    /// elsewhere the canonical path of the file is checked before it is opened.
+    /// Paths are compared as spelled, so spell **root** the same way as the hashed folder
+    pub fn jail<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.jail = Some(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Stop after **max_files** files
+    ///
+    /// Only functions that return an [Outcome] honor the limit, e.g. [Manifest::create_bounded](crate::manifest::Manifest::create_bounded)
+    pub fn max_files(mut self, max_files: Option<usize>) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Stop before the file that would bring the total size of the run over **max_bytes**
+    ///
+    /// Only functions that return an [Outcome] honor the limit, e.g. [Manifest::create_bounded](crate::manifest::Manifest::create_bounded)
+    pub fn max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Mix the size of every file into the combined hash of several files or a folder
+    ///
+    /// The encoded hash of every file is followed by its size as 8 bytes little-endian,
+    /// so e.g. a file truncated to zero bytes changes the result even if its hash is never compared on its own.
+    /// See [get_hash_files_with_factory](crate::file::get_hash_files_with_factory) for the whole format
+    pub fn include_sizes(mut self, include_sizes: bool) -> Self {
+        self.include_sizes = include_sizes;
+        self
+    }
+
+    /// Hash reflinked copies only once
+    ///
+    /// Files of the same size whose extents are all shared and lie at the same physical offsets
+    /// (`FS_IOC_FIEMAP`, e.g. `cp --reflink` on btrfs or XFS) have the same content, so only one of them is read.
+    /// Used by [find_duplicates](crate::dedup::find_duplicates).
+    /// Only **Linux** is supported, elsewhere the option is ignored
+    pub fn shared_extents(mut self, shared_extents: bool) -> Self {
+        self.shared_extents = shared_extents;
+        self
+    }
+
+    /// Keep the files of **entries** that fit into the limits of the run, in order.
+    /// The rest is reported through **progress** as [ProgressInfo::Skipped]
+    pub(crate) fn apply_limits(
+        &self,
+        mut entries: Vec<crate::fs::FileEntry>,
+        progress: &impl Fn(ProgressInfo),
+    ) -> (Vec<crate::fs::FileEntry>, Option<Limit>) {
+        let mut total: u64 = 0;
+        let mut limit = None;
+
+        for (index, entry) in entries.iter().enumerate() {
+            if self.max_files.is_some_and(|max| index >= max) {
+                limit = Some((index, Limit::Files(index)));
+                break;
+            }
+
+            total = total.saturating_add(entry.size());
+            if let Some(max) = self.max_bytes.filter(|max| total > *max) {
+                limit = Some((index, Limit::Bytes(max)));
+                break;
+            }
+        }
+
+        let (index, limit) = match limit {
+            Some(limit) => limit,
+            None => return (entries, None),
+        };
+
+        for entry in entries.split_off(index) {
+            progress(ProgressInfo::Skipped {
+                path: entry.path,
+                reason: SkipReason::Limit,
+            });
+        }
+
+        (entries, Some(limit))
+    }
+
+    /// Record paths relative to **base** instead of the hashed folder
+    ///
+    /// Used by [Manifest](crate::manifest::Manifest), [Snapshot](crate::snapshot::Snapshot) and [verify_folder](crate::verify::verify_folder).
+    /// E.g. with base `/data` the files of `/data/photos` are recorded as `photos/...`,
+    /// so a manifest of several folders stays valid wherever `/data` is mounted.
+    /// Files outside of **base** keep their full path
+    pub fn base<P: AsRef<Path>>(mut self, base: P) -> Self {
+        self.base = Some(base.as_ref().to_path_buf());
+        self
+    }
+
+    /// Record paths with the separator of the platform instead of `/`
+    ///
+    /// By default recorded paths always use `/`, so a manifest written on Windows verifies on Linux and vice versa.
+    /// Only **Windows** is affected, elsewhere the separator is `/` anyway
+    pub fn native_separators(mut self, native_separators: bool) -> Self {
+        self.native_separators = native_separators;
+        self
+    }
+
+    /// Format every hash of the run with **encoder** instead of **hex lower**
+    ///
+    /// Used by all functions that take [HashOptions], e.g. [Manifest](crate::manifest::Manifest) and [Snapshot](crate::snapshot::Snapshot),
+    /// except for formats with a fixed layout. Functions without options always return **hex lower**, see the crate docs
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use file_hashing::{encoding::Encoding, HashOptions};
+    ///
+    /// let options = HashOptions::new(12).encoder(Encoding::Base64);
+    /// ```
+    pub fn encoder(mut self, encoder: impl Encoder + 'static) -> Self {
+        self.encoder = Some(EncoderRef(Arc::new(encoder)));
+        self
+    }
+
+    /// Finish **hash** as text with the encoder of the run, `hash` itself is not finalized
+    pub(crate) fn encode<HashType>(&self, hash: &HashType) -> String
+    where
+        HashType: DynDigest + ?Sized,
+    {
+        match &self.encoder {
+            Some(encoder) => crate::encoding::encoded(hash, &*encoder.0),
+            None => crate::encoding::encoded(hash, &Encoding::HexLower),
+        }
+    }
+
+    /// The same options for formats with a fixed layout (BagIt, OCFL, checksum files, ...):
+    /// paths are relative to the folder itself and hashes are **hex lower**
+    pub(crate) fn for_layout(&self) -> Self {
+        Self {
+            base: None,
+            encoder: None,
+            ..self.clone()
+        }
+    }
+
+    /// Stop the run when **token** is cancelled
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel_token
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// # Error
+    ///
+    /// * If the run is cancelled, the **IOErrorKind::Interrupted** error will be returned
+    pub(crate) fn check_cancelled(&self) -> Result<(), IOError> {
+        match self.is_cancelled() {
+            true => Err(IOError::new(IOErrorKind::Interrupted, "cancelled")),
+            false => Ok(()),
+        }
+    }
+
+    /// Send **info** to the subscribers only, e.g. from the threads that hash
+    pub(crate) fn notify(&self, info: ProgressInfo) {
+        for subscriber in self.subscribers.0.iter() {
+            subscriber(&info);
+        }
+    }
+
+    /// Wrap **progress** so that the subscribers see everything it does
+    pub(crate) fn broadcast<'a>(
+        &'a self,
+        progress: impl Fn(ProgressInfo) + 'a,
+    ) -> impl Fn(ProgressInfo) + 'a {
+        move |info| {
+            for subscriber in self.subscribers.0.iter() {
+                subscriber(&info);
+            }
+
+            progress(info);
+        }
+    }
+
+    /// Find the root that limits **path** and its number of threads
+    pub(crate) fn limit_for(
+        &self,
+        path: &Path,
+        network_mounts: &[PathBuf],
+    ) -> Option<(PathBuf, usize)> {
+        let root = self
+            .root_threads
+            .iter()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count());
+
+        if let Some((root, num_threads)) = root {
+            return Some((root.clone(), *num_threads));
+        }
+
+        let num_threads = self.network_threads?;
+        let absolute =
+            std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        network_mounts
+            .iter()
+            .filter(|mount| absolute.starts_with(mount))
+            .max_by_key(|mount| mount.components().count())
+            .map(|mount| (mount.clone(), num_threads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashOptions;
+    use crate::ProgressInfo;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn auto() {
+        let threads = super::default_threads();
+        assert!(threads >= 1);
+        assert_eq!(HashOptions::auto().num_threads, threads);
+    }
+
+    #[test]
+    fn limit_for() {
+        let options = HashOptions::new(12)
+            .root_threads("/mnt", 4)
+            .root_threads("/mnt/smb", 2)
+            .network_threads(Some(1));
+        let network = vec![PathBuf::from("/media/nfs")];
+
+        assert_eq!(
+            options.limit_for(Path::new("/mnt/smb/file"), &network),
+            Some((PathBuf::from("/mnt/smb"), 2))
+        );
+        assert_eq!(
+            options.limit_for(Path::new("/mnt/local/file"), &network),
+            Some((PathBuf::from("/mnt"), 4))
+        );
+        assert_eq!(
+            options.limit_for(Path::new("/media/nfs/file"), &network),
+            Some((PathBuf::from("/media/nfs"), 1))
+        );
+        assert_eq!(
+            options.limit_for(Path::new("/home/file"), &network),
+            None
+        );
+    }
+
+    #[test]
+    fn subscribe() {
+        let files = Arc::new(AtomicU64::new(0));
+        let errors = Arc::new(AtomicU64::new(0));
+
+        let options = {
+            let files = files.clone();
+            let errors = errors.clone();
+
+            HashOptions::new(4)
+                .subscribe(move |info| {
+                    if let ProgressInfo::Yield(done_files) = info {
+                        files.store(*done_files, Ordering::SeqCst);
+                    }
+                })
+                .subscribe(move |info| {
+                    if let ProgressInfo::Error(_) = info {
+                        errors.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+        };
+
+        let calls = AtomicU64::new(0);
+        let progress = options.broadcast(|_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+
+        progress(ProgressInfo::Yield(1));
+        progress(ProgressInfo::Yield(2));
+        progress(ProgressInfo::Error(std::io::Error::from(
+            std::io::ErrorKind::NotFound,
+        )));
+
+        assert_eq!(files.load(Ordering::SeqCst), 2);
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,298 @@
+//! Overlapped IO read path for **Windows**
+//!
+//! Several reads of one file are kept in flight at the same time,
+//! which keeps fast NVMe drives and network shares busy while the previous block is hashed
+
+use super::*;
+use std::fs::OpenOptions;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_HANDLE_EOF, ERROR_IO_PENDING, HANDLE,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    ReadFile, FILE_FLAG_OVERLAPPED,
+};
+use windows_sys::Win32::System::Threading::CreateEventW;
+use windows_sys::Win32::System::IO::{
+    CancelIoEx, GetOverlappedResult, OVERLAPPED,
+};
+
+/// Size of one read
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Get hash from **file** with up to **queue_depth** overlapped reads in flight
+///
+/// The result is the same as with [get_hash_file](crate::get_hash_file)
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::overlapped::get_hash_file_overlapped;
+///
+/// let hash = Blake2s256::new();
+/// let result =
+///     get_hash_file_overlapped("C:\\Users\\gladi\\backup.tar", &hash, 8).unwrap();
+///
+/// assert_eq!(result.len(), 64); // Blake2s256 len == 64
+/// ```
+///
+/// # Error
+///
+/// * If the **queue_depth** is 0, the **IOErrorKind::InvalidInput** error will be returned
+pub fn get_hash_file_overlapped<HashType, P>(
+    path: P,
+    hash: &HashType,
+    queue_depth: usize,
+) -> Result<String, IOError>
+where
+    HashType: DynDigest + Clone,
+    P: AsRef<Path>,
+{
+    if queue_depth == 0 {
+        return Err(IOError::from(IOErrorKind::InvalidInput));
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_OVERLAPPED)
+        .open(path)?;
+    let handle = file.as_raw_handle() as HANDLE;
+
+    let mut hash = hash.clone();
+    let mut slots = Vec::with_capacity(queue_depth);
+    for _ in 0..queue_depth {
+        slots.push(Slot::new()?);
+    }
+
+    let mut offset = 0u64;
+    let mut in_flight = 0;
+    let mut eof = false;
+    let mut result = Ok(());
+
+    for slot in slots.iter_mut() {
+        // SAFETY: the slot is not moved or reused until its read is finished
+        match unsafe { slot.start(handle, offset) } {
+            Ok(true) => in_flight += 1,
+            Ok(false) => {}
+            Err(error) => {
+                // Reads that are already in flight are still waited for below
+                eof = true;
+                result = Err(error);
+                break;
+            }
+        }
+        offset += BLOCK_SIZE as u64;
+    }
+
+    let mut index = 0;
+
+    // Reads are finished in the order they were started, so blocks are hashed in file order
+    while in_flight > 0 {
+        let slot = &mut slots[index];
+        index = (index + 1) % queue_depth;
+
+        if !slot.pending {
+            continue;
+        }
+
+        in_flight -= 1;
+        // SAFETY: the slot has a pending read on `handle`
+        let read = match unsafe { slot.finish(handle) } {
+            Ok(read) => read,
+            Err(error) => {
+                eof = true;
+                result = Err(error);
+                continue;
+            }
+        };
+
+        if eof {
+            continue;
+        }
+
+        hash.update(&slot.buffer[..read]);
+
+        if read < BLOCK_SIZE {
+            eof = true;
+            continue;
+        }
+
+        // SAFETY: the previous read of the slot is finished
+        match unsafe { slot.start(handle, offset) } {
+            Ok(true) => in_flight += 1,
+            Ok(false) => eof = true,
+            Err(error) => {
+                eof = true;
+                result = Err(error);
+            }
+        }
+        offset += BLOCK_SIZE as u64;
+    }
+
+    result?;
+    Ok(crate::encoding::get_lowerhex(&mut hash))
+}
+
+/// Buffer with its own OVERLAPPED and event
+///
+/// A slot with a pending read cancels it and waits for it when dropped,
+/// so the kernel never writes into a freed buffer
+struct Slot {
+    overlapped: Box<OVERLAPPED>,
+    buffer: Vec<u8>,
+    pending: bool,
+
+    /// File of the pending read
+    handle: HANDLE,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Offset at which [Slot::start] fails, to test the error path
+    static FAIL_START_AT: std::cell::Cell<Option<u64>> =
+        const { std::cell::Cell::new(None) };
+}
+
+impl Slot {
+    fn new() -> Result<Self, IOError> {
+        // SAFETY: manual-reset event without name and security attributes
+        let event =
+            unsafe { CreateEventW(std::ptr::null(), 1, 0, std::ptr::null()) };
+        if event.is_null() {
+            return Err(IOError::last_os_error());
+        }
+
+        // SAFETY: OVERLAPPED is a plain C struct, all zeroes is valid
+        let mut overlapped: Box<OVERLAPPED> =
+            Box::new(unsafe { std::mem::zeroed() });
+        overlapped.hEvent = event;
+
+        Ok(Self {
+            overlapped,
+            buffer: vec![0; BLOCK_SIZE],
+            pending: false,
+            handle: std::ptr::null_mut(),
+        })
+    }
+
+    /// Start read at **offset**, `false` means the end of file
+    ///
+    /// # Safety
+    ///
+    /// The slot must not have a pending read
+    unsafe fn start(
+        &mut self,
+        handle: HANDLE,
+        offset: u64,
+    ) -> Result<bool, IOError> {
+        #[cfg(test)]
+        if FAIL_START_AT.get() == Some(offset) {
+            return Err(IOError::other("failing start for the test"));
+        }
+
+        self.overlapped.Anonymous.Anonymous.Offset = offset as u32;
+        self.overlapped.Anonymous.Anonymous.OffsetHigh =
+            (offset >> 32) as u32;
+
+        let ok = ReadFile(
+            handle,
+            self.buffer.as_mut_ptr(),
+            BLOCK_SIZE as u32,
+            std::ptr::null_mut(),
+            &mut *self.overlapped,
+        );
+
+        if ok == 0 {
+            match GetLastError() {
+                ERROR_IO_PENDING => {}
+                ERROR_HANDLE_EOF => return Ok(false),
+                _ => return Err(IOError::last_os_error()),
+            }
+        }
+
+        self.pending = true;
+        self.handle = handle;
+        Ok(true)
+    }
+
+    /// Wait for the pending read and get the number of bytes read
+    ///
+    /// # Safety
+    ///
+    /// The slot must have a pending read on **handle**
+    unsafe fn finish(&mut self, handle: HANDLE) -> Result<usize, IOError> {
+        self.pending = false;
+        let mut read = 0;
+
+        if GetOverlappedResult(handle, &*self.overlapped, &mut read, 1) == 0 {
+            return match GetLastError() {
+                ERROR_HANDLE_EOF => Ok(0),
+                _ => Err(IOError::last_os_error()),
+            };
+        }
+
+        Ok(read as usize)
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if self.pending {
+            let mut read = 0;
+
+            // SAFETY: the read is pending on `handle`, which is still open because
+            // slots are dropped before the file. The wait is needed even if the
+            // cancellation fails, the read may still be in flight
+            unsafe {
+                CancelIoEx(self.handle, &*self.overlapped);
+                GetOverlappedResult(
+                    self.handle,
+                    &*self.overlapped,
+                    &mut read,
+                    1,
+                );
+            }
+        }
+
+        // SAFETY: the event was created in `Slot::new`
+        unsafe { CloseHandle(self.overlapped.hEvent) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::extra;
+    use blake2::{Blake2s256, Digest};
+
+    #[test]
+    fn get_hash_file_overlapped() {
+        let (_temp_dir, path) =
+            extra::generate_random_file(3 * 1024 * 1024 + 17);
+
+        let hash = Blake2s256::new();
+        let expected = crate::get_hash_file(&path, &hash).unwrap();
+
+        for queue_depth in [1, 2, 8] {
+            let result =
+                super::get_hash_file_overlapped(&path, &hash, queue_depth)
+                    .unwrap();
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn failing_start() {
+        let (_temp_dir, path) = extra::generate_random_file(8 * 1024 * 1024);
+
+        // The third read fails while the first two are still in flight
+        super::FAIL_START_AT.set(Some(2 * super::BLOCK_SIZE as u64));
+        let result =
+            super::get_hash_file_overlapped(&path, &Blake2s256::new(), 4);
+        super::FAIL_START_AT.set(None);
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,572 @@
+//! Worker threads
+//!
+//! Without the `parallel` feature the workers are scoped threads ([std::thread::scope]) that take jobs from a shared queue.
+//! On targets without threads (WebAssembly, e.g. `wasm32-wasip1`) every job runs on the current thread
+
+/// Number of pools [Pool::shared] keeps, so sizing runs per request doesn't keep idle threads of every size
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+const MAX_SHARED_POOLS: usize = 8;
+
+/// Pool of hashing workers
+#[derive(Clone)]
+pub(crate) struct Pool {
+    /// Every job runs on the calling thread, see [HashOptions::single_thread](crate::HashOptions::single_thread)
+    inline: bool,
+
+    /// `None` runs on the rayon pool of the calling thread
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pool: Option<std::sync::Arc<rayon::ThreadPool>>,
+
+    /// Workers of [Pool::map_with] without rayon
+    #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+    num_threads: usize,
+}
+
+impl std::fmt::Debug for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pool")
+    }
+}
+
+impl PartialEq for Pool {
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    fn eq(&self, other: &Self) -> bool {
+        let pool = match (&self.pool, &other.pool) {
+            (Some(a), Some(b)) => std::sync::Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        pool && self.inline == other.inline
+    }
+
+    #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+    fn eq(&self, other: &Self) -> bool {
+        self.num_threads == other.num_threads && self.inline == other.inline
+    }
+
+    #[cfg(target_family = "wasm")]
+    fn eq(&self, other: &Self) -> bool {
+        self.inline == other.inline
+    }
+}
+
+impl Eq for Pool {}
+
+/// Pools of [Pool::shared], most recently used last
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+struct SharedPools(Vec<((usize, bool), Pool)>);
+
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+impl SharedPools {
+    const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Pool for **num_threads** and **numa**, the least recently used one is dropped beyond **max** pools
+    fn get(&mut self, num_threads: usize, numa: bool, max: usize) -> Pool {
+        let key = (num_threads, numa);
+        let entry = match self.0.iter().position(|(k, _)| *k == key) {
+            Some(index) => self.0.remove(index),
+            None => (key, Pool::new(num_threads, numa)),
+        };
+
+        let pool = entry.1.clone();
+        self.0.push(entry);
+        if self.0.len() > max {
+            self.0.remove(0);
+        }
+
+        pool
+    }
+}
+
+impl Pool {
+    /// Pool with workers named `file-hashing-worker-N`, `0` threads means [default_threads](crate::options::default_threads)
+    ///
+    /// With **numa** the workers are spread over NUMA nodes, see [HashOptions::numa](crate::HashOptions::numa)
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub(crate) fn new(num_threads: usize, numa: bool) -> Self {
+        let num_threads = match num_threads {
+            0 => crate::options::default_threads(),
+            num_threads => num_threads,
+        };
+        let mut builder = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("file-hashing-worker-{}", index));
+
+        let nodes = match numa {
+            true => crate::numa::get_nodes(),
+            false => Vec::new(),
+        };
+
+        if nodes.len() > 1 {
+            // A worker that cannot be pinned still hashes, just without locality
+            builder = builder.start_handler(move |index| {
+                let _ = crate::numa::pin_current_thread(
+                    &nodes[index % nodes.len()],
+                );
+            });
+        }
+
+        Self::from_rayon(std::sync::Arc::new(builder.build().unwrap()))
+    }
+
+    /// Pool of the caller, see [HashOptions::thread_pool](crate::HashOptions::thread_pool)
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub(crate) fn from_rayon(
+        pool: std::sync::Arc<rayon::ThreadPool>,
+    ) -> Self {
+        Self {
+            inline: false,
+            pool: Some(pool),
+        }
+    }
+
+    /// The same as [Pool::new], but the pool is built on first use and kept for later runs,
+    /// so repeated runs don't pay for spawning and joining threads.
+    /// Runs with the same number of threads share the pool, only the [MAX_SHARED_POOLS] most recently used
+    /// pools are kept and the threads of older ones exit once their runs are done
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub(crate) fn shared(num_threads: usize, numa: bool) -> Self {
+        use std::sync::Mutex;
+
+        static POOLS: Mutex<SharedPools> = Mutex::new(SharedPools::new());
+
+        POOLS
+            .lock()
+            .unwrap()
+            .get(num_threads, numa, MAX_SHARED_POOLS)
+    }
+
+    #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+    pub(crate) fn new(num_threads: usize, _numa: bool) -> Self {
+        let num_threads = match num_threads {
+            0 => crate::options::default_threads(),
+            num_threads => num_threads,
+        };
+        Self {
+            inline: false,
+            num_threads,
+        }
+    }
+
+    #[cfg(target_family = "wasm")]
+    pub(crate) fn new(_num_threads: usize, _numa: bool) -> Self {
+        Self { inline: false }
+    }
+
+    /// Pool without workers, see [HashOptions::single_thread](crate::HashOptions::single_thread)
+    pub(crate) fn inline() -> Self {
+        Self {
+            inline: true,
+            #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+            pool: None,
+            #[cfg(all(
+                not(feature = "parallel"),
+                not(target_family = "wasm")
+            ))]
+            num_threads: 1,
+        }
+    }
+
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub(crate) fn is_inline(&self) -> bool {
+        self.inline
+    }
+
+    #[cfg(not(all(feature = "parallel", not(target_family = "wasm"))))]
+    pub(crate) fn shared(num_threads: usize, numa: bool) -> Self {
+        Self::new(num_threads, numa)
+    }
+
+    /// Pool for functions without options: inside a rayon pool (e.g. in `pool.install` of the caller)
+    /// that pool is used, otherwise the same as [Pool::shared]
+    pub(crate) fn ambient(num_threads: usize) -> Self {
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        if rayon::current_thread_index().is_some() {
+            return Self {
+                inline: false,
+                pool: None,
+            };
+        }
+
+        Self::shared(num_threads, false)
+    }
+
+    /// Run **op** in a [rayon::Scope] of the pool
+    #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+    pub(crate) fn scope<'scope, R: Send>(
+        &self,
+        op: impl FnOnce(&rayon::Scope<'scope>) -> R + Send,
+    ) -> R {
+        match &self.pool {
+            Some(pool) => pool.scope(op),
+            None => rayon::scope(op),
+        }
+    }
+
+    /// Run **op** inside the pool
+    pub(crate) fn install<R: Send>(
+        &self,
+        op: impl FnOnce() -> R + Send,
+    ) -> R {
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+
+        #[cfg(not(all(feature = "parallel", not(target_family = "wasm"))))]
+        {
+            op()
+        }
+    }
+
+    /// Number of workers of the pool
+    pub(crate) fn num_threads(&self) -> usize {
+        if self.inline {
+            return 1;
+        }
+
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        match &self.pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        }
+
+        #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+        {
+            self.num_threads
+        }
+
+        #[cfg(target_family = "wasm")]
+        {
+            1
+        }
+    }
+
+    /// Run **op** on **workers** workers of the pool at the same time and wait for all of them,
+    /// e.g. for workers that take jobs from a queue
+    ///
+    /// Without threads (inline pools, WebAssembly) the workers run one after another on the calling thread
+    pub(crate) fn run_workers(&self, workers: usize, op: impl Fn() + Sync) {
+        if self.inline || workers <= 1 {
+            return (0..workers).for_each(|_| op());
+        }
+
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        {
+            let op = &op;
+            self.scope(|scope| {
+                for _ in 0..workers {
+                    scope.spawn(move |_| op());
+                }
+            })
+        }
+
+        #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+        {
+            run_each(vec![(); workers], |_| op());
+        }
+
+        #[cfg(target_family = "wasm")]
+        {
+            (0..workers).for_each(|_| op())
+        }
+    }
+
+    /// Apply **op** to every item in parallel, results are in the order of **items**
+    ///
+    /// Every worker gets its own copy of **init**, e.g. a digest to clone for each item
+    pub(crate) fn map_with<T, S, R>(
+        &self,
+        items: Vec<T>,
+        init: S,
+        op: impl Fn(&mut S, T) -> R + Sync + Send,
+    ) -> Vec<R>
+    where
+        T: Send,
+        S: Clone + Send,
+        R: Send,
+    {
+        if self.inline {
+            let mut init = init;
+            return items
+                .into_iter()
+                .map(|item| op(&mut init, item))
+                .collect();
+        }
+
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        {
+            use rayon::prelude::*;
+            self.install(|| {
+                items.into_par_iter().map_with(init, op).collect()
+            })
+        }
+
+        #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+        {
+            scoped_map(self.num_threads, items, init, op)
+        }
+
+        #[cfg(target_family = "wasm")]
+        {
+            let mut init = init;
+            items.into_iter().map(|item| op(&mut init, item)).collect()
+        }
+    }
+}
+
+/// Run **op** for every job on its own thread and wait for all of them
+///
+/// A single job runs on the calling thread
+pub(crate) fn run_each<T, R>(
+    jobs: Vec<T>,
+    op: impl Fn(T) -> R + Sync,
+) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+{
+    #[cfg(not(target_family = "wasm"))]
+    {
+        if jobs.len() <= 1 {
+            return jobs.into_iter().map(op).collect();
+        }
+
+        std::thread::scope(|scope| {
+            let op = &op;
+            let handles: Vec<_> = jobs
+                .into_iter()
+                .map(|job| scope.spawn(move || op(job)))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    #[cfg(target_family = "wasm")]
+    {
+        jobs.into_iter().map(op).collect()
+    }
+}
+
+/// Apply **op** to every item on up to **num_threads** scoped threads, results are in the order of **items**
+///
+/// Every thread gets its own copy of **init** and takes the next item from a shared queue,
+/// so slow items don't hold up the others
+#[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+fn scoped_map<T, S, R>(
+    num_threads: usize,
+    items: Vec<T>,
+    init: S,
+    op: impl Fn(&mut S, T) -> R + Sync,
+) -> Vec<R>
+where
+    T: Send,
+    S: Clone + Send,
+    R: Send,
+{
+    let workers = num_threads.min(items.len());
+    if workers <= 1 {
+        let mut init = init;
+        return items.into_iter().map(|item| op(&mut init, item)).collect();
+    }
+
+    let mut results: Vec<Option<R>> = Vec::new();
+    results.resize_with(items.len(), || None);
+    let queue = std::sync::Mutex::new(items.into_iter().enumerate());
+
+    std::thread::scope(|scope| {
+        let (queue, op) = (&queue, &op);
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let mut init = init.clone();
+                scope.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        match next {
+                            Some((index, item)) => {
+                                done.push((index, op(&mut init, item)))
+                            }
+                            None => break done,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            for (index, result) in handle.join().unwrap() {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pool;
+
+    #[test]
+    fn pool() {
+        let pool = Pool::new(2, true);
+        let name = pool.install(|| {
+            std::thread::current().name().map(|name| name.to_string())
+        });
+        #[cfg(feature = "parallel")]
+        assert!(name.unwrap().starts_with("file-hashing-worker-"));
+        #[cfg(not(feature = "parallel"))]
+        assert_eq!(name, std::thread::current().name().map(String::from));
+
+        assert_eq!(
+            pool.map_with(vec![1, 2, 3], (), |_, x| x * 2),
+            vec![2, 4, 6]
+        );
+        assert_eq!(
+            Pool::new(0, false).map_with(vec![1], (), |_, x| x),
+            vec![1]
+        );
+        assert_eq!(
+            pool.map_with(vec![1, 2, 3], 10, |init, x| *init + x),
+            vec![11, 12, 13]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn worker_names() {
+        let pool = Pool::new(3, false);
+        let mut names = pool.install(|| {
+            rayon::broadcast(|_| {
+                std::thread::current().name().map(String::from).unwrap()
+            })
+        });
+        names.sort();
+
+        assert_eq!(
+            names,
+            vec![
+                "file-hashing-worker-0",
+                "file-hashing-worker-1",
+                "file-hashing-worker-2",
+            ]
+        );
+    }
+
+    #[test]
+    fn shared() {
+        let a = Pool::shared(3, false);
+        let b = Pool::shared(3, false);
+        assert_eq!(a.map_with(vec![1, 2], (), |_, x| x + 1), vec![2, 3]);
+
+        #[cfg(feature = "parallel")]
+        {
+            assert_eq!(a, b);
+            assert_ne!(a, Pool::shared(2, false));
+
+            // Inside a pool of the caller only functions without options use it
+            let caller = rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .thread_name(|_| String::from("caller"))
+                .build()
+                .unwrap();
+            let name = |pool: fn() -> Pool| {
+                caller.install(|| {
+                    pool().install(|| {
+                        std::thread::current().name().map(String::from)
+                    })
+                })
+            };
+            assert_eq!(name(|| Pool::ambient(3)).as_deref(), Some("caller"));
+            assert!(name(|| Pool::shared(3, false))
+                .unwrap()
+                .starts_with("file-hashing-worker-"));
+        }
+        #[cfg(not(feature = "parallel"))]
+        assert_eq!(a, b);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn shared_evicted() {
+        let mut pools = super::SharedPools::new();
+        let first = pools.get(1, false, 2);
+        assert_eq!(first, pools.get(1, false, 2));
+
+        let second = pools.get(2, false, 2);
+        assert_eq!(first, pools.get(1, false, 2));
+        pools.get(3, false, 2);
+
+        // 2 was used least recently
+        assert_eq!(first, pools.get(1, false, 2));
+        assert_ne!(second, pools.get(2, false, 2));
+        assert_eq!(pools.0.len(), 2);
+    }
+
+    #[cfg(all(not(feature = "parallel"), not(target_family = "wasm")))]
+    #[test]
+    fn scoped_map() {
+        let threads = std::sync::Mutex::new(std::collections::HashSet::new());
+        let items: Vec<u64> = (0..64).collect();
+
+        let results = super::scoped_map(4, items.clone(), 1, |init, x| {
+            threads.lock().unwrap().insert(std::thread::current().id());
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            x + *init
+        });
+
+        assert_eq!(results, (1..65).collect::<Vec<_>>());
+        assert!(threads.into_inner().unwrap().len() > 1);
+        assert_eq!(
+            super::scoped_map(4, Vec::<u64>::new(), (), |_, x| x),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn inline() {
+        let pool = Pool::inline();
+        assert_ne!(pool, Pool::new(1, false));
+
+        let current = std::thread::current().id();
+        let threads = pool
+            .map_with(vec![1, 2, 3], (), |_, _| std::thread::current().id());
+        assert_eq!(threads, vec![current; 3]);
+    }
+
+    #[test]
+    fn run_workers() {
+        let pool = Pool::new(3, false);
+        assert_eq!(pool.num_threads(), 3);
+        assert_eq!(Pool::inline().num_threads(), 1);
+
+        let runs = std::sync::atomic::AtomicUsize::new(0);
+        let names = std::sync::Mutex::new(Vec::new());
+        pool.run_workers(3, || {
+            runs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            names
+                .lock()
+                .unwrap()
+                .push(std::thread::current().name().map(String::from));
+        });
+
+        assert_eq!(runs.into_inner(), 3);
+        #[cfg(feature = "parallel")]
+        assert!(names.into_inner().unwrap().iter().all(|name| name
+            .as_deref()
+            .unwrap()
+            .starts_with("file-hashing-worker-")));
+    }
+
+    #[test]
+    fn run_each() {
+        assert_eq!(super::run_each(vec![1, 2, 3], |x| x + 1), vec![2, 3, 4]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Known-answer self-tests
+//!
+//! Standard test vectors are run through the same chunked pipeline that hashes files,
+//! so a deployment can prove at startup that hashing works before it trusts any result
+
+use super::*;
+use std::io::Read;
+
+/// Digests of `""`, `"abc"`, the 448-bit NIST message and one million `a`
+const VECTORS: &[(&str, [&str; 4])] = &[
+    (
+        "MD5",
+        [
+            "d41d8cd98f00b204e9800998ecf8427e",
+            "900150983cd24fb0d6963f7d28e17f72",
+            "8215ef0796a20bcaaae116d3876c664a",
+            "7707d6ae4e027c70eea2a935c2296f21",
+        ],
+    ),
+    (
+        "SHA-1",
+        [
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f",
+        ],
+    ),
+    (
+        "SHA-256",
+        [
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+        ],
+    ),
+    (
+        "SHA-384",
+        [
+            "38b060a751ac96384cd9327eb1b1e36a21fdb71114be07434c0cc7bf63f6e1da274edebfe76f65fbd51ad2f14898b95b",
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+            "3391fdddfc8dc7393707a65b1b4709397cf8b1d162af05abfe8f450de5f36bc6b0455a8520bc4e6f5fe95b1fe3c8452b",
+            "9d0e1809716474cb086e834e310a4a1ced149e9c00f248527972cec5704c2a5b07b8b3dc38ecc4ebae97ddd87f3d8985",
+        ],
+    ),
+    (
+        "SHA-512",
+        [
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            "204a8fc6dda82f0a0ced7beb8e08a41657c16ef468b228a8279be331a703c33596fd15c13b1b07f9aa1d3bea57789ca031ad85c7a71dd70354ec631238ca3445",
+            "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973ebde0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b",
+        ],
+    ),
+    (
+        "SHA3-256",
+        [
+            "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+            "41c0dba2a9d6240849100376a8235e2c82e1b9998a999e21db32dd97496d3376",
+            "5c8875ae474a3634ba4fd55ec85bffd661f32aca75c6d699d0cdcb6c115891c1",
+        ],
+    ),
+    (
+        "SHA3-512",
+        [
+            "a69f73cca23a9ac5c8b567dc185a756e97c982164fe25859e0d1dcc1475c80a615b2123af1f5f94c11e3e9402c3ac558f500199d95b6d3e301758586281dcd26",
+            "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0",
+            "04a371e84ecfb5b8b77cb48610fca8182dd457ce6f326a0fd3d7ec2f1e91636dee691fbe0c985302ba1b0d8dc78c086346b533b49c030d99a27daf1139d6e75e",
+            "3c3a876da14034ab60627c077bb98f7e120a2a5370212dffb3385a18d4f38859ed311d0a9d5141ce9cc5c66ee689b266a8aa18ace8282a0e0db596c90b0a7b87",
+        ],
+    ),
+    (
+        "BLAKE2s-256",
+        [
+            "69217a3079908094e11121d042354a7c1f55b6482ca1a51e1b250dfd1ed0eef9",
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982",
+            "6f4df5116a6f332edab1d9e10ee87df6557beab6259d7663f3bcd5722c13f189",
+            "bec0c0e6cde5b67acb73b81f79a67a4079ae1c60dac9d2661af18e9f8b50dfa5",
+        ],
+    ),
+    (
+        "BLAKE2b-512",
+        [
+            "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce",
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            "7285ff3e8bd768d69be62b3bf18765a325917fa9744ac2f582a20850bc2b1141ed1b3e4528595acc90772bdf2d37dc8a47130b44f33a02e8730e5ad8e166e888",
+            "98fb3efb7206fd19ebf69b6f312cf7b64e3b94dbe1a17107913975a793f177e1d077609d7fba363cbba00d05f7aa4e4fa8715d6428104c0a75643b0ff3fd3eaf",
+        ],
+    ),
+    (
+        "BLAKE3",
+        [
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            "c19012cc2aaf0dc3d8e5c45a1b79114d2df42abb2a410bf54be09e891af06ff8",
+            "616f575a1b58d4c9797d4217b9730ae5e6eb319d76edef6549b46f4efe31ff8b",
+        ],
+    ),
+];
+
+const INPUTS: [&str; 4] = [
+    "\"\"",
+    "\"abc\"",
+    "the 448-bit message",
+    "one million \"a\"",
+];
+
+fn input(index: usize) -> Box<dyn Read> {
+    match index {
+        0 => Box::new(&b""[..]),
+        1 => Box::new(&b"abc"[..]),
+        2 => Box::new(
+            &b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"[..],
+        ),
+        _ => Box::new(std::io::repeat(b'a').take(1_000_000)),
+    }
+}
+
+/// Buffer sizes of the pipeline, odd ones split the input mid-block
+const BUFFER_SIZES: [usize; 3] = [3, 1000, PAGE_SIZE];
+
+/// Name of the algorithm of `hasher`, recognized by its digest of the empty input
+///
+/// Unlike the hashing functions, the algorithm itself is checked, so data already fed to `hasher` is ignored.
+/// Known are the algorithms of [verify_hasher], `None` for the rest
+pub fn identify<HashType>(hasher: &HashType) -> Option<&'static str>
+where
+    HashType: DynDigest + Clone,
+{
+    let mut hash = hasher.clone();
+    hash.reset();
+    let empty = crate::encoding::get_lowerhex(&mut hash);
+
+    VECTORS
+        .iter()
+        .find(|(_, expected)| expected[0] == empty)
+        .map(|(name, _)| *name)
+}
+
+/// Known name of the algorithm **name**, case and punctuation are ignored (`sha256` is `SHA-256`)
+pub(crate) fn canonical_name(name: &str) -> Option<&'static str> {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let name = normalize(name);
+
+    VECTORS
+        .iter()
+        .map(|(known, _)| *known)
+        .find(|known| normalize(known) == name)
+}
+
+/// Check that `hasher` gives the standard digests when it is fed through the chunked pipeline of the crate
+///
+/// The algorithm is recognized by its digest of the empty input, its name is returned.
+/// Supported: MD5, SHA-1, SHA-256, SHA-384, SHA-512, SHA3-256, SHA3-512, BLAKE2s-256, BLAKE2b-512 and BLAKE3
+///
+/// # Example
+///
+/// ```
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::selftest::verify_hasher;
+///
+/// let algorithm = verify_hasher(&Sha256::new()).expect("hashing is broken, refusing to start");
+/// assert_eq!(algorithm, "SHA-256");
+/// ```
+///
+/// # Error
+///
+/// * If the algorithm is not supported, the **IOErrorKind::Unsupported** error will be returned
+/// * If a digest is wrong, the **IOErrorKind::InvalidData** error will be returned
+pub fn verify_hasher<HashType>(
+    hasher: &HashType,
+) -> Result<&'static str, IOError>
+where
+    HashType: DynDigest + Clone,
+{
+    let name = identify(hasher).ok_or_else(|| {
+        IOError::new(IOErrorKind::Unsupported, "unknown algorithm")
+    })?;
+    let (_, expected) = VECTORS
+        .iter()
+        .find(|(vector, _)| *vector == name)
+        .expect("identified by the vectors");
+
+    for (index, expected) in expected.iter().enumerate() {
+        for size in BUFFER_SIZES {
+            let mut buf = crate::io::AlignedBuf::new(
+                size,
+                crate::io::DEFAULT_ALIGNMENT,
+            )?;
+            let mut hash = hasher.clone();
+            hash.reset();
+
+            crate::io::hash_tee_with(
+                input(index),
+                &mut hash,
+                std::io::sink(),
+                &mut buf,
+            )?;
+
+            if crate::encoding::get_lowerhex(&mut hash) != *expected {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidData,
+                    format!(
+                        "{}: wrong digest of {} with a buffer of {} bytes",
+                        name, INPUTS[index], size
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use blake2::{Blake2b512, Blake2s256, Digest};
+    use sha2::{Sha256, Sha512};
+
+    #[test]
+    fn verify_hasher() {
+        assert_eq!(super::verify_hasher(&Sha256::new()).unwrap(), "SHA-256");
+        assert_eq!(super::verify_hasher(&Sha512::new()).unwrap(), "SHA-512");
+        assert_eq!(
+            super::verify_hasher(&Blake2s256::new()).unwrap(),
+            "BLAKE2s-256"
+        );
+
+        // A hasher with state is reset first
+        let mut hash = Blake2b512::new();
+        hash.update(b"state");
+        assert_eq!(super::verify_hasher(&hash).unwrap(), "BLAKE2b-512");
+
+        let error = super::verify_hasher(&sha2::Sha224::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,358 @@
+//! Folder snapshots
+
+use super::*;
+use crate::verify::{DiffEntry, DiffReport, VerifyOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Hash and metadata of one file in a [Snapshot]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Hash from [get_hash_file](crate::get_hash_file)
+    pub hash: String,
+
+    /// Size in bytes
+    pub size: u64,
+
+    /// Last modification time, if the platform supports it
+    pub modified: Option<SystemTime>,
+}
+
+/// State of a **folder** at a point in time
+///
+/// All paths are relative to the folder
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{snapshot::Snapshot, HashOptions};
+///
+/// let hash = Blake2s256::new();
+///
+/// let before = Snapshot::create("/home/gladi/Pictures", &hash, &HashOptions::new(12), |_| {}).unwrap();
+/// // ...
+/// let after = Snapshot::create("/home/gladi/Pictures", &hash, &HashOptions::new(12), |_| {}).unwrap();
+///
+/// println!("{}", before.compare(&after));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// When the snapshot was created
+    pub created: SystemTime,
+
+    /// Files of the folder
+    pub files: BTreeMap<PathBuf, SnapshotEntry>,
+}
+
+impl Snapshot {
+    /// Create snapshot of **folder**
+    ///
+    /// Each file is hashed with a copy of `hash`
+    ///
+    /// # Error
+    ///
+    /// Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are not included
+    pub fn create<HashType, P>(
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<Snapshot, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let base = fs::resolve_base(dir, options)?;
+        let progress = options.broadcast(progress);
+        let created = SystemTime::now();
+        let entries = fs::walk(dir, options).report_errors(&progress);
+        let paths: Vec<_> = entries.iter().map(|entry| &entry.path).collect();
+        let results = file::get_hash_each_file(&paths, hash, options);
+        options.check_cancelled()?;
+
+        let mut files = BTreeMap::new();
+        let mut done_files = 0;
+
+        for (entry, result) in entries.iter().zip(results) {
+            done_files += 1;
+
+            match result {
+                Ok(hash) => {
+                    files.insert(
+                        fs::relative_path(&entry.path, &base, options),
+                        SnapshotEntry {
+                            hash,
+                            size: entry.size(),
+                            modified: entry.modified(),
+                        },
+                    );
+                    progress(ProgressInfo::Yield(done_files));
+                }
+                Err(error) => progress(ProgressInfo::Error(error)),
+            }
+        }
+
+        Ok(Snapshot { created, files })
+    }
+
+    /// Compare with a **newer** snapshot
+    ///
+    /// `self` is treated as the baseline
+    pub fn compare(&self, other: &Snapshot) -> DiffReport {
+        let mut report = DiffReport::default();
+
+        for (path, entry) in other.files.iter() {
+            match self.files.get(path) {
+                None => report.push_added(path.clone(), entry.hash.clone()),
+                Some(old) if old.hash != entry.hash => report.push_modified(
+                    path.clone(),
+                    old.hash.clone(),
+                    entry.hash.clone(),
+                ),
+                Some(_) => {}
+            }
+        }
+
+        for (path, entry) in self.files.iter() {
+            if !other.files.contains_key(path) {
+                report.push_removed(path.clone(), entry.hash.clone());
+            }
+        }
+
+        report
+    }
+
+    /// Verify **folder** against this snapshot
+    ///
+    /// Paths of the snapshot are resolved relative to `dir`.
+    /// With [VerifyOptions::size_check] files whose size has changed are not hashed at all,
+    /// with [VerifyOptions::quick_check] files whose size and modification time have not changed are not hashed either
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::{snapshot::Snapshot, HashOptions};
+    /// use file_hashing::verify::VerifyOptions;
+    ///
+    /// let hash = Blake2s256::new();
+    /// let snapshot = Snapshot::create("/home/gladi/Pictures", &hash, &HashOptions::new(12), |_| {}).unwrap();
+    ///
+    /// let report = snapshot
+    ///     .verify("/home/gladi/Pictures", &hash, &HashOptions::new(12), &VerifyOptions::default(), |_| {})
+    ///     .unwrap();
+    ///
+    /// assert!(report.is_empty());
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are skipped
+    pub fn verify<HashType, P>(
+        &self,
+        dir: P,
+        hash: &HashType,
+        options: &HashOptions,
+        verify_options: &VerifyOptions,
+        progress: impl Fn(ProgressInfo),
+    ) -> Result<DiffReport, IOError>
+    where
+        HashType: DynDigest + Clone + std::marker::Send,
+        P: AsRef<Path>,
+    {
+        let dir = fs::resolve_root(dir.as_ref(), options)?;
+        let dir = dir.as_path();
+        let base = fs::resolve_base(dir, options)?;
+        let progress = options.broadcast(progress);
+        let mut report = DiffReport::default();
+        let mut seen = HashSet::new();
+        let mut to_hash = Vec::new();
+
+        for file_entry in fs::walk(dir, options).report_errors(&progress) {
+            let path = file_entry.path;
+            let relative = fs::relative_path(&path, &base, options);
+
+            if let Some(entry) = self.files.get(&relative) {
+                if verify_options.quick_check
+                    && file_entry.metadata.len() == entry.size
+                    && entry.modified.is_some()
+                    && file_entry.metadata.modified().ok() == entry.modified
+                {
+                    seen.insert(relative);
+                    continue;
+                }
+
+                if verify_options.size_check
+                    && file_entry.metadata.len() != entry.size
+                {
+                    report.modified.push(DiffEntry {
+                        path: relative.clone(),
+                        old: Some(entry.hash.clone()),
+                        new: None,
+                    });
+                    seen.insert(relative);
+                    continue;
+                }
+            }
+
+            seen.insert(relative.clone());
+            to_hash.push((path, relative));
+        }
+
+        let paths: Vec<_> = to_hash.iter().map(|(path, _)| path).collect();
+        let results = file::get_hash_each_file(&paths, hash, options);
+        options.check_cancelled()?;
+        let mut done_files = 0;
+
+        for ((_, relative), result) in to_hash.into_iter().zip(results) {
+            done_files += 1;
+
+            let new = match result {
+                Ok(new) => new,
+                Err(error) => {
+                    progress(ProgressInfo::Error(error));
+                    continue;
+                }
+            };
+
+            match self.files.get(&relative) {
+                None => report.push_added(relative, new),
+                Some(old) if old.hash != new => {
+                    report.push_modified(relative, old.hash.clone(), new)
+                }
+                Some(_) => {}
+            }
+
+            progress(ProgressInfo::Yield(done_files));
+        }
+
+        for (path, entry) in self.files.iter() {
+            if !seen.contains(path) {
+                report.push_removed(path.clone(), entry.hash.clone());
+            }
+        }
+
+        report.sort();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::fs::extra;
+    use crate::verify::VerifyOptions;
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+    use std::path::PathBuf;
+
+    #[test]
+    fn create_and_compare() {
+        let hash = Blake2s256::new();
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let before =
+            Snapshot::create(&temp_dir, &hash, &HashOptions::new(4), |_| {})
+                .unwrap();
+        assert_eq!(before.files.len(), 10);
+        assert!(before.compare(&before).is_empty());
+
+        std::fs::write(paths[0].path(), "changed").unwrap();
+        let after =
+            Snapshot::create(&temp_dir, &hash, &HashOptions::new(4), |_| {})
+                .unwrap();
+
+        let report = before.compare(&after);
+        assert!(report.added.is_empty() && report.removed.is_empty());
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(
+            report.modified[0].path,
+            PathBuf::from("random_file_0.txt")
+        );
+    }
+
+    #[test]
+    fn verify_with_size_check() {
+        let hash = Blake2s256::new();
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let snapshot =
+            Snapshot::create(&temp_dir, &hash, &HashOptions::new(4), |_| {})
+                .unwrap();
+
+        std::fs::write(paths[0].path(), "changed").unwrap();
+        std::fs::remove_file(paths[1].path()).unwrap();
+
+        let report = snapshot
+            .verify(
+                &temp_dir,
+                &hash,
+                &HashOptions::new(4),
+                &VerifyOptions::default(),
+                |_| {},
+            )
+            .unwrap();
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].new, None); // size differs, not hashed
+
+        let options = VerifyOptions::full();
+        let report = snapshot
+            .verify(&temp_dir, &hash, &HashOptions::new(4), &options, |_| {})
+            .unwrap();
+        assert_eq!(report.modified.len(), 1);
+        assert!(report.modified[0].new.is_some());
+    }
+
+    #[test]
+    fn verify_with_quick_check() {
+        let hash = Blake2s256::new();
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(10, 32);
+
+        let snapshot =
+            Snapshot::create(&temp_dir, &hash, &HashOptions::new(4), |_| {})
+                .unwrap();
+
+        // Same size and modification time, only a full check sees it
+        let file = std::fs::File::options()
+            .write(true)
+            .open(paths[0].path())
+            .unwrap();
+        let modified = file.metadata().unwrap().modified().unwrap();
+        std::io::Write::write_all(&mut &file, &[0; 32]).unwrap();
+        file.set_modified(modified).unwrap();
+
+        let hashed = std::cell::Cell::new(0);
+        let report = snapshot
+            .verify(
+                &temp_dir,
+                &hash,
+                &HashOptions::new(4),
+                &VerifyOptions::quick(),
+                |_| hashed.set(hashed.get() + 1),
+            )
+            .unwrap();
+        assert!(report.is_empty());
+        assert_eq!(hashed.get(), 0);
+
+        let report = snapshot
+            .verify(
+                &temp_dir,
+                &hash,
+                &HashOptions::new(4),
+                &VerifyOptions::full(),
+                |_| {},
+            )
+            .unwrap();
+        assert_eq!(report.modified.len(), 1);
+    }
+}
@@ -0,0 +1,74 @@
+//! Statistics of hashing runs
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Statistics of a hashing run, returned alongside the hash
+///
+/// # Example
+///
+/// ```no_run
+/// use sha2::{Digest, Sha256};
+/// use file_hashing::{folder::get_hash_folder_with_stats, HashOptions};
+///
+/// let (result, stats) =
+///     get_hash_folder_with_stats("/home/gladi/Pictures", Sha256::new, &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// println!(
+///     "{}: {} files, {} bytes in {:?} ({:.0} B/s)",
+///     result,
+///     stats.files,
+///     stats.bytes,
+///     stats.elapsed,
+///     stats.throughput()
+/// );
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Stats {
+    /// Files that were hashed
+    pub files: u64,
+
+    /// Bytes read from the hashed files
+    pub bytes: u64,
+
+    /// Entries that were left out without an error, e.g. symbolic links
+    pub skipped: u64,
+
+    /// Files and entries that could not be read
+    pub errors: u64,
+
+    /// Wall time of the whole run, including the walk
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// Average bytes per second, `0.0` if no time has passed
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use std::time::Duration;
+
+    #[test]
+    fn throughput() {
+        let mut stats = Stats {
+            bytes: 3000,
+            elapsed: Duration::from_millis(1500),
+            ..Stats::default()
+        };
+        assert_eq!(stats.throughput(), 2000.0);
+
+        stats.elapsed = Duration::ZERO;
+        assert_eq!(stats.throughput(), 0.0);
+    }
+}
@@ -0,0 +1,491 @@
+//! Verification functions
+
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Difference between the **baseline** and the current state of a folder
+///
+/// All paths are relative to the verified folder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriftEvent {
+    /// The file exists in both, but its hash has changed
+    HashChanged {
+        path: PathBuf,
+        old: String,
+        new: String,
+    },
+
+    /// The file is in the baseline, but not in the folder
+    FileMissing(PathBuf),
+
+    /// The file is in the folder, but not in the baseline
+    NewFile(PathBuf),
+}
+
+/// Settings for verification against a [Snapshot](crate::snapshot::Snapshot)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// Compare file sizes before hashing
+    ///
+    /// A file with a different size is reported as modified **without reading it**,
+    /// its new hash will be `None`
+    pub size_check: bool,
+
+    /// Trust files whose size and modification time match the snapshot, like `rsync` does
+    ///
+    /// Only the other files are hashed. Fast, but misses changes that kept both (e.g. bit rot)
+    pub quick_check: bool,
+}
+
+impl VerifyOptions {
+    /// Hash only files whose size or modification time has changed
+    pub fn quick() -> Self {
+        Self {
+            size_check: true,
+            quick_check: true,
+        }
+    }
+
+    /// Hash every file, whatever its metadata says
+    pub fn full() -> Self {
+        Self {
+            size_check: false,
+            quick_check: false,
+        }
+    }
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            size_check: true,
+            quick_check: false,
+        }
+    }
+}
+
+/// One file of a [DiffReport]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffEntry {
+    pub path: PathBuf,
+
+    /// Hash in the baseline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+
+    /// Current hash
+    ///
+    /// May be `None` for a modified file if it was not hashed, see [VerifyOptions::size_check]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+/// File whose current hash does not match the expected one, see [DiffReport::mismatches]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mismatch {
+    pub path: PathBuf,
+
+    /// Hash in the baseline, `None` for a new file
+    pub expected: Option<String>,
+
+    /// Current hash, `None` for a missing file or a modified file that was not hashed
+    pub actual: Option<String>,
+}
+
+/// Result of comparing a folder or a [Snapshot](crate::snapshot::Snapshot) with a baseline
+///
+/// # Example
+///
+/// ```no_run
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::{snapshot::Snapshot, HashOptions};
+///
+/// let hash = Blake2s256::new();
+/// let before = Snapshot::create("/home/gladi/Pictures", &hash, &HashOptions::new(12), |_| {}).unwrap();
+/// let after = Snapshot::create("/home/gladi/Pictures", &hash, &HashOptions::new(12), |_| {}).unwrap();
+///
+/// let report = before.compare(&after);
+/// println!("{}", report); // for humans
+/// println!("{}", report.to_json().unwrap()); // for tools
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffReport {
+    /// Files that are not in the baseline
+    pub added: Vec<DiffEntry>,
+
+    /// Files that are only in the baseline
+    pub removed: Vec<DiffEntry>,
+
+    /// Files with a different hash
+    pub modified: Vec<DiffEntry>,
+}
+
+impl DiffReport {
+    /// No differences were found
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+
+    /// Serialize to pretty **JSON**
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parse from **JSON**
+    pub fn from_json(json: &str) -> serde_json::Result<DiffReport> {
+        serde_json::from_str(json)
+    }
+
+    /// Every added, removed and modified file as one list sorted by path
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use blake2::{Blake2s256, Digest};
+    /// use file_hashing::{manifest::Manifest, HashOptions};
+    ///
+    /// let manifest = Manifest::load("/home/gladi/B2SUMS").unwrap();
+    /// let report = manifest
+    ///     .verify("/home/gladi/Pictures", &Blake2s256::new(), &HashOptions::new(12), |_| {})
+    ///     .unwrap();
+    ///
+    /// for mismatch in report.mismatches() {
+    ///     println!(
+    ///         "{}: expected {:?}, got {:?}",
+    ///         mismatch.path.display(),
+    ///         mismatch.expected,
+    ///         mismatch.actual
+    ///     );
+    /// }
+    /// ```
+    pub fn mismatches(&self) -> Vec<Mismatch> {
+        let mut mismatches: Vec<Mismatch> = self
+            .added
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.modified.iter())
+            .map(|entry| Mismatch {
+                path: entry.path.clone(),
+                expected: entry.old.clone(),
+                actual: entry.new.clone(),
+            })
+            .collect();
+
+        mismatches.sort_by(|a, b| a.path.cmp(&b.path));
+        mismatches
+    }
+
+    /// Convert back to [DriftEvent]
+    pub fn events(&self) -> Vec<DriftEvent> {
+        let mut events = Vec::with_capacity(
+            self.added.len() + self.removed.len() + self.modified.len(),
+        );
+
+        for entry in self.modified.iter() {
+            events.push(DriftEvent::HashChanged {
+                path: entry.path.clone(),
+                old: entry.old.clone().unwrap_or_default(),
+                new: entry.new.clone().unwrap_or_default(),
+            });
+        }
+
+        events.extend(
+            self.removed
+                .iter()
+                .map(|entry| DriftEvent::FileMissing(entry.path.clone())),
+        );
+        events.extend(
+            self.added
+                .iter()
+                .map(|entry| DriftEvent::NewFile(entry.path.clone())),
+        );
+
+        events
+    }
+
+    pub(crate) fn push_added(&mut self, path: PathBuf, new: String) {
+        self.added.push(DiffEntry {
+            path,
+            old: None,
+            new: Some(new),
+        });
+    }
+
+    pub(crate) fn push_removed(&mut self, path: PathBuf, old: String) {
+        self.removed.push(DiffEntry {
+            path,
+            old: Some(old),
+            new: None,
+        });
+    }
+
+    pub(crate) fn push_modified(
+        &mut self,
+        path: PathBuf,
+        old: String,
+        new: String,
+    ) {
+        self.modified.push(DiffEntry {
+            path,
+            old: Some(old),
+            new: Some(new),
+        });
+    }
+
+    pub(crate) fn sort(&mut self) {
+        self.added.sort_by(|a, b| a.path.cmp(&b.path));
+        self.removed.sort_by(|a, b| a.path.cmp(&b.path));
+        self.modified.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in self.added.iter() {
+            writeln!(
+                f,
+                "+ {} {}",
+                entry.path.display(),
+                entry.new.as_deref().unwrap_or_default()
+            )?;
+        }
+
+        for entry in self.removed.iter() {
+            writeln!(
+                f,
+                "- {} {}",
+                entry.path.display(),
+                entry.old.as_deref().unwrap_or_default()
+            )?;
+        }
+
+        for entry in self.modified.iter() {
+            writeln!(
+                f,
+                "~ {} {} -> {}",
+                entry.path.display(),
+                entry.old.as_deref().unwrap_or_default(),
+                entry.new.as_deref().unwrap_or_default()
+            )?;
+        }
+
+        write!(
+            f,
+            "added: {}, removed: {}, modified: {}",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        )
+    }
+}
+
+/// Compare **folder** with a baseline of file hashes
+///
+/// The baseline maps paths relative to `dir` to hashes from [get_hash_file](crate::get_hash_file).
+/// Each file is hashed with a copy of `hash`
+///
+/// # Example
+///
+/// ```no_run
+/// use std::collections::HashMap;
+/// use std::path::PathBuf;
+/// use blake2::{Blake2s256, Digest};
+/// use file_hashing::verify::{verify_folder, DriftEvent};
+/// use file_hashing::HashOptions;
+///
+/// let mut baseline = HashMap::new();
+/// baseline.insert(PathBuf::from("photo.png"), String::from("8c5a..."));
+///
+/// let hash = Blake2s256::new();
+/// let report =
+///     verify_folder("/home/gladi/Pictures", &baseline, &hash, &HashOptions::new(12), |_| {})
+///         .unwrap();
+///
+/// for event in report.events() {
+///     match event {
+///         DriftEvent::HashChanged { path, old, new } => {
+///             println!("changed {:?}: {} -> {}", path, old, new)
+///         }
+///         DriftEvent::FileMissing(path) => println!("missing {:?}", path),
+///         DriftEvent::NewFile(path) => println!("new {:?}", path),
+///     }
+/// }
+/// ```
+///
+/// # Error
+///
+/// Files that cannot be read are reported through **progress** as [ProgressInfo::Error] and are skipped
+pub fn verify_folder<HashType, P>(
+    dir: P,
+    baseline: &HashMap<PathBuf, String>,
+    hash: &HashType,
+    options: &HashOptions,
+    progress: impl Fn(ProgressInfo),
+) -> Result<DiffReport, IOError>
+where
+    HashType: DynDigest + Clone + std::marker::Send,
+    P: AsRef<Path>,
+{
+    let dir = fs::resolve_root(dir.as_ref(), options)?;
+    let dir = dir.as_path();
+    let base = fs::resolve_base(dir, options)?;
+    let progress = options.broadcast(progress);
+    let paths: Vec<PathBuf> = fs::walk(dir, options)
+        .report_errors(&progress)
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    let results = file::get_hash_each_file(&paths, hash, options);
+    options.check_cancelled()?;
+
+    let mut report = DiffReport::default();
+    let mut seen = HashSet::with_capacity(paths.len());
+    let mut done_files = 0;
+
+    for (path, result) in paths.iter().zip(results) {
+        done_files += 1;
+
+        let new = match result {
+            Ok(new) => new,
+            Err(error) => {
+                progress(ProgressInfo::Error(error));
+                continue;
+            }
+        };
+
+        let relative = fs::relative_path(path, &base, options);
+
+        match baseline.get(&relative) {
+            None => report.push_added(relative.clone(), new),
+            Some(old) if *old != new => {
+                report.push_modified(relative.clone(), old.clone(), new)
+            }
+            Some(_) => {}
+        }
+
+        seen.insert(relative);
+        progress(ProgressInfo::Yield(done_files));
+    }
+
+    for (path, old) in baseline.iter() {
+        if !seen.contains(path) {
+            report.push_removed(path.clone(), old.clone());
+        }
+    }
+
+    report.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffReport, DriftEvent, Mismatch};
+    use crate::fs::extra;
+    use crate::HashOptions;
+    use blake2::{Blake2s256, Digest};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn verify_folder() {
+        let hash = Blake2s256::new();
+        let (temp_dir, paths) =
+            extra::generate_random_folder_with_files(3, 32);
+
+        let mut baseline: HashMap<PathBuf, String> = HashMap::new();
+        for path in paths.iter() {
+            let hash = Blake2s256::new();
+            let relative = path.strip_prefix(temp_dir.path()).unwrap();
+            baseline.insert(
+                relative.to_path_buf(),
+                crate::get_hash_file(path, &hash).unwrap(),
+            );
+        }
+
+        let report = super::verify_folder(
+            temp_dir.path(),
+            &baseline,
+            &hash,
+            &HashOptions::new(4),
+            |_| {},
+        )
+        .unwrap();
+        assert!(report.is_empty());
+
+        std::fs::write(paths[0].path(), "changed").unwrap();
+        std::fs::remove_file(paths[1].path()).unwrap();
+        std::fs::write(temp_dir.path().join("new.txt"), "new").unwrap();
+
+        let report = super::verify_folder(
+            temp_dir.path(),
+            &baseline,
+            &hash,
+            &HashOptions::new(4),
+            |_| {},
+        )
+        .unwrap();
+        let events = report.events();
+
+        assert_eq!(events.len(), 3);
+        assert!(
+            events.contains(&DriftEvent::NewFile(PathBuf::from("new.txt")))
+        );
+        assert!(events.contains(&DriftEvent::FileMissing(PathBuf::from(
+            "random_file_1.txt"
+        ))));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            DriftEvent::HashChanged { path, .. } if path == &PathBuf::from("random_file_0.txt")
+        )));
+    }
+
+    #[test]
+    fn diff_report_json() {
+        let mut report = DiffReport::default();
+        report.push_added(PathBuf::from("a.txt"), String::from("aa"));
+        report.push_removed(PathBuf::from("b.txt"), String::from("bb"));
+        report.push_modified(
+            PathBuf::from("c.txt"),
+            String::from("cc"),
+            String::from("dd"),
+        );
+
+        let json = report.to_json().unwrap();
+        assert_eq!(DiffReport::from_json(&json).unwrap(), report);
+
+        println!("{}", report);
+        assert_eq!(report.events().len(), 3);
+    }
+
+    #[test]
+    fn mismatches() {
+        let mut report = DiffReport::default();
+        report.push_modified(
+            PathBuf::from("c.txt"),
+            String::from("cc"),
+            String::from("dd"),
+        );
+        report.push_removed(PathBuf::from("b.txt"), String::from("bb"));
+        report.push_added(PathBuf::from("a.txt"), String::from("aa"));
+
+        let mismatches = report.mismatches();
+        let paths: Vec<_> = mismatches.iter().map(|m| &m.path).collect();
+        assert_eq!(paths, ["a.txt", "b.txt", "c.txt"]);
+
+        assert_eq!(
+            mismatches[2],
+            Mismatch {
+                path: PathBuf::from("c.txt"),
+                expected: Some(String::from("cc")),
+                actual: Some(String::from("dd")),
+            }
+        );
+        assert_eq!(mismatches[0].expected, None);
+        assert_eq!(mismatches[1].actual, None);
+    }
+}